@@ -1,7 +1,10 @@
 //! Contains the implementation to extract the database of all aircrafts available in ADS-B exchange
 //! The database contains "current" status.
 use std::error::Error;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_recursion::async_recursion;
 use futures::{StreamExt, TryStreamExt};
@@ -14,11 +17,35 @@ use crate::country::CountryIcaoRanges;
 use crate::csv;
 use crate::fs::BlobStorageProvider;
 
-static DATABASE: &'static str = "aircraft/db/";
+static DATABASE: &str = "aircraft/db/";
+
+/// The minimum number of aircrafts of a plausible snapshot; snapshots have more than 400.000
+pub static MIN_AIRCRAFTS: usize = 100_000;
 
 /// [`HashMap`] between tail number (e.g. "OY-TWM") and an [`Aircraft`]
 pub type Aircrafts = HashMap<Arc<str>, Aircraft>;
 
+/// Returns the icao numbers (lowercased) of `data`, one per line (e.g. `459CD3`), ignoring empty lines
+/// and comments (starting with `#`), e.g. a curated list of aircraft (see [`crate::PrivateJetFilter::icao_numbers`])
+/// # Error
+/// Errors when a line is not an icao number, i.e. 6 hexadecimal digits
+pub fn parse_icao_numbers(data: &str) -> Result<HashSet<Arc<str>>, String> {
+    data.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, icao_number)| {
+            if icao_number.len() == 6 && icao_number.chars().all(|c| c.is_ascii_hexdigit()) {
+                Ok(icao_number.to_ascii_lowercase().into())
+            } else {
+                Err(format!(
+                    "line {line} (\"{icao_number}\") is not an icao number (6 hexadecimal digits)"
+                ))
+            }
+        })
+        .collect()
+}
+
 /// An in-memory representation of an aircraft data
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct Aircraft {
@@ -32,6 +59,23 @@ pub struct Aircraft {
     pub model: String,
     /// The country in ISO 3166 of the aircraft
     pub country: Option<Arc<str>>,
+    /// Whether the aircraft is flagged as military (or otherwise state-operated) by adsbexchange.
+    /// Snapshots prior to this column default to `false`.
+    #[serde(default)]
+    pub military: bool,
+    /// The registered owner or operator of the aircraft, when known by adsbexchange.
+    /// Snapshots prior to this column default to `None`.
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+impl Aircraft {
+    /// Returns whether the tail number of the aircraft starts with any of `prefixes` (e.g. `OY-`)
+    pub fn has_tail_prefix(&self, prefixes: &[impl AsRef<str>]) -> bool {
+        prefixes
+            .iter()
+            .any(|prefix| self.tail_number.starts_with(prefix.as_ref()))
+    }
 }
 
 fn pk_to_blob_name(date: &time::Date) -> String {
@@ -78,14 +122,10 @@ async fn children<'a: 'async_recursion>(
         return Ok(Default::default());
     };
 
-    let mut entries = futures::future::try_join_all(
-        entries
-            .into_iter()
-            .map(|x| x.unwrap())
-            .map(|x| db_current(x)),
-    )
-    .await
-    .map_err(|e| e.to_string())?;
+    let mut entries =
+        futures::future::try_join_all(entries.into_iter().map(|x| x.unwrap()).map(db_current))
+            .await
+            .map_err(|e| e.to_string())?;
 
     // recurse over all children
     let mut _children =
@@ -96,14 +136,20 @@ async fn children<'a: 'async_recursion>(
     Ok(entries)
 }
 
+/// Returns the first hex digit of every icao number (`A`-`F` and `0`-`9`), the roots of
+/// adsbexchange's database of aircrafts
+fn prefixes() -> impl Iterator<Item = String> {
+    (b'A'..=b'F')
+        .chain(b'0'..=b'9')
+        .map(|x| std::str::from_utf8(&[x]).unwrap().to_string())
+}
+
 /// Returns [`Aircrafts`] known in [ADS-B exchange](https://globe.adsbexchange.com) as of now.
 /// It returns ~0.5m aircrafts
 /// # Implementation
 /// This function is not pure: the result depends on adsbexchange.com's current state.
 async fn extract_aircrafts() -> Result<Vec<Aircraft>, Box<dyn Error>> {
-    let prefixes = (b'A'..=b'F').chain(b'0'..b'9');
-    let prefixes = prefixes.map(|x| std::str::from_utf8(&[x]).unwrap().to_string());
-    extract_aircrafts_prefix(prefixes).await
+    extract_aircrafts_prefix(prefixes()).await
 }
 
 async fn extract_aircrafts_prefix(
@@ -111,7 +157,7 @@ async fn extract_aircrafts_prefix(
 ) -> Result<Vec<Aircraft>, Box<dyn Error>> {
     let country_ranges = CountryIcaoRanges::new();
 
-    let mut entries = futures::future::try_join_all(prefixes.map(|x| db_current(x))).await?;
+    let mut entries = futures::future::try_join_all(prefixes.map(db_current)).await?;
 
     let mut _children =
         futures::future::try_join_all(entries.iter_mut().map(|entry| children(&mut entry.1)))
@@ -128,7 +174,11 @@ async fn extract_aircrafts_prefix(
                 .filter_map(|(icao_number, mut data)| {
                     let tail_number = std::mem::take(&mut data[0])?;
                     let type_designator = std::mem::take(&mut data[1])?;
+                    // flags of adsbexchange, where the first is whether it is military
+                    let military = data[2].as_deref().is_some_and(|f| f.starts_with('1'));
                     let model = std::mem::take(&mut data[3])?;
+                    // the owner or operator, when adsbexchange has one
+                    let owner = data.get_mut(4).and_then(std::mem::take);
                     let country = country_ranges
                         .country(&icao_number)
                         .expect("Data from adsb-b to be a valid hex");
@@ -139,6 +189,8 @@ async fn extract_aircrafts_prefix(
                         type_designator,
                         model,
                         country: country.cloned(),
+                        military,
+                        owner,
                     })
                 });
             acc.extend(items);
@@ -146,7 +198,7 @@ async fn extract_aircrafts_prefix(
         }))
 }
 
-async fn load(
+pub(crate) async fn load(
     aircraft: Vec<Aircraft>,
     date: &Date,
     client: &dyn BlobStorageProvider,
@@ -163,32 +215,227 @@ pub async fn etl_aircrafts(client: &dyn BlobStorageProvider) -> Result<(), Box<d
     load(aircraft, &now, client).await
 }
 
+/// Validates the snapshot of `date` with `count` aircrafts, logging a warning if it has fewer than [`MIN_AIRCRAFTS`].
+/// # Error
+/// Errors if it is empty (e.g. an empty blob), since any result computed from it would be meaningless
+fn validate(date: Date, count: usize) -> Result<(), std::io::Error> {
+    if count == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("The snapshot of aircrafts of {date} is empty"),
+        ));
+    }
+    if count < MIN_AIRCRAFTS {
+        log::warn!(
+            "The snapshot of aircrafts of {date} has only {count} aircrafts (expected at least {MIN_AIRCRAFTS}); it is likely incomplete"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the snapshot of [`Aircrafts`] of `date`.
+/// # Error
+/// Errors if the snapshot does not exist or is empty
 pub async fn read(
     date: Date,
     client: &dyn BlobStorageProvider,
 ) -> Result<Aircrafts, std::io::Error> {
+    read_filtered(date, client, &|_| true).await
+}
+
+/// Returns the [`Aircrafts`] of the snapshot of `date` for which `predicate` is true, e.g. those of a country.
+/// Like [`read`] followed by filtering, but without holding the aircrafts that do not pass `predicate`.
+/// # Implementation
+/// The snapshot is validated (see [`read`]) on all its aircrafts, regardless of `predicate`.
+/// # Error
+/// Errors if the snapshot does not exist or is empty
+pub async fn read_filtered(
+    date: Date,
+    client: &dyn BlobStorageProvider,
+    predicate: &(dyn Fn(&Aircraft) -> bool + Sync),
+) -> Result<Aircrafts, std::io::Error> {
+    let data = get(date, client).await?;
+    parse_filtered(date, &data, predicate)
+}
+
+/// Returns the blob of the snapshot of `date`
+/// # Error
+/// Errors if the snapshot does not exist
+async fn get(date: Date, client: &dyn BlobStorageProvider) -> Result<Vec<u8>, std::io::Error> {
     let key = pk_to_blob_name(&date);
-    let aircrafts = crate::io::get_csv::<Aircraft>(&key, client).await?;
+    client.maybe_get(&key).await?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("The snapshot of aircrafts of {date} does not exist"),
+        )
+    })
+}
+
+/// Returns the [`Aircrafts`] of `data`, the snapshot of `date`, for which `predicate` is true (see [`read_filtered`])
+fn parse_filtered(
+    date: Date,
+    data: &[u8],
+    predicate: &(dyn Fn(&Aircraft) -> bool + Sync),
+) -> Result<Aircrafts, std::io::Error> {
+    let mut count = 0;
+    let mut aircrafts = Aircrafts::new();
+    for aircraft in csv::deserialize::<Aircraft>(data) {
+        let aircraft = aircraft?;
+        count += 1;
+        if predicate(&aircraft) {
+            aircrafts.insert(aircraft.icao_number.clone(), aircraft);
+        }
+    }
+    validate(date, count)?;
+    Ok(aircrafts)
+}
 
-    Ok(aircrafts
+/// Returns the most recent snapshot of [`Aircrafts`] and its date.
+/// # Error
+/// Errors if no snapshot exists in `client`
+pub async fn read_latest(
+    client: &dyn BlobStorageProvider,
+) -> Result<(Date, Aircrafts), std::io::Error> {
+    let date = client
+        .list(DATABASE)
+        .await?
         .into_iter()
-        .map(|x: Aircraft| (x.icao_number.clone(), x))
-        .collect())
+        .map(|key| blob_name_to_pk(&key))
+        .max()
+        .ok_or_else(|| std::io::Error::other("No snapshot of aircrafts exists"))?;
+
+    Ok((date, read(date, client).await?))
 }
 
+/// Returns all snapshots of [`Aircrafts`] by date.
+/// # Error
+/// Errors if no snapshot exists in `client` or any is empty
 pub async fn read_all(
     client: &dyn BlobStorageProvider,
 ) -> Result<HashMap<Date, Aircrafts>, std::io::Error> {
+    read_all_filtered(client, &|_| true).await
+}
+
+/// Returns all snapshots of [`Aircrafts`] by date, each with only the aircrafts for which `predicate` is true
+/// (see [`read_filtered`]).
+/// # Error
+/// Errors if no snapshot exists in `client` or any is empty
+pub async fn read_all_filtered(
+    client: &dyn BlobStorageProvider,
+    predicate: &(dyn Fn(&Aircraft) -> bool + Sync),
+) -> Result<HashMap<Date, Aircrafts>, std::io::Error> {
+    let snapshots = list(client).await?;
+
+    let tasks = snapshots.into_iter().map(|date| async move {
+        read_filtered(date, client, predicate)
+            .await
+            .map(|x| (date, x))
+    });
+
+    futures::stream::iter(tasks)
+        .buffered(100)
+        .try_collect::<HashMap<_, _>>()
+        .await
+}
+
+/// Returns the dates of all snapshots in `client`
+/// # Error
+/// Errors if no snapshot exists in `client`
+async fn list(client: &dyn BlobStorageProvider) -> Result<Vec<Date>, std::io::Error> {
     let snapshots = client
         .list(DATABASE)
         .await?
         .into_iter()
         .map(|key| blob_name_to_pk(&key))
         .collect::<Vec<_>>();
+    if snapshots.is_empty() {
+        return Err(std::io::Error::other(format!(
+            "No snapshot of aircrafts exists in \"{DATABASE}\""
+        )));
+    }
+    Ok(snapshots)
+}
 
-    let tasks = snapshots
-        .into_iter()
-        .map(|date| async move { read(date, client).await.map(|x| (date, x)) });
+/// The blob of the cache of [`read_all_cached`] with the snapshots listed and when they were listed
+static LISTING: &str = "aircraft/listing.json";
+
+/// The dates of the snapshots of aircrafts, as listed at a given time
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+struct Listing {
+    #[serde(with = "time::serde::rfc3339")]
+    listed_at: time::OffsetDateTime,
+    snapshots: Vec<Date>,
+}
+
+/// The default time during which the listed snapshots are read from the cache of [`read_all_cached`]
+pub static DEFAULT_REGISTRY_TTL: time::Duration = time::Duration::days(7);
+
+/// The options of the cache of the snapshots of aircrafts of [`read_all_cached`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegistryCache {
+    /// The time during which the listed snapshots are read from the cache, before listing them again
+    pub ttl: time::Duration,
+    /// Whether to list and read all snapshots again, regardless of `ttl`
+    pub refresh: bool,
+}
+
+impl Default for RegistryCache {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_REGISTRY_TTL,
+            refresh: false,
+        }
+    }
+}
+
+/// Like [`read_all_filtered`], caching the snapshots of `client` in `cache` (e.g. [`crate::fs::LocalDisk`]),
+/// so that repeated runs do not read them from `client`.
+/// # Implementation
+/// A snapshot never changes once written, so it is cached like the positions of past months (see [`crate::fs::cached`]).
+/// New snapshots are written over time, so the list of snapshots is only read from the cache during `options.ttl` of `now`.
+/// # Error
+/// Errors if no snapshot exists in `client` or any is empty
+pub async fn read_all_cached(
+    client: &dyn BlobStorageProvider,
+    cache: &dyn BlobStorageProvider,
+    options: &RegistryCache,
+    now: time::OffsetDateTime,
+    predicate: &(dyn Fn(&Aircraft) -> bool + Sync),
+) -> Result<HashMap<Date, Aircrafts>, std::io::Error> {
+    let listing = if options.refresh {
+        None
+    } else {
+        cache
+            .maybe_get(LISTING)
+            .await?
+            .map(|data| serde_json::from_slice::<Listing>(&data))
+            .transpose()?
+            .filter(|listing| now - listing.listed_at < options.ttl)
+    };
+    let snapshots = match listing {
+        Some(listing) => listing.snapshots,
+        None => {
+            log::info!("listing snapshots of aircrafts");
+            let snapshots = list(client).await?;
+            let listing = Listing {
+                listed_at: now,
+                snapshots,
+            };
+            cache.put(LISTING, serde_json::to_vec(&listing)?).await?;
+            listing.snapshots
+        }
+    };
+
+    let action = if options.refresh {
+        crate::fs::CacheAction::FetchWrite
+    } else {
+        crate::fs::CacheAction::ReadFetchWrite
+    };
+    let tasks = snapshots.into_iter().map(|date| async move {
+        let data =
+            crate::fs::cached(&pk_to_blob_name(&date), get(date, client), cache, action).await?;
+        parse_filtered(date, &data, predicate).map(|x| (date, x))
+    });
 
     futures::stream::iter(tasks)
         .buffered(100)
@@ -217,6 +464,54 @@ mod test {
         //assert!(extract_aircrafts().await.unwrap().len() > 400000);
     }
 
+    #[test]
+    fn all_prefixes() {
+        assert_eq!(prefixes().count(), 16);
+        assert!(prefixes().any(|x| x == "9"));
+    }
+
+    #[test]
+    fn read_without_military() {
+        let data = b"icao_number,tail_number,type_designator,model,country\n459cd3,OY-GFS,F2TH,Something,Denmark\n";
+        let aircraft = csv::deserialize::<Aircraft>(data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(!aircraft[0].military);
+    }
+
+    #[test]
+    fn tail_prefix() {
+        let aircraft = |tail_number: &str| Aircraft {
+            icao_number: "340000".into(),
+            tail_number: tail_number.into(),
+            type_designator: "F2TH".into(),
+            model: "Something".into(),
+            country: Some("Spain".into()),
+            military: false,
+            owner: None,
+        };
+        let prefixes = ["EC-", "EM-"];
+        assert!(aircraft("EC-ABC").has_tail_prefix(&prefixes));
+        assert!(aircraft("EM-ABC").has_tail_prefix(&prefixes));
+        assert!(!aircraft("OY-ABC").has_tail_prefix(&prefixes));
+    }
+
+    #[test]
+    fn empty_snapshot() {
+        let date = date!(2023 - 01 - 01);
+        let error = validate(date, 0).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let aircraft = csv::deserialize::<Aircraft>(
+            b"icao_number,tail_number,type_designator,model,country\n459cd3,OY-GFS,F2TH,Something,Denmark\n",
+        )
+        .map(|a| a.map(|a| (a.icao_number.clone(), a)))
+        .collect::<Result<Aircrafts, _>>()
+        .unwrap();
+        // small snapshots are only warned about
+        assert!(validate(date, aircraft.len()).is_ok());
+    }
+
     #[tokio::test]
     async fn load_works() {
         let original = Aircraft {
@@ -225,6 +520,8 @@ mod test {
             type_designator: "F2TH".into(),
             model: "Something".into(),
             country: Some("UK".into()),
+            military: false,
+            owner: None,
         };
         let date = date!(2023 - 01 - 01);
         load(vec![original.clone()], &date, &crate::fs::LocalDisk)
@@ -239,4 +536,119 @@ mod test {
         )]);
         assert_eq!(data, expected);
     }
+
+    #[tokio::test]
+    async fn filtered() {
+        let aircraft = |icao_number: &str, model: &str, country: &str| Aircraft {
+            icao_number: icao_number.into(),
+            tail_number: format!("T-{icao_number}"),
+            type_designator: "F2TH".into(),
+            model: model.into(),
+            country: Some(country.into()),
+            military: false,
+            owner: None,
+        };
+        let client = crate::fs::InMemory::default();
+        let date = date!(2023 - 01 - 01);
+        let all = vec![
+            aircraft("a", "GULFSTREAM 5", "Denmark"),
+            aircraft("b", "GULFSTREAM 5", "Portugal"),
+            aircraft("c", "BOEING 737", "Denmark"),
+        ];
+        load(all, &date, &client).await.unwrap();
+        let predicate = |a: &Aircraft| a.country.as_deref() == Some("Denmark");
+
+        let expected = read(date, &client)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|(_, a)| predicate(a))
+            .collect::<Aircrafts>();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(
+            read_filtered(date, &client, &predicate).await.unwrap(),
+            expected
+        );
+        assert_eq!(
+            read_all_filtered(&client, &predicate).await.unwrap(),
+            HashMap::from([(date, expected)])
+        );
+
+        // validation is on the whole snapshot
+        assert!(read_filtered(date, &client, &|_| false)
+            .await
+            .unwrap()
+            .is_empty());
+        let error = read_filtered(date!(2023 - 02 - 01), &client, &predicate)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn cached() {
+        let snapshot = |icao_number: &str| {
+            vec![Aircraft {
+                icao_number: icao_number.into(),
+                tail_number: "OY-GFS".into(),
+                type_designator: "F2TH".into(),
+                model: "Something".into(),
+                country: Some("Denmark".into()),
+                military: false,
+                owner: None,
+            }]
+        };
+        let client = &crate::fs::InMemory::default();
+        let cache = &crate::fs::InMemory::default();
+        let (jan, feb) = (date!(2023 - 01 - 01), date!(2023 - 02 - 01));
+        load(snapshot("a"), &jan, client).await.unwrap();
+        let now = time::macros::datetime!(2023-02-10 12:00 UTC);
+        let options = RegistryCache::default();
+        let read = |now, options| async move {
+            let mut dates = read_all_cached(client, cache, &options, now, &|_| true)
+                .await
+                .unwrap()
+                .into_keys()
+                .collect::<Vec<_>>();
+            dates.sort();
+            dates
+        };
+
+        assert_eq!(read(now, options).await, vec![jan]);
+
+        // within the ttl, snapshots are read from the cache
+        client.delete(&pk_to_blob_name(&jan)).await.unwrap();
+        load(snapshot("b"), &feb, client).await.unwrap();
+        assert_eq!(
+            read(now + time::Duration::days(1), options).await,
+            vec![jan]
+        );
+
+        // after the ttl, snapshots are listed again
+        load(snapshot("a"), &jan, client).await.unwrap();
+        let later = now + DEFAULT_REGISTRY_TTL + time::Duration::days(1);
+        assert_eq!(read(later, options).await, vec![jan, feb]);
+
+        // a refresh lists and reads them again
+        let refresh = RegistryCache {
+            refresh: true,
+            ..options
+        };
+        let march = date!(2023 - 03 - 01);
+        load(snapshot("c"), &march, client).await.unwrap();
+        assert_eq!(read(later, refresh).await, vec![jan, feb, march]);
+    }
+
+    #[test]
+    fn icao_numbers() {
+        let data = "# Danish private jets\n459CD3\n\n  45860d \n459cd3\n";
+        assert_eq!(
+            parse_icao_numbers(data).unwrap(),
+            HashSet::from(["459cd3".into(), "45860d".into()])
+        );
+        assert!(parse_icao_numbers("").unwrap().is_empty());
+        let error = parse_icao_numbers("459cd3\nOY-GFS\n").unwrap_err();
+        assert!(error.contains("line 2"));
+        assert!(parse_icao_numbers("459cd").is_err());
+    }
 }
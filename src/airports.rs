@@ -0,0 +1,460 @@
+//! Contains the resolution of positions (e.g. the start and end of a [`Leg`](crate::legs::Leg)) to airports.
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+};
+
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    fs::BlobStorageProvider, geo::great_circle_distance_km, http::HttpFetcher,
+    icao_to_trace::AircraftLegsOptions, legs::Leg, report::AircraftLegs, LatLon,
+};
+
+/// The default maximum distance in km from an airport for a position to be resolved to it
+pub static DEFAULT_RADIUS: f64 = 5.0;
+
+/// The size in degrees of the cells of the spatial index of [`Airports`]
+static CELL: f64 = 1.0;
+
+/// The approximate length in km of one degree of latitude
+static KM_PER_DEGREE: f64 = 111.0;
+
+/// The url of the airports of [OurAirports](https://ourairports.com/data/), in the public domain
+pub static OURAIRPORTS_URL: &str = "https://davidmegginson.github.io/ourairports-data/airports.csv";
+
+/// The name of the blob where the airports of [`OURAIRPORTS_URL`] are cached
+static OURAIRPORTS_BLOB: &str = "airport/ourairports.csv";
+
+/// An airport, as described in `src/airports.csv` (or another file of the same format)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Airport {
+    /// The ICAO code (e.g. `EKCH`)
+    pub icao: String,
+    /// The IATA code (e.g. `CPH`), when it has one
+    pub iata: Option<String>,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// The elevation of the field in feet, e.g. for [`LegOptions::field_elevation`](crate::legs::LegOptions::field_elevation)
+    pub elevation: f64,
+}
+
+impl Airport {
+    pub fn pos(&self) -> LatLon {
+        LatLon {
+            latitude: self.latitude,
+            longitude: self.longitude,
+        }
+    }
+}
+
+/// An airport, as described in the CSV of [`OURAIRPORTS_URL`]; other columns are ignored
+#[derive(Deserialize)]
+struct OurAirport {
+    /// e.g. `large_airport`, `small_airport` or `heliport`
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    latitude_deg: f64,
+    longitude_deg: f64,
+    elevation_ft: Option<f64>,
+    gps_code: Option<String>,
+    iata_code: Option<String>,
+    /// Only in recent versions of the CSV
+    #[serde(default)]
+    icao_code: Option<String>,
+}
+
+impl OurAirport {
+    /// Returns it as an [`Airport`] if it is a large or medium airport with an ICAO code
+    /// (or a GPS code of 4 letters and digits, which is its ICAO code when it has one)
+    fn airport(self) -> Option<Airport> {
+        if !matches!(self.kind.as_str(), "large_airport" | "medium_airport") {
+            return None;
+        }
+        let is_icao =
+            |code: &String| code.len() == 4 && code.bytes().all(|b| b.is_ascii_alphanumeric());
+        let icao = self
+            .icao_code
+            .filter(is_icao)
+            .or_else(|| self.gps_code.filter(is_icao))?;
+        Some(Airport {
+            icao,
+            iata: self.iata_code.filter(|code| !code.is_empty()),
+            name: self.name,
+            latitude: self.latitude_deg,
+            longitude: self.longitude_deg,
+            elevation: self.elevation_ft.unwrap_or(0.0),
+        })
+    }
+}
+
+/// Returns the cell of the spatial index of [`Airports`] containing `pos`
+fn cell(pos: LatLon) -> (i32, i32) {
+    (
+        (pos.latitude / CELL).floor() as i32,
+        (pos.longitude / CELL).floor() as i32,
+    )
+}
+
+/// The known [`Airport`]s, indexed by their location
+#[derive(Debug, Clone)]
+pub struct Airports {
+    airports: Vec<Airport>,
+    /// The indexes of the airports in each cell of [`CELL`] degrees
+    grid: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Airports {
+    /// Returns the [`Airports`] of `src/airports.csv`
+    pub fn new() -> Self {
+        let data = std::fs::read("src/airports.csv").expect("src/airports.csv to exist");
+        Self::from_slice(&data).expect("src/airports.csv to be deserializable")
+    }
+
+    /// Returns the [`Airports`] of a CSV with columns `icao,iata,name,latitude,longitude,elevation`
+    /// # Error
+    /// Errors when `data` is not such a CSV
+    pub fn from_slice(data: &[u8]) -> Result<Self, std::io::Error> {
+        crate::csv::deserialize::<Airport>(data)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::from)
+    }
+
+    /// Returns the large and medium airports with an ICAO code of a CSV of OurAirports (see [`OURAIRPORTS_URL`]).
+    /// Airports without an elevation are at the sea level.
+    /// # Error
+    /// Errors when `data` is not such a CSV
+    pub fn from_ourairports(data: &[u8]) -> Result<Self, std::io::Error> {
+        crate::csv::deserialize::<OurAirport>(data)
+            .filter_map(|airport| airport.map(OurAirport::airport).transpose())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self::from)
+    }
+
+    /// Returns the [`Airport`] of the ICAO code `icao` (case insensitive), if known
+    pub fn get(&self, icao: &str) -> Option<&Airport> {
+        self.airports
+            .iter()
+            .find(|airport| airport.icao.eq_ignore_ascii_case(icao))
+    }
+
+    /// Returns the nearest [`Airport`] to `pos` within `radius` km, if any.
+    /// # Implementation
+    /// Only the airports in the cells of the spatial index overlapping `radius` are compared.
+    pub fn nearest(&self, pos: LatLon, radius: f64) -> Option<&Airport> {
+        let (row, column) = cell(pos);
+        let rows = (radius / KM_PER_DEGREE / CELL).ceil() as i32;
+        // degrees of longitude shrink towards the poles
+        let shrink = pos.latitude.abs().min(89.0).to_radians().cos();
+        let columns = ((radius / (KM_PER_DEGREE * shrink) / CELL).ceil() as i32).min(180);
+
+        (row - rows..=row + rows)
+            .flat_map(|row| (column - columns..=column + columns).map(move |column| (row, column)))
+            .filter_map(|cell| self.grid.get(&cell))
+            .flatten()
+            .map(|&index| {
+                let airport = &self.airports[index];
+                (airport, great_circle_distance_km(pos, airport.pos()))
+            })
+            .filter(|(_, distance)| *distance <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(airport, _)| airport)
+    }
+}
+
+/// Returns the large and medium airports of OurAirports (see [`Airports::from_ourairports`]), e.g. for airports
+/// not in `src/airports.csv`.
+/// # Implementation
+/// The CSV is downloaded from [`OURAIRPORTS_URL`] by `fetcher` once, and cached on `client` (or on local disk
+/// when `client` cannot be written to); delete the cached blob to download it again.
+pub async fn ourairports(
+    client: &dyn BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+) -> Result<Airports, std::io::Error> {
+    let fetch = fetcher.get_bytes(OURAIRPORTS_URL);
+    let data = crate::fs::cached_call(
+        OURAIRPORTS_BLOB,
+        fetch,
+        client,
+        crate::fs::CacheAction::ReadFetchWrite,
+    )
+    .await?;
+    Airports::from_ourairports(&data)
+}
+
+/// Returns whether `leg` starts or ends at `airport`, i.e. whether `airport` is the nearest of `airports`
+/// to either end within `radius` km (see [`Leg::from_airport`])
+fn visits(leg: &Leg, airport: &Airport, airports: &Airports, radius: f64) -> bool {
+    [leg.from().pos(), leg.to().pos()].into_iter().any(|pos| {
+        // cheap check first, so that the spatial index is only searched near `airport`
+        great_circle_distance_km(pos, airport.pos()) <= radius
+            && airports
+                .nearest(pos, radius)
+                .is_some_and(|nearest| nearest.icao == airport.icao)
+    })
+}
+
+/// Returns the legs of each aircraft of `legs` that start or end at `airport` (see [`Leg::from_airport`]),
+/// ordered by icao number. Aircraft without such legs are omitted.
+pub fn legs_visiting(
+    airport: &Airport,
+    airports: &Airports,
+    radius: f64,
+    legs: &AircraftLegs,
+) -> Vec<(Arc<str>, Vec<Leg>)> {
+    let mut visiting = legs
+        .iter()
+        .filter_map(|(icao_number, legs)| {
+            let legs = legs
+                .iter()
+                .filter(|leg| visits(leg, airport, airports, radius))
+                .cloned()
+                .collect::<Vec<_>>();
+            (!legs.is_empty()).then(|| (icao_number.clone(), legs))
+        })
+        .collect::<Vec<_>>();
+    visiting.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    visiting
+}
+
+/// The options of [`jets_visiting`]
+#[derive(Debug, Clone, Copy)]
+pub struct VisitingOptions<'a> {
+    /// The airports the airport visited is one of
+    pub airports: &'a Airports,
+    /// The maximum distance in km from the airport for a leg's start or end to visit it (see [`DEFAULT_RADIUS`])
+    pub radius: f64,
+    /// The options to identify the legs of each aircraft
+    pub legs: AircraftLegsOptions,
+}
+
+/// Returns the private jets of `icao_numbers` with a leg starting or ending at the airport `airport_icao`
+/// between `from` (inclusive) and `to` (exclusive), with those legs (see [`legs_visiting`]).
+/// # Implementation
+/// The legs of each aircraft are identified by [`aircraft_legs_with`](crate::icao_to_trace::aircraft_legs_with),
+/// at most [`AircraftLegsOptions::concurrency`] aircraft concurrently.
+/// # Error
+/// Errors when the airport is not in `airports` or when positions cannot be retrieved
+pub async fn jets_visiting(
+    airport_icao: &str,
+    icao_numbers: &HashSet<Arc<str>>,
+    from: Date,
+    to: Date,
+    client: &dyn BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    options: &VisitingOptions<'_>,
+) -> Result<Vec<(Arc<str>, Vec<Leg>)>, Box<dyn Error>> {
+    let airport = options
+        .airports
+        .get(airport_icao)
+        .ok_or_else(|| format!("Airport \"{airport_icao}\" is not known"))?;
+
+    let tasks = icao_numbers.iter().map(|icao_number| async move {
+        let legs = crate::icao_to_trace::aircraft_legs_with(
+            from,
+            to,
+            icao_number,
+            client,
+            fetcher,
+            &options.legs,
+        )
+        .await?;
+        Result::<_, Box<dyn Error>>::Ok((icao_number.clone(), legs))
+    });
+    let legs = futures::stream::iter(tasks)
+        .buffer_unordered(options.legs.concurrency)
+        .try_collect::<AircraftLegs>()
+        .await?;
+
+    Ok(legs_visiting(
+        airport,
+        options.airports,
+        options.radius,
+        &legs,
+    ))
+}
+
+impl From<Vec<Airport>> for Airports {
+    fn from(airports: Vec<Airport>) -> Self {
+        let grid = airports.iter().enumerate().fold(
+            HashMap::<_, Vec<_>>::new(),
+            |mut acc, (index, airport)| {
+                acc.entry(cell(airport.pos())).or_default().push(index);
+                acc
+            },
+        );
+        Self { airports, grid }
+    }
+}
+
+impl Default for Airports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(latitude: f64, longitude: f64) -> LatLon {
+        LatLon {
+            latitude,
+            longitude,
+        }
+    }
+
+    #[test]
+    fn nearest() {
+        let airports = Airports::new();
+        let icao = |latitude, longitude| {
+            airports
+                .nearest(at(latitude, longitude), DEFAULT_RADIUS)
+                .map(|airport| airport.icao.as_str())
+        };
+        // runway thresholds, away from the reference points
+        assert_eq!(icao(55.6270, 12.6440), Some("EKCH"));
+        assert_eq!(icao(57.0950, 9.8600), Some("EKYT"));
+        assert_eq!(icao(39.2190, -106.8650), Some("KASE"));
+        assert_eq!(icao(32.3600, -64.6800), Some("TXKF"));
+        // Roskilde and Kastrup are 33 km apart
+        assert_eq!(icao(55.5900, 12.1300), Some("EKRK"));
+        // the Kattegat
+        assert_eq!(icao(56.5, 11.5), None);
+        assert_eq!(airports.get("ekch").unwrap().iata.as_deref(), Some("CPH"));
+        assert_eq!(airports.get("KASE").unwrap().elevation, 7820.0);
+    }
+
+    #[test]
+    fn cell_boundaries() {
+        // airports in neighbouring cells (across the meridian and the equator) are found
+        let airports = Airports::from(vec![
+            Airport {
+                icao: "WEST".to_string(),
+                iata: None,
+                name: "West".to_string(),
+                latitude: 0.01,
+                longitude: -0.01,
+                elevation: 0.0,
+            },
+            Airport {
+                icao: "SOUT".to_string(),
+                iata: None,
+                name: "South".to_string(),
+                latitude: -0.05,
+                longitude: 0.05,
+                elevation: 0.0,
+            },
+        ]);
+        let nearest = |pos| airports.nearest(pos, 5.0).map(|a| a.icao.as_str());
+        assert_eq!(nearest(at(0.01, 0.01)), Some("WEST"));
+        assert_eq!(nearest(at(-0.01, 0.04)), Some("SOUT"));
+        assert_eq!(nearest(at(1.0, 1.0)), None);
+    }
+
+    #[test]
+    fn visiting() {
+        let airports = Airports::new();
+        let position = |t: i64, latitude, longitude, altitude| crate::Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude,
+            longitude,
+            altitude,
+        };
+        let leg = |from: LatLon, to: LatLon| {
+            Leg::new(vec![
+                position(0, from.latitude, from.longitude, None),
+                position(1800, 56.5, 11.0, Some(30000.0)),
+                position(3600, to.latitude, to.longitude, None),
+            ])
+        };
+        let (kastrup, billund, aalborg) = (
+            at(55.6270, 12.6440),
+            at(55.7403, 9.1518),
+            at(57.0950, 9.8600),
+        );
+        let ekch = airports.get("EKCH").unwrap();
+        let legs = AircraftLegs::from([
+            // departs from Kastrup
+            (
+                "a".into(),
+                vec![leg(kastrup, aalborg), leg(aalborg, billund)],
+            ),
+            // never at Kastrup
+            ("b".into(), vec![leg(billund, aalborg)]),
+            // arrives at Kastrup
+            ("c".into(), vec![leg(aalborg, kastrup)]),
+        ]);
+
+        let visiting = legs_visiting(ekch, &airports, DEFAULT_RADIUS, &legs);
+        let visiting = visiting
+            .iter()
+            .map(|(icao_number, legs)| (icao_number.as_ref(), legs.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(visiting, vec![("a", 1), ("c", 1)]);
+
+        // outside of the radius
+        assert!(legs_visiting(ekch, &airports, 0.1, &legs).is_empty());
+    }
+
+    /// The header and rows of the CSV of OurAirports, with only a few of its columns
+    static OURAIRPORTS: &str = r#"id,ident,type,name,latitude_deg,longitude_deg,elevation_ft,gps_code,iata_code,icao_code
+1,EKCH,large_airport,"Copenhagen Kastrup Airport",55.6181,12.6561,17,EKCH,CPH,EKCH
+2,EKRK,medium_airport,"Copenhagen Roskilde Airport",55.5856,12.1314,146,EKRK,RKE,
+3,DK-0001,small_airport,"A farm strip",55.5,12.5,10,,,
+4,DK-0002,medium_airport,"An airport without an ICAO code",55.4,12.4,,,,
+5,DK-0003,heliport,"A heliport",55.3,12.3,20,EKHP,,
+"#;
+
+    /// [`HttpFetcher`] of [`OURAIRPORTS`], counting its requests
+    #[derive(Default)]
+    struct OurAirportsFixture(std::sync::atomic::AtomicUsize);
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for OurAirportsFixture {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            assert_eq!(url, OURAIRPORTS_URL);
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Ok(OURAIRPORTS.as_bytes().to_vec())
+        }
+    }
+
+    #[test]
+    fn from_ourairports() {
+        let airports = Airports::from_ourairports(OURAIRPORTS.as_bytes()).unwrap();
+        assert_eq!(airports.airports.len(), 2);
+        let kastrup = airports.get("EKCH").unwrap();
+        assert_eq!(kastrup.iata.as_deref(), Some("CPH"));
+        assert_eq!(kastrup.name, "Copenhagen Kastrup Airport");
+        assert_eq!(kastrup.elevation, 17.0);
+        // the ICAO code is the GPS code when the column is empty
+        let roskilde = airports.get("EKRK").unwrap();
+        assert_eq!(roskilde.iata.as_deref(), Some("RKE"));
+        assert_eq!(
+            airports
+                .nearest(at(55.5900, 12.1300), DEFAULT_RADIUS)
+                .map(|airport| airport.icao.as_str()),
+            Some("EKRK")
+        );
+        assert!(airports.get("EKHP").is_none());
+        assert!(Airports::from_ourairports(b"id,name\n1,a\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn ourairports_cached() {
+        let client = crate::fs::InMemory::default();
+        let fetcher = OurAirportsFixture::default();
+        let airports = ourairports(&client, &fetcher).await.unwrap();
+        assert!(airports.get("EKCH").is_some());
+
+        // served from the cache
+        let airports = ourairports(&client, &fetcher).await.unwrap();
+        assert!(airports.get("EKRK").is_some());
+        assert_eq!(fetcher.0.into_inner(), 1);
+    }
+}
@@ -0,0 +1,85 @@
+//! Contains the running of batches of [`Job`]s, e.g. reports of different countries and periods.
+use std::{future::Future, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+/// A report to produce
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Job {
+    /// Optional country to report on (in ISO 3166); defaults to whole world
+    #[serde(default)]
+    pub country: Option<String>,
+    /// The start date (inclusive), in `yyyy-mm-dd`
+    #[serde(with = "crate::serde::date")]
+    pub from: Date,
+    /// The end date (exclusive), in `yyyy-mm-dd`
+    #[serde(with = "crate::serde::date")]
+    pub to: Date,
+    /// The path to write the report to
+    pub output: PathBuf,
+}
+
+/// Returns the [`Job`]s of a JSON array of jobs
+/// # Error
+/// Errors if `data` is not a JSON array of jobs
+pub fn read_jobs(data: &[u8]) -> Result<Vec<Job>, std::io::Error> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+/// Runs `jobs` sequentially with `run`, and returns the status of each of them.
+/// A failed job does not prevent the remaining jobs from running.
+pub async fn run<F, Fut, E>(jobs: Vec<Job>, mut run: F) -> Vec<(Job, Result<(), String>)>
+where
+    F: FnMut(Job) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let mut statuses = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        log::info!("job {job:?}");
+        let status = run(job.clone()).await.map_err(|e| e.to_string());
+        if let Err(e) = &status {
+            log::error!("job {job:?} failed: {e}");
+        }
+        statuses.push((job, status));
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn jobs() {
+        let dir = std::env::temp_dir().join(format!("batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let jobs = format!(
+            r#"[
+                {{"country": "Denmark", "from": "2024-01-01", "to": "2024-02-01", "output": "{0}/dk.md"}},
+                {{"from": "2024-01-01", "to": "2024-02-01", "output": "{0}/world.md"}},
+                {{"from": "2024-01-01", "to": "2024-02-01", "output": "{0}/missing/world.md"}}
+            ]"#,
+            dir.display()
+        );
+        let jobs = read_jobs(jobs.as_bytes()).unwrap();
+        assert_eq!(jobs[0].country.as_deref(), Some("Denmark"));
+        assert_eq!(jobs[1].to, time::macros::date!(2024 - 02 - 01));
+
+        // the last fails because its directory does not exist
+        let statuses = run(jobs, |job| async move {
+            std::fs::write(&job.output, format!("{:?}", job.country))
+        })
+        .await;
+
+        let statuses = statuses
+            .into_iter()
+            .map(|(_, status)| status.is_ok())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![true, true, false]);
+        assert!(dir.join("dk.md").exists());
+        assert!(dir.join("world.md").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
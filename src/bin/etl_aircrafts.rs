@@ -12,7 +12,7 @@ enum Backend {
     Remote,
 }
 
-const ABOUT: &'static str = r#"Creates a new snapshot of the database of all worldwide aircrafts according to `M-aircrafts-in-time`.
+const ABOUT: &str = r#"Creates a new snapshot of the database of all worldwide aircrafts according to `M-aircrafts-in-time`.
 This ETL is append only - every time it runs, it creates a new snapshot.
 If `access_key` and `secret_access_key` are not provided, data is written to the local disk.
 "#;
@@ -9,10 +9,15 @@ use futures::{StreamExt, TryStreamExt};
 use serde::Serialize;
 use simple_logger::SimpleLogger;
 
-use flights::{aircraft::Aircraft, fs::BlobStorageProvider, model::AircraftModel, Position};
+use flights::{
+    aircraft::Aircraft,
+    fs::BlobStorageProvider,
+    legs::{Leg, LegOptions},
+    model::AircraftModel,
+};
 
-static DATABASE_ROOT: &'static str = "leg/v2/";
-static DATABASE: &'static str = "leg/v2/data/";
+static DATABASE_ROOT: &str = "leg/v2/";
+static DATABASE: &str = "leg/v2/data/";
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct LegOut {
@@ -52,6 +57,8 @@ struct LegOut {
     hours_above_40000: f64,
     /// CO2 emissions in kg
     co2_emissions: f64,
+    /// The version of the methodology used to compute the leg
+    methodology: u32,
 }
 
 #[derive(serde::Serialize)]
@@ -78,50 +85,108 @@ async fn write_csv(
     client: &dyn BlobStorageProvider,
 ) -> Result<(), std::io::Error> {
     let data_csv = flights::csv::serialize(items);
-    client.put(&key, data_csv).await?;
+    client.put(key, data_csv).await?;
     Ok(())
 }
 
+/// Returns the legs of an aircraft at a given month, or `None` when its positions are not stored.
+/// When they are not stored and the month is not `required`, it has no legs.
+async fn month_legs(
+    icao_number: &str,
+    month: time::Date,
+    client: &dyn BlobStorageProvider,
+    options: &LegOptions,
+    required: &HashSet<(Arc<str>, time::Date)>,
+) -> Result<Option<Vec<Leg>>, std::io::Error> {
+    let positions =
+        flights::icao_to_trace::maybe_get_month_positions(icao_number, month, client).await?;
+    Ok(match positions {
+        Some(positions) => {
+            Some(flights::legs::legs_with(positions.into_iter(), *options).collect())
+        }
+        None if required.contains(&(icao_number.into(), month)) => None,
+        None => Some(vec![]),
+    })
+}
+
+/// Returns the legs that start at `month`, whereby legs across the boundary of two months
+/// (e.g. a red-eye departing on the 31st) are stitched with the legs of the neighbouring months
+/// (see [`flights::legs::stitch_legs`]) and belong to the month they start at.
+/// Returns `None` when a neighbouring month is needed and `required`, but its positions are not stored yet,
+/// so that the legs are computed once they are instead of cut at the boundary.
+async fn extract(
+    icao_number: &str,
+    month: time::Date,
+    client: &dyn BlobStorageProvider,
+    required: &HashSet<(Arc<str>, time::Date)>,
+) -> Result<Option<Vec<Leg>>, std::io::Error> {
+    let options = LegOptions::default();
+    let positions = flights::icao_to_trace::get_month_positions(icao_number, month, client).await?;
+    let legs = flights::legs::legs_with(positions.into_iter(), options).collect::<Vec<_>>();
+
+    // the neighbouring months are only needed when a leg is cut at the boundary
+    let previous = match legs.first() {
+        Some(leg) if leg.starts_airborne() => {
+            let previous = month
+                .previous_day()
+                .expect("dates to be far from the start of time")
+                .replace_day(1)
+                .expect("day 1 to exist");
+            match month_legs(icao_number, previous, client, &options, required).await? {
+                Some(legs) => legs,
+                None => return Ok(None),
+            }
+        }
+        _ => vec![],
+    };
+    let next = match legs.last() {
+        Some(leg) if leg.ends_airborne() => {
+            let next = flights::icao_to_trace::first_of_next_month(&month);
+            match month_legs(icao_number, next, client, &options, required).await? {
+                Some(legs) => legs,
+                None => return Ok(None),
+            }
+        }
+        _ => vec![],
+    };
+
+    let legs = [previous, legs, next].concat();
+    Ok(Some(
+        flights::legs::stitch_legs(&legs, &options)
+            .into_iter()
+            .filter(|leg| {
+                let start = leg.from().datetime().date();
+                (start.year(), start.month()) == (month.year(), month.month())
+            })
+            .collect(),
+    ))
+}
+
 fn transform<'a>(
     icao_number: &'a Arc<str>,
     aircraft: &'a Aircraft,
     model: &'a AircraftModel,
-    positions: Vec<Position>,
+    legs: Vec<Leg>,
 ) -> impl Iterator<Item = LegOut> + 'a {
-    flights::legs::legs(positions.into_iter()).map(|leg| LegOut {
+    legs.into_iter().map(|leg| LegOut {
         icao_number: icao_number.clone(),
         tail_number: aircraft.tail_number.clone().into(),
         aircraft_model: aircraft.model.clone().into(),
         start: leg.from().datetime(),
         start_lat: leg.from().latitude(),
         start_lon: leg.from().longitude(),
-        start_altitude: leg.from().altitude(),
+        start_altitude: leg.from().altitude().unwrap_or(0.0),
         end: leg.to().datetime(),
         end_lat: leg.to().latitude(),
         end_lon: leg.to().longitude(),
-        end_altitude: leg.to().altitude(),
+        end_altitude: leg.to().altitude().unwrap_or(0.0),
         duration: leg.duration().as_seconds_f64() / 60.0 / 60.0,
         distance: leg.distance(),
         great_circle_distance: leg.great_circle_distance(),
-        hours_above_30000: leg
-            .positions()
-            .windows(2)
-            .filter_map(|w| {
-                (w[0].altitude() > 30000.0 && w[1].altitude() > 30000.0).then(|| {
-                    (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0
-                })
-            })
-            .sum::<f64>(),
-        hours_above_40000: leg
-            .positions()
-            .windows(2)
-            .filter_map(|w| {
-                (w[0].altitude() > 40000.0 && w[1].altitude() > 40000.0).then(|| {
-                    (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0
-                })
-            })
-            .sum::<f64>(),
+        hours_above_30000: leg.hours_above(30000.0),
+        hours_above_40000: leg.hours_above(40000.0),
         co2_emissions: flights::emissions::leg_co2_kg(model.gph.into(), leg.duration()),
+        methodology: flights::legs::METHODOLOGY_VERSION,
     })
 }
 
@@ -133,7 +198,13 @@ async fn write(
 ) -> Result<(), Box<dyn Error>> {
     let key = pk_to_blob_name(icao, month);
 
-    write_csv(legs, &key, client).await?;
+    let mut legs = legs.peekable();
+    if legs.peek().is_some() {
+        write_csv(legs, &key, client).await?;
+    } else {
+        // stamped, so that it is not invalidated as of an unknown methodology
+        client.put(&key, flights::legs::empty_legs_csv()).await?;
+    }
     log::info!("Written {} {}", icao, month);
     Ok(())
 }
@@ -152,7 +223,7 @@ fn pk_to_blob_name(icao: &str, month: time::Date) -> String {
     format!("{DATABASE}month={month}/icao_number={icao}/data.csv")
 }
 
-const ABOUT: &'static str = "Builds the database of all legs";
+const ABOUT: &str = "Builds the database of all legs";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = ABOUT)]
@@ -166,6 +237,9 @@ struct Cli {
     /// Optional country to fetch from (in ISO 3166); defaults to whole world
     #[arg(long)]
     country: Option<String>,
+    /// Whether to include military and other state aircraft
+    #[arg(long)]
+    include_state: bool,
 }
 
 async fn etl_task(
@@ -173,15 +247,28 @@ async fn etl_task(
     model: &AircraftModel,
     month: time::Date,
     client: &dyn BlobStorageProvider,
+    required: &HashSet<(Arc<str>, time::Date)>,
 ) -> Result<(), Box<dyn Error>> {
     let icao_number = &aircraft.icao_number;
+    match read_u8(icao_number, month, client).await? {
+        Some(data) if flights::legs::is_current_methodology(&data) => {
+            log::info!("Skipped icao={icao_number} month={month}: up to date");
+            return Ok(());
+        }
+        Some(_) => {
+            log::warn!("Invalidated icao={icao_number} month={month}: older methodology")
+        }
+        None => {}
+    };
     // extract
-    let positions =
-        flights::icao_to_trace::get_month_positions(&icao_number, month, client).await?;
+    let Some(legs) = extract(icao_number, month, client, required).await? else {
+        log::info!("Deferred icao={icao_number} month={month}: positions of a neighbouring month are not stored");
+        return Ok(());
+    };
     // transform
-    let legs = transform(&icao_number, aircraft, model, positions);
+    let legs = transform(icao_number, aircraft, model, legs);
     // load
-    write(&icao_number, month, legs, client).await
+    write(icao_number, month, legs, client).await
 }
 
 async fn aggregate(
@@ -212,12 +299,19 @@ async fn aggregate(
             .await?
             .into_iter()
             .flatten() // drop those that do not exist
-            .map(|content| {
+            // drop those computed with an older methodology (e.g. whose task failed)
+            .filter(|content| {
+                let current = flights::legs::is_current_methodology(content);
+                if !current && !content.is_empty() {
+                    log::warn!("Ignored legs computed with an older methodology");
+                }
+                current
+            })
+            .flat_map(|content| {
                 flights::csv::deserialize::<LegOut>(&content)
                     .collect::<Result<Vec<_>, _>>()
                     .unwrap()
-            })
-            .flatten();
+            });
 
         log::info!("Writing all legs for year={year}");
         let key = format!("{DATABASE_ROOT}all/year={year}/data.csv");
@@ -252,16 +346,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let client = &client;
 
     log::info!("computing required tasks...");
-    let required =
-        flights::private_jets_in_month((2019..2025).rev(), cli.country.as_deref(), client).await?;
+    let required = flights::private_jets_in_month(
+        (2019..2025).rev(),
+        &flights::PrivateJetFilter {
+            country: cli.country.clone(),
+            include_state: cli.include_state,
+            ..Default::default()
+        },
+        &flights::model::load_private_jet_models()?,
+        client,
+    )
+    .await?;
     log::info!("required : {}", required.len());
 
     log::info!("executing required...");
+    let months = required.keys().cloned().collect::<HashSet<_>>();
+    let months = &months;
     let tasks = required
         .clone()
         .into_iter()
         .map(|((_, month), (aircraft, model))| async move {
-            etl_task(&aircraft, &model, month, client).await
+            etl_task(&aircraft, &model, month, client, months).await
         });
 
     let _ = futures::stream::iter(tasks)
@@ -278,3 +383,188 @@ async fn main() -> Result<(), Box<dyn Error>> {
     log::info!("aggregating...");
     aggregate(required.into_keys(), client).await
 }
+
+#[cfg(test)]
+mod test {
+    use flights::fs::InMemory;
+
+    use super::*;
+
+    /// Stores the positions of hops of 40 minutes at 30.000 feet departing at `starts` (minutes since
+    /// 2023-02-01), split by month as [`flights::icao_to_trace::month_positions`] does
+    async fn store(icao_number: &str, starts: &[i64], client: &dyn BlobStorageProvider) {
+        let midnight = time::macros::datetime!(2023-02-01 00:00 UTC);
+        let mut months = HashMap::<time::Date, Vec<serde_json::Value>>::new();
+        for start in starts {
+            for i in 0..=8 {
+                let datetime = midnight + time::Duration::minutes(start + i * 5);
+                let mut position = serde_json::json!({
+                    "datetime": datetime.format(&time::format_description::well_known::Rfc3339).unwrap(),
+                    "latitude": 55.6 + (start + i * 5) as f64 * 0.02,
+                    "longitude": 12.6,
+                });
+                if i > 0 && i < 8 {
+                    position["altitude"] = 30000.0.into();
+                }
+                let month = datetime.date().replace_day(1).unwrap();
+                months.entry(month).or_default().push(position);
+            }
+        }
+        for (month, positions) in months {
+            let month = flights::serde::month_to_part(month);
+            let blob = format!("position/icao_number={icao_number}/month={month}/data.json");
+            client
+                .put(&blob, serde_json::to_vec(&positions).unwrap())
+                .await
+                .unwrap();
+        }
+    }
+
+    /// The aircraft `aa0001` of the positions of [`store`], and its model
+    fn falcon() -> (Aircraft, AircraftModel) {
+        let aircraft = Aircraft {
+            icao_number: "aa0001".into(),
+            tail_number: "OY-GFS".to_string(),
+            type_designator: "F2TH".to_string(),
+            model: "FALCON 2000".to_string(),
+            country: None,
+            military: false,
+            owner: None,
+        };
+        let model = AircraftModel {
+            model: "FALCON 2000".to_string(),
+            gph: 240,
+            source: "".to_string(),
+            date: "".to_string(),
+        };
+        (aircraft, model)
+    }
+
+    #[tokio::test]
+    async fn stitch_across_months() {
+        let client = InMemory::default();
+        // a hop on January, a red-eye across midnight, and a hop on February
+        store("aa0001", &[-300, -20, 120], &client).await;
+        let (aircraft, model) = falcon();
+
+        let january = time::macros::date!(2023 - 01 - 01);
+        let february = time::macros::date!(2023 - 02 - 01);
+        let required = HashSet::from([
+            (aircraft.icao_number.clone(), january),
+            (aircraft.icao_number.clone(), february),
+        ]);
+        for month in [january, february] {
+            etl_task(&aircraft, &model, month, &client, &required)
+                .await
+                .unwrap();
+        }
+        let legs = |month| {
+            let client = &client;
+            let icao_number = aircraft.icao_number.clone();
+            async move {
+                let content = read_u8(&icao_number, month, client).await.unwrap().unwrap();
+                flights::csv::deserialize::<LegOut>(&content)
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap()
+            }
+        };
+
+        // the red-eye is whole and belongs to the month it departed at
+        let january = legs(january).await;
+        assert_eq!(january.len(), 2);
+        assert_eq!(
+            january[1].start,
+            time::macros::datetime!(2023-01-31 23:40 UTC)
+        );
+        assert_eq!(
+            january[1].end,
+            time::macros::datetime!(2023-02-01 00:20 UTC)
+        );
+        let february = legs(february).await;
+        assert_eq!(february.len(), 1);
+        assert_eq!(
+            february[0].start,
+            time::macros::datetime!(2023-02-01 02:00 UTC)
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_neighbour_is_deferred() {
+        let client = InMemory::default();
+        // a red-eye across midnight, whose February is not stored yet
+        store("aa0001", &[-20], &client).await;
+        let february_blob = "position/icao_number=aa0001/month=2023-02/data.json";
+        let february_positions = client.maybe_get(february_blob).await.unwrap().unwrap();
+        client.delete(february_blob).await.unwrap();
+        let (aircraft, model) = falcon();
+
+        let january = time::macros::date!(2023 - 01 - 01);
+        let february = time::macros::date!(2023 - 02 - 01);
+        let required = HashSet::from([
+            (aircraft.icao_number.clone(), january),
+            (aircraft.icao_number.clone(), february),
+        ]);
+        etl_task(&aircraft, &model, january, &client, &required)
+            .await
+            .unwrap();
+        // not written, so that it is computed again
+        let stored = read_u8(&aircraft.icao_number, january, &client).await;
+        assert_eq!(stored.unwrap(), None);
+
+        client.put(february_blob, february_positions).await.unwrap();
+        etl_task(&aircraft, &model, january, &client, &required)
+            .await
+            .unwrap();
+        let content = read_u8(&aircraft.icao_number, january, &client)
+            .await
+            .unwrap()
+            .unwrap();
+        let legs = flights::csv::deserialize::<LegOut>(&content)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(legs.len(), 1);
+        assert_eq!(legs[0].end, time::macros::datetime!(2023-02-01 00:20 UTC));
+
+        // a neighbour that is not required is not needed, e.g. before the first month of the database
+        let client = InMemory::default();
+        store("aa0001", &[-20], &client).await;
+        client.delete(february_blob).await.unwrap();
+        etl_task(&aircraft, &model, january, &client, &HashSet::new())
+            .await
+            .unwrap();
+        let content = read_u8(&aircraft.icao_number, january, &client)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(flights::legs::is_current_methodology(&content));
+    }
+
+    #[tokio::test]
+    async fn empty_month() {
+        let client = InMemory::default();
+        let march = time::macros::date!(2023 - 03 - 01);
+        client
+            .put(
+                "position/icao_number=aa0001/month=2023-03/data.json",
+                b"[]".to_vec(),
+            )
+            .await
+            .unwrap();
+        let (aircraft, model) = falcon();
+
+        etl_task(&aircraft, &model, march, &client, &HashSet::new())
+            .await
+            .unwrap();
+
+        // no legs, of the current methodology
+        let content = read_u8(&aircraft.icao_number, march, &client)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(flights::legs::is_current_methodology(&content));
+        let legs = flights::csv::deserialize::<LegOut>(&content)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(legs.is_empty());
+    }
+}
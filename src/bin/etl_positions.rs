@@ -1,10 +1,14 @@
-use std::{collections::HashSet, error::Error};
+use std::{collections::HashSet, error::Error, path::PathBuf};
 
 use clap::Parser;
-use futures::StreamExt;
 use simple_logger::SimpleLogger;
 
-const ABOUT: &'static str = r#"Builds the database of all private jet positions since 2019"#;
+use flights::{
+    http::{Limited, Retry, RetryPolicy},
+    icao_to_trace::AdsbExchange,
+};
+
+const ABOUT: &str = r#"Builds the database of all private jet positions since 2019"#;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = ABOUT)]
@@ -18,6 +22,50 @@ struct Cli {
     /// Optional country to fetch from (in ISO 3166); defaults to whole world
     #[arg(long)]
     country: Option<String>,
+    /// Whether to include military and other state aircraft
+    #[arg(long)]
+    include_state: bool,
+    /// Optional path to a local catalog of the existing positions, used to only list
+    /// those that are not in it (e.g. new months). Created when it does not exist, and updated
+    /// with the positions stored.
+    #[arg(long)]
+    catalog: Option<PathBuf>,
+    /// Whether to list all existing positions and rebuild the catalog
+    #[arg(long)]
+    rebuild_catalog: bool,
+    /// The maximum number of (icao, month), and of days within each, fetched concurrently
+    #[arg(long, default_value_t = 10)]
+    concurrency: usize,
+    /// The maximum number of concurrent requests to adsbexchange
+    #[arg(long, default_value_t = 10)]
+    max_requests: usize,
+    /// The maximum number of attempts of each request to adsbexchange and to the remote database,
+    /// including the first; only transient errors (429, 5xx, connection errors and timeouts) are retried
+    #[arg(long, default_value_t = RetryPolicy::default().max_attempts)]
+    retry_attempts: usize,
+    /// The delay in seconds before the first retry of a request; it doubles on each subsequent retry
+    #[arg(long, default_value_t = RetryPolicy::default().base_delay.as_secs_f64())]
+    retry_base_delay: f64,
+    /// The maximum fraction of the delay before a retry added at random, so that concurrent retries spread out
+    #[arg(long, default_value_t = RetryPolicy::default().jitter)]
+    retry_jitter: f64,
+    /// The compression of the positions written (`none`, `gzip` or `zstd`); positions of any compression are read
+    #[arg(long, default_value = "none")]
+    compression: flights::fs::Compression,
+    /// Whether to only print how many months are in the database and how many would be fetched, without fetching
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Cli {
+    /// The [`RetryPolicy`] of `--retry-attempts`, `--retry-base-delay` and `--retry-jitter`
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.retry_attempts,
+            base_delay: std::time::Duration::from_secs_f64(self.retry_base_delay),
+            jitter: self.retry_jitter,
+        }
+    }
 }
 
 #[tokio::main(flavor = "multi_thread")]
@@ -29,35 +77,85 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let cli = Cli::parse();
 
-    let client = flights::fs_s3::client(cli.access_key, cli.secret_access_key).await;
+    let client = flights::fs_s3::ContainerClientBuilder::default()
+        .credentials(cli.access_key.clone(), cli.secret_access_key.clone())
+        .retry_policy(cli.retry_policy())
+        .compression(cli.compression)
+        .build()
+        .await;
+    // retries wait without holding one of the `--max-requests`
+    let fetcher = &Retry::new(
+        Limited::new(AdsbExchange, cli.max_requests),
+        client.retry_policy(),
+    );
 
-    let required =
-        flights::private_jets_in_month((2019..2025).rev(), cli.country.as_deref(), &client).await?;
+    let required = flights::private_jets_in_month(
+        (2019..2025).rev(),
+        &flights::PrivateJetFilter {
+            country: cli.country.clone(),
+            include_state: cli.include_state,
+            ..Default::default()
+        },
+        &flights::model::load_private_jet_models()?,
+        &client,
+    )
+    .await?;
 
     let required = required.keys().cloned().collect::<HashSet<_>>();
 
     log::info!("required : {}", required.len());
 
-    let completed = flights::icao_to_trace::list_months_positions(&client).await?;
+    let completed = if let Some(catalog) = &cli.catalog {
+        flights::icao_to_trace::list_months_positions_cached(
+            &required,
+            catalog,
+            cli.rebuild_catalog,
+            &client,
+        )
+        .await?
+    } else {
+        flights::icao_to_trace::list_months_positions(&client).await?
+    };
     log::info!("completed: {}", completed.len());
-    let mut todo = required.difference(&completed).collect::<Vec<_>>();
-    todo.sort_unstable_by_key(|(icao_number, date)| (date, icao_number));
-    log::info!("todo     : {}", todo.len());
-
-    let tasks = todo.into_iter().map(|(icao_number, month)| {
-        flights::icao_to_trace::month_positions(icao_number, *month, &client)
-    });
-
-    futures::stream::iter(tasks)
-        // limit concurrent tasks
-        .buffered(10)
-        // continue if error
-        .map(|r| {
-            if let Err(e) = r {
-                log::error!("{e}");
-            }
-        })
-        .collect::<Vec<_>>()
-        .await;
+    if cli.dry_run {
+        let estimate = flights::icao_to_trace::FetchEstimate::new(&required, &completed);
+        println!("{}", serde_json::to_string(&estimate)?);
+        return Ok(());
+    }
+    let log_progress = |progress: flights::icao_to_trace::Progress| {
+        log::info!(
+            "{}/{}: {} {}",
+            progress.completed,
+            progress.total,
+            progress.icao_number,
+            progress.month
+        )
+    };
+    let backfill = flights::icao_to_trace::backfill_required(
+        &required,
+        &completed,
+        &client,
+        fetcher,
+        cli.concurrency,
+        &log_progress,
+    )
+    .await;
+    log::info!(
+        "skipped: {}, stored: {}, failed: {}",
+        backfill.skipped,
+        backfill.stored,
+        backfill.errors.len()
+    );
+    if let Some(catalog) = &cli.catalog {
+        let failed = backfill
+            .errors
+            .iter()
+            .map(|(pk, _)| pk)
+            .collect::<HashSet<_>>();
+        flights::icao_to_trace::catalog_stored(
+            catalog,
+            required.iter().filter(|pk| !failed.contains(pk)),
+        )?;
+    }
     Ok(())
 }
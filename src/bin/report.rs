@@ -0,0 +1,1090 @@
+use std::{collections::HashMap, error::Error, path::PathBuf, sync::Arc};
+
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use simple_logger::SimpleLogger;
+use time::Date;
+
+use flights::{
+    aircraft::{Aircraft, Aircrafts, RegistryCache},
+    airports::Airports,
+    batch::Job,
+    emissions::{CitizenEmissions, Class, EmissionsOptions},
+    export::Format,
+    fs,
+    http::{HttpFetcher, Limited, Retry, RetryPolicy},
+    icao_to_trace::{AdsbExchange, AircraftLegsOptions},
+    model::AircraftModels,
+    report::{
+        AircraftLegs, BusinessHours, CitizenFact, Co2eFact, CommercialFact, Comparison,
+        CountryPlace, CountryReport, Fleet, Report, TimeZone,
+    },
+    Countries, PrivateJetFilter, RequiredTasks,
+};
+
+#[derive(clap::ValueEnum, Debug, Clone)]
+enum Backend {
+    Disk,
+    Remote,
+}
+
+const ABOUT: &str = r#"Reports the legs of private jets between two dates, optionally of a country.
+Positions are read from the database (and fetched from adsbexchange when missing).
+Alternatively, `--jobs` reports on each job of a file, continuing when a job fails.
+Defaults of the arguments are read from `--config` (e.g. `country = "Denmark"`), when it exists.
+The subcommand `list-aircraft` lists the private jets matching the filters instead, without fetching any position.
+"#;
+
+/// The default path of `--config`
+static CONFIG: &str = "private-jets.toml";
+
+fn parse_date(arg: &str) -> Result<Date, time::error::Parse> {
+    Date::parse(
+        arg,
+        time::macros::format_description!("[year]-[month]-[day]"),
+    )
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT, subcommand_negates_reqs = true)]
+struct Cli {
+    /// Optional path to a TOML file with the defaults of the arguments, whose keys are their names
+    /// (e.g. `tail_prefix = ["OY-"]` or `include_state = true`); arguments passed override them.
+    /// Defaults to `private-jets.toml`, when it exists
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Optional country to report on (in ISO 3166); defaults to whole world
+    #[arg(long)]
+    country: Option<String>,
+    /// Optional second country (in ISO 3166) to compare `--country` with, reporting on both side by side
+    #[arg(long, requires = "country", conflicts_with_all = ["tail_prefix", "jobs", "output_jsonld"])]
+    compare: Option<String>,
+    /// Optional country to report on by its ISO 3166-1 alpha-2 code (e.g. `DK`), as listed in `--countries`.
+    /// Reports on the aircraft registered in it (i.e. whose tail number starts with any of its `tail_prefixes`),
+    /// unless `--tail-prefix` is passed
+    #[arg(long, conflicts_with_all = ["country", "jobs"])]
+    country_code: Option<String>,
+    /// Optional path to a JSON array of countries (`{"code": .., "name": .., "plural": .., "possessive": .., "tail_prefixes": [..]}`)
+    /// used to resolve `--country-code`; defaults to `src/countries.json`
+    #[arg(long, requires = "country_code")]
+    countries: Option<PathBuf>,
+    /// Optional tail number prefixes to report on (e.g. `OY-`), overriding `--country`
+    #[arg(long)]
+    tail_prefix: Vec<String>,
+    /// Whether to include military and other state aircraft
+    #[arg(long)]
+    include_state: bool,
+    /// Optional path to a CSV of private jet models with the columns of `src/models.csv`; defaults to `src/models.csv`
+    #[arg(long)]
+    models: Option<PathBuf>,
+    /// Optional owner or operator of the aircraft to report on (case insensitive), when known by adsbexchange
+    #[arg(long)]
+    owner: Option<String>,
+    /// Optional path to a file with the icao numbers of the aircraft to report on, one per line (e.g. `459cd3`),
+    /// overriding `--country`, `--tail-prefix`, `--include-state` and `--owner`
+    #[arg(long, conflicts_with_all = ["country", "country_code", "compare"])]
+    icao_file: Option<PathBuf>,
+    /// The start date (inclusive), in `yyyy-mm-dd`
+    #[arg(long, value_parser = parse_date, required_unless_present = "jobs")]
+    from: Option<Date>,
+    /// The end date (exclusive), in `yyyy-mm-dd`
+    #[arg(long, value_parser = parse_date, required_unless_present = "jobs")]
+    to: Option<Date>,
+    /// Where the database is read from
+    #[arg(long, value_enum, default_value_t = Backend::Remote)]
+    backend: Backend,
+    /// Optional name of a bucket owned by the user to write the positions missing from the remote database to,
+    /// and read them from in later runs (requires `--write-access-key` and `--write-secret-access-key`)
+    #[arg(long, requires_all = ["write_access_key", "write_secret_access_key"])]
+    write_bucket: Option<String>,
+    /// The access key of `--write-bucket`
+    #[arg(long, requires = "write_bucket")]
+    write_access_key: Option<String>,
+    /// The secret access key of `--write-bucket`
+    #[arg(long, requires = "write_bucket")]
+    write_secret_access_key: Option<String>,
+    /// The compression of the positions written to `--write-bucket` (`none`, `gzip` or `zstd`)
+    #[arg(long, requires = "write_bucket", default_value = "none")]
+    write_compression: fs::Compression,
+    /// Whether to list and read the snapshots of aircrafts from the remote database again,
+    /// instead of from their cache on disk
+    #[arg(long)]
+    refresh_registry: bool,
+    /// The number of days during which the list of snapshots of aircrafts is read from their cache on disk
+    #[arg(long, default_value_t = flights::aircraft::DEFAULT_REGISTRY_TTL.whole_days())]
+    registry_ttl_days: i64,
+    /// Optional root directory of the database of `--backend disk`, with the same layout as the remote database;
+    /// defaults to the environment variable `FLIGHTS_DATABASE`, or `database/`
+    #[arg(long)]
+    disk_root: Option<PathBuf>,
+    /// Optional path to write the report to, whose extension selects the format: the report
+    /// (`md` or `json`) or its legs (`csv`, `geojson`, `kml` or `gpx`); defaults to markdown to stdout,
+    /// or to `<country>_story.json` for `--format json` of `--country`
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Optional format of the report (`markdown`, `json`, `csv`, `geojson`, `kml` or `gpx`), overriding the
+    /// extension of `--output`; defaults to the extension of `--output`, or markdown without it
+    #[arg(long)]
+    format: Option<Format>,
+    /// Optional path to also write the facts of the report to, as schema.org JSON-LD
+    #[arg(long)]
+    output_jsonld: Option<PathBuf>,
+    /// The maximum number of concurrent requests to adsbexchange
+    #[arg(long, default_value_t = 10)]
+    max_requests: usize,
+    /// The maximum number of attempts of each request to adsbexchange and to the remote database,
+    /// including the first; only transient errors (429, 5xx, connection errors and timeouts) are retried
+    #[arg(long, default_value_t = RetryPolicy::default().max_attempts)]
+    retry_attempts: usize,
+    /// The delay in seconds before the first retry of a request; it doubles on each subsequent retry
+    #[arg(long, default_value_t = RetryPolicy::default().base_delay.as_secs_f64())]
+    retry_base_delay: f64,
+    /// The maximum fraction of the delay before a retry added at random, so that concurrent retries spread out
+    #[arg(long, default_value_t = RetryPolicy::default().jitter)]
+    retry_jitter: f64,
+    /// Optional path to a CSV of places (e.g. airports) with columns `country,latitude,longitude`,
+    /// used to report the foreign countries flown to and from `--country`
+    #[arg(long)]
+    places: Option<PathBuf>,
+    /// Optional path to a CSV of the annual CO2 emissions per capita of countries with columns
+    /// `country,year,tons_co2,source`, used to compare the emissions to those of citizens of `--country`
+    /// on the year of `--from` (or on the latest year available)
+    #[arg(long)]
+    citizen_emissions: Option<PathBuf>,
+    /// Optional radiative forcing index to also report CO2 equivalent emissions accounting for
+    /// the non-CO2 effects of flying at altitude (e.g. `2.7`)
+    #[arg(long)]
+    rfi: Option<f64>,
+    /// Whether to also report CO2 equivalent emissions including those of producing the fuel
+    #[arg(long)]
+    well_to_wake: bool,
+    /// Optional class (`economy`, `business` or `first`) of a commercial passenger flying the same legs,
+    /// whose emissions are reported for comparison
+    #[arg(long)]
+    commercial_class: Option<Class>,
+    /// The great-circle distance in km below which a leg is short, i.e. where rail is a competitive alternative
+    #[arg(long, default_value_t = flights::report::SHORT_LEG_KM)]
+    short_leg_km: f64,
+    /// The boundaries in km of the bands of great-circle distance by which legs and emissions are reported
+    #[arg(long, value_delimiter = ',', default_values_t = flights::report::DISTANCE_BANDS_KM)]
+    distance_bands: Vec<f64>,
+    /// The maximum distance in km from a place for a leg's start or end to be resolved to its country
+    #[arg(long, default_value_t = 10.0)]
+    places_radius: f64,
+    /// Optional ICAO code of an airport (e.g. `LSZS`) to write the legs of the private jets starting or ending at it
+    /// instead of a report, in a format of legs (`csv`, `geojson`, `kml` or `gpx`); defaults to CSV to stdout
+    #[arg(long, conflicts_with_all = ["compare", "jobs", "output_jsonld"])]
+    visiting: Option<String>,
+    /// Optional path to a CSV of airports with columns `icao,iata,name,latitude,longitude,elevation` (as `src/airports.csv`)
+    /// used by `--visiting`; defaults to the large and medium airports of OurAirports, downloaded once and cached
+    #[arg(long, requires = "visiting")]
+    airports: Option<PathBuf>,
+    /// The maximum distance in km from the airport of `--visiting` for a leg's start or end to be at it
+    #[arg(long, requires = "visiting", default_value_t = flights::airports::DEFAULT_RADIUS)]
+    airport_radius: f64,
+    /// Optional path to write the number of legs of each private jet on each day to, as CSV
+    /// with one row per icao number and one column per day (e.g. for a calendar heatmap)
+    #[arg(long, conflicts_with = "jobs")]
+    output_activity: Option<PathBuf>,
+    /// Optional path to write the CO2 emissions of each month to, as CSV with columns
+    /// `month,co2_emissions_tons` (e.g. to chart trends)
+    #[arg(long, conflicts_with = "jobs")]
+    output_monthly: Option<PathBuf>,
+    /// Whether to only report on legs overlapping business hours (monday to friday, 07:00 to 19:00 local time)
+    #[arg(long)]
+    business_hours: bool,
+    /// Whether business hours include saturdays and sundays
+    #[arg(long, requires = "business_hours")]
+    include_weekends: bool,
+    /// The time zone of local time used for business hours and the departures by hour and day, either an IANA
+    /// time zone adjusted for daylight saving time (e.g. `Europe/Copenhagen`) or a fixed offset from UTC (e.g. `+01:00`)
+    #[arg(long, alias = "utc-offset", default_value = "+00:00")]
+    time_zone: TimeZone,
+    /// Whether to compare the aircraft active in the period with those active in the previous
+    /// period of the same duration, reporting which are newly active and newly dormant
+    #[arg(long)]
+    compare_previous: bool,
+    /// The number of private jets in the ranking of the largest emitters (the leaderboard of the report)
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+    /// Optional maximum number of private jets to report on, e.g. for quick runs.
+    /// Selects those with the smallest icao numbers, or a random sample with `--seed`
+    #[arg(long)]
+    max_aircraft: Option<usize>,
+    /// Optional seed of the random sample of `--max-aircraft`; the same seed selects the same private jets
+    #[arg(long, requires = "max_aircraft")]
+    seed: Option<u64>,
+    /// The maximum speed (km/h) relative to its neighbours above which a position is dropped as a glitch
+    /// before identifying legs
+    #[arg(long, default_value_t = flights::legs::MAX_PLAUSIBLE_SPEED)]
+    max_speed: f64,
+    /// Optional number of positions over which altitude is smoothed (moving median) before identifying legs
+    #[arg(long)]
+    smooth_window: Option<usize>,
+    /// The maximum number of months (and days within a month) of an aircraft fetched concurrently
+    #[arg(long, default_value_t = flights::icao_to_trace::DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+    /// Optional path to a JSON array of jobs (`{"country": .., "from": .., "to": .., "output": ..}`)
+    /// to report on sequentially, instead of `--country`, `--from`, `--to` and `--output`
+    #[arg(long, conflicts_with_all = ["country", "from", "to", "output", "output_jsonld", "format"])]
+    jobs: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Lists the private jets of the latest snapshot of aircrafts matching the filters (e.g. `--country`,
+    /// `--tail-prefix`, `--owner`, `--icao-file` or `--models`, passed before it), without fetching any position.
+    /// Useful to validate filters before a long scan
+    ListAircraft(ListAircraft),
+}
+
+#[derive(clap::Args, Debug)]
+struct ListAircraft {
+    /// Optional path to write the list as JSON to; defaults to CSV to stdout
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Optional path to write the models of the aircraft matching the filters that are not private jet models
+    /// to, as CSV with columns `model,aircraft` (the number of aircraft of the model)
+    #[arg(long)]
+    report_unmatched: Option<PathBuf>,
+}
+
+impl Cli {
+    /// The [`RetryPolicy`] of `--retry-attempts`, `--retry-base-delay` and `--retry-jitter`
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.retry_attempts,
+            base_delay: std::time::Duration::from_secs_f64(self.retry_base_delay),
+            jitter: self.retry_jitter,
+        }
+    }
+
+    /// The [`AircraftLegsOptions`] of `--concurrency`, `--smooth-window` and `--max-speed`
+    fn legs_options(&self) -> AircraftLegsOptions {
+        AircraftLegsOptions {
+            concurrency: self.concurrency,
+            smooth_window: self.smooth_window,
+            legs: flights::legs::LegOptions {
+                max_speed: self.max_speed,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The format of `--format`, or of the extension of `--output`; defaults to CSV for `--visiting`,
+    /// and to markdown otherwise
+    fn output_format(&self) -> Result<Format, String> {
+        match (self.format, self.output.as_deref()) {
+            (Some(format), _) => Ok(format),
+            (None, Some(path)) => Format::from_path(path),
+            (None, None) if self.visiting.is_some() => Ok(Format::Csv),
+            (None, None) => Ok(Format::Markdown),
+        }
+    }
+
+    /// The path to write the report of `--country` to: `--output`, or `<country>_story.json` (e.g.
+    /// `denmark_story.json`) for `--format json`. `None` writes it to stdout
+    fn report_output(&self) -> Option<PathBuf> {
+        match (&self.output, self.format, &self.country) {
+            (Some(path), _, _) => Some(path.clone()),
+            (None, Some(Format::Json), Some(country)) => {
+                Some(format!("{}_story.json", country.to_lowercase().replace(' ', "_")).into())
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolves `--country-code` from `--countries` into the name of the country and, unless
+    /// `--tail-prefix` is passed, the tail prefixes of the aircraft registered in it
+    fn resolve_country_code(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(code) = &self.country_code else {
+            return Ok(());
+        };
+        let countries = match &self.countries {
+            Some(path) => Countries::from_slice(&std::fs::read(path)?)?,
+            None => Countries::new(),
+        };
+        let country = countries
+            .get(code)
+            .ok_or_else(|| format!("Country code \"{code}\" is not known"))?;
+        if self.tail_prefix.is_empty() {
+            self.tail_prefix = country.tail_prefixes.clone();
+        }
+        self.country = Some(country.name.clone());
+        Ok(())
+    }
+}
+
+/// Returns the value of the argument `--{name}` of `args`, if passed
+fn argument<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let flag = format!("--{name}");
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if *arg == flag {
+            args.get(i + 1).map(String::as_str)
+        } else {
+            arg.strip_prefix(&flag)?.strip_prefix('=')
+        }
+    })
+}
+
+/// Returns `args` (the program name followed by arguments) with the arguments of `config` (a TOML table
+/// whose keys are names of arguments) inserted before them, except those in `args`, so that they take precedence.
+/// `true` booleans are flags, `false` ones are omitted, and arrays repeat the argument.
+/// # Error
+/// Errors when `config` is not such a table
+fn with_config(config: &str, args: Vec<String>) -> Result<Vec<String>, Box<dyn Error>> {
+    let config = config.parse::<toml::Table>()?;
+    let mut args = args.into_iter();
+    let mut merged = args.next().into_iter().collect::<Vec<_>>();
+    let args = args.collect::<Vec<_>>();
+
+    for (key, value) in config {
+        let flag = format!("--{}", key.replace('_', "-"));
+        let passed = args
+            .iter()
+            .any(|arg| *arg == flag || arg.starts_with(&format!("{flag}=")));
+        if passed {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => merged.push(flag.clone()),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(value) => merged.extend([flag.clone(), value]),
+                toml::Value::Integer(value) => merged.extend([flag.clone(), value.to_string()]),
+                toml::Value::Float(value) => merged.extend([flag.clone(), value.to_string()]),
+                toml::Value::Datetime(value) => merged.extend([flag.clone(), value.to_string()]),
+                _ => return Err(format!("`{key}` must be a value or an array of values").into()),
+            }
+        }
+    }
+    merged.extend(args);
+    Ok(merged)
+}
+
+/// Returns the [`Cli`] of the arguments of the process, with the defaults of its `--config`
+fn parse_cli() -> Result<Cli, Box<dyn Error>> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let args = match argument(&args[1..], "config") {
+        Some(path) => with_config(&std::fs::read_to_string(path)?, args)?,
+        None if std::path::Path::new(CONFIG).exists() => {
+            with_config(&std::fs::read_to_string(CONFIG)?, args)?
+        }
+        None => args,
+    };
+    Ok(Cli::parse_from(args))
+}
+
+/// Returns the period between `from` and `to` (see [`flights::date_range`]) and the start of the positions
+/// required to report on it, which includes the previous period when comparing with it
+fn period(from: Date, to: Date, cli: &Cli) -> Result<(Date, Date, Date), Box<dyn Error>> {
+    let (from, to) = flights::date_range(from, to, time::OffsetDateTime::now_utc().date())?;
+    let start = if cli.compare_previous {
+        from - (to - from)
+    } else {
+        from
+    };
+    Ok((from, to, start))
+}
+
+/// Returns the private jet models of `--models`
+fn models(cli: &Cli) -> Result<AircraftModels, Box<dyn Error>> {
+    Ok(match &cli.models {
+        Some(path) => flights::model::load_private_jet_models_from(path)?,
+        None => flights::model::load_private_jet_models()?,
+    })
+}
+
+/// Returns the private jets of `filter` in each month between `start` and `to`
+async fn private_jets(
+    start: Date,
+    to: Date,
+    filter: &PrivateJetFilter,
+    cli: &Cli,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<RequiredTasks, Box<dyn Error>> {
+    let models = models(cli)?;
+    Ok(if client.can_put() {
+        flights::private_jets_in_month(start.year()..=to.year(), filter, &models, client).await?
+    } else {
+        // the remote database is read-only: cache its snapshots of aircrafts on disk
+        let options = RegistryCache {
+            ttl: time::Duration::days(cli.registry_ttl_days),
+            refresh: cli.refresh_registry,
+        };
+        flights::private_jets_in_month_cached(
+            start.year()..=to.year(),
+            filter,
+            &models,
+            client,
+            &fs::LocalDisk,
+            &options,
+        )
+        .await?
+    })
+}
+
+/// Returns the [`PrivateJetFilter`] of `country` (or of `--tail-prefix` when not empty, or of `--icao-file`)
+fn filter(country: Option<&str>, cli: &Cli) -> Result<PrivateJetFilter, Box<dyn Error>> {
+    let icao_numbers = match &cli.icao_file {
+        Some(path) => {
+            let data = std::fs::read_to_string(path)?;
+            let icao_numbers = flights::aircraft::parse_icao_numbers(&data)
+                .map_err(|e| format!("{path:?}: {e}"))?;
+            Some(icao_numbers)
+        }
+        None => None,
+    };
+    Ok(PrivateJetFilter {
+        country: country.map(|x| x.to_string()),
+        tail_prefixes: cli.tail_prefix.clone(),
+        include_state: cli.include_state,
+        owner: cli.owner.clone(),
+        icao_numbers,
+    })
+}
+
+/// Returns the private jets of `aircrafts` matching `filter`, ordered by icao number
+fn matching_aircraft(
+    aircrafts: Aircrafts,
+    models: &AircraftModels,
+    filter: &PrivateJetFilter,
+) -> Vec<Arc<Aircraft>> {
+    let mut private_jets = flights::private_jets(aircrafts, models, filter)
+        .into_values()
+        .map(|(aircraft, _)| aircraft)
+        .collect::<Vec<_>>();
+    private_jets.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+    private_jets
+}
+
+/// Writes the private jets of the latest snapshot of aircrafts matching the filters of `cli`
+/// (see [`Command::ListAircraft`])
+async fn list_aircraft(
+    args: &ListAircraft,
+    cli: &Cli,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<(), Box<dyn Error>> {
+    let models = models(cli)?;
+    let (date, aircrafts) = flights::aircraft::read_latest(client).await?;
+    log::info!("snapshot of aircrafts from {date}");
+
+    let filter = filter(cli.country.as_deref(), cli)?;
+    if let Some(path) = &args.report_unmatched {
+        let unmatched = flights::unmatched_models(&aircrafts, &models, &filter);
+        log::info!("unmatched models: {}", unmatched.len());
+        for model in unmatched.iter().take(10) {
+            log::info!(
+                "unmatched model: {} ({} aircraft)",
+                model.model,
+                model.aircraft
+            );
+        }
+        std::fs::write(path, flights::csv::serialize(unmatched.iter()))?;
+    }
+
+    let private_jets = matching_aircraft(aircrafts, &models, &filter);
+    log::info!("private jets: {}", private_jets.len());
+
+    let items = private_jets.iter().map(|a| a.as_ref());
+    match &args.output {
+        Some(path) => std::fs::write(path, serde_json::to_vec(&items.collect::<Vec<_>>())?)?,
+        None => print!("{}", String::from_utf8(flights::csv::serialize(items))?),
+    }
+    Ok(())
+}
+
+/// Returns the private jets of `required` in any of the months between `start` and `to`
+fn in_period(required: RequiredTasks, start: Date, to: Date) -> RequiredTasks {
+    required
+        .into_iter()
+        .filter(|((_, month), _)| {
+            flights::icao_to_trace::first_of_next_month(month) > start && month < &to
+        })
+        .collect()
+}
+
+/// Returns the legs between `from` and `to` of the private jets of `--country` (or of `--tail-prefix` when not empty)
+/// starting or ending at the airport `airport` (see [`flights::airports::jets_visiting`]), serialized in `format`
+async fn visiting(
+    airport: &str,
+    format: Format,
+    from: Date,
+    to: Date,
+    cli: &Cli,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (from, to, _) = period(from, to, cli)?;
+    let filter = filter(cli.country.as_deref(), cli)?;
+    let required = private_jets(from, to, &filter, cli, client).await?;
+    let fleet = Fleet::new(in_period(required, from, to));
+    log::info!("private jets: {}", fleet.private_jets.len());
+
+    let airports = match &cli.airports {
+        Some(path) => Airports::from_slice(&std::fs::read(path)?)?,
+        None => {
+            let fetcher = Retry::new(flights::http::Get, cli.retry_policy());
+            flights::airports::ourairports(client, &fetcher).await?
+        }
+    };
+    let icao_numbers = fleet.private_jets.keys().cloned().collect();
+    let options = flights::airports::VisitingOptions {
+        airports: &airports,
+        radius: cli.airport_radius,
+        legs: cli.legs_options(),
+    };
+    let legs = flights::airports::jets_visiting(
+        airport,
+        &icao_numbers,
+        from,
+        to,
+        client,
+        fetcher,
+        &options,
+    )
+    .await?;
+    log::info!("private jets visiting {airport}: {}", legs.len());
+    let legs = legs.into_iter().collect::<AircraftLegs>();
+    Ok(flights::export::export_legs(format, &fleet, &legs)?)
+}
+
+/// A [`Report`] and the private jets and legs it was computed from
+struct Reported {
+    report: Report,
+    fleet: Fleet,
+    legs: AircraftLegs,
+}
+
+impl Reported {
+    /// Returns it serialized in `format`: its legs for formats of legs (see [`Format::is_legs`]),
+    /// its report otherwise
+    fn export(&self, format: Format) -> Result<Vec<u8>, std::io::Error> {
+        if format.is_legs() {
+            flights::export::export_legs(format, &self.fleet, &self.legs)
+        } else {
+            flights::export::export(format, &self.report)
+        }
+    }
+}
+
+/// Returns the [`Report`] of the private jets of `country` (or of `--tail-prefix` when not empty) between `from` and `to`.
+/// The private jets are selected from `registry` when passed (e.g. of all countries, loaded once for several reports),
+/// or loaded otherwise.
+async fn report(
+    country: Option<&str>,
+    from: Date,
+    to: Date,
+    cli: &Cli,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    registry: Option<&RequiredTasks>,
+) -> Result<Reported, Box<dyn Error>> {
+    let (from, to, start) = period(from, to, cli)?;
+    let filter = filter(country, cli)?;
+    let required = match registry {
+        Some(registry) => registry
+            .iter()
+            .filter(|(_, (aircraft, _))| filter.matches(aircraft))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        None => private_jets(start, to, &filter, cli, client).await?,
+    };
+    if let Some(icao_numbers) = &filter.icao_numbers {
+        for icao_number in icao_numbers {
+            if !required.keys().any(|(x, _)| x == icao_number) {
+                log::warn!(
+                    "{icao_number} is not a private jet of a known model; it is not reported"
+                );
+            }
+        }
+    }
+    let mut fleet = Fleet::new(in_period(required, start, to));
+    if let Some(max) = cli.max_aircraft {
+        fleet.private_jets = flights::sample_private_jets(fleet.private_jets, max, cli.seed);
+    }
+    let private_jets = &fleet.private_jets;
+    log::info!("private jets: {}", private_jets.len());
+
+    let options = &cli.legs_options();
+    let tasks = private_jets.keys().map(|icao_number| async move {
+        let legs = flights::icao_to_trace::aircraft_legs_with(
+            start,
+            to,
+            icao_number,
+            client,
+            fetcher,
+            options,
+        )
+        .await?;
+        Result::<_, Box<dyn Error>>::Ok((Arc::clone(icao_number), legs))
+    });
+    let legs = futures::stream::iter(tasks)
+        .buffered(10)
+        .try_collect::<HashMap<_, _>>()
+        .await?;
+    let activity_changes = cli
+        .compare_previous
+        .then(|| flights::report::activity_changes(&fleet, &legs, from, to));
+    let business_hours = cli.business_hours.then_some(BusinessHours {
+        time_zone: cli.time_zone,
+        include_weekends: cli.include_weekends,
+        ..Default::default()
+    });
+    // the legs of the period
+    let legs = legs
+        .into_iter()
+        .map(|(icao_number, legs)| {
+            let legs = legs
+                .into_iter()
+                .filter(|leg| leg.from().datetime().date() >= from)
+                .filter(|leg| match &business_hours {
+                    Some(hours) => hours.overlaps(leg),
+                    None => true,
+                })
+                .collect::<Vec<_>>();
+            (icao_number, legs)
+        })
+        .collect::<HashMap<_, _>>();
+
+    if let Some(path) = &cli.output_activity {
+        let (icao_numbers, days, grid) = flights::report::activity_matrix(&legs, from, to);
+        let mut csv = std::iter::once("icao_number".to_string())
+            .chain(days.iter().map(|day| day.to_string()))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push('\n');
+        for (icao_number, row) in icao_numbers.iter().zip(grid) {
+            csv.push_str(icao_number);
+            row.iter()
+                .for_each(|count| csv.push_str(&format!(",{count}")));
+            csv.push('\n');
+        }
+        std::fs::write(path, csv)?;
+    }
+
+    if let Some(path) = &cli.output_monthly {
+        let monthly = flights::report::monthly_emissions(&fleet, &legs);
+        flights::export::monthly_emissions_to_csv(std::fs::File::create(path)?, &monthly)?;
+    }
+
+    let mut report = Report::new(&fleet, &legs);
+    report.ranking.truncate(cli.top);
+    report.short_legs = Some(flights::report::short_legs(&fleet, &legs, cli.short_leg_km));
+    report.distance_bands = Some(flights::report::emissions_by_distance_band(
+        &fleet,
+        &legs,
+        &cli.distance_bands,
+    ));
+    report.departures = Some(flights::report::departure_histogram(&legs, cli.time_zone));
+    report.activity_changes = activity_changes;
+    if let (Some(places), Some(country)) = (&cli.places, filter.country.as_deref()) {
+        let places = flights::csv::deserialize::<CountryPlace>(&std::fs::read(places)?)
+            .collect::<Result<Vec<_>, _>>()?;
+        let resolve = |pos| flights::report::nearest_country(&places, pos, cli.places_radius);
+        report.foreign_countries = Some(flights::report::foreign_countries(
+            &fleet, &legs, country, resolve,
+        ));
+    }
+    if let (Some(path), Some(country)) = (&cli.citizen_emissions, filter.country.as_deref()) {
+        let table = flights::csv::deserialize::<CitizenEmissions>(&std::fs::read(path)?)
+            .collect::<Result<Vec<_>, _>>()?;
+        report.citizen_emissions =
+            flights::emissions::citizen_emissions(&table, country, from.year())
+                .map(|citizen| CitizenFact::new(report.co2_emissions, citizen));
+    }
+    let options = EmissionsOptions {
+        rfi: cli.rfi,
+        well_to_wake: cli.well_to_wake,
+    };
+    report.co2e = Co2eFact::new(report.co2_emissions, &options);
+    report.commercial = cli
+        .commercial_class
+        .map(|class| CommercialFact::new(&legs, class));
+    Ok(Reported {
+        report,
+        fleet,
+        legs,
+    })
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let mut cli = parse_cli()?;
+    cli.resolve_country_code()?;
+    let format = cli.output_format()?;
+    if cli.visiting.is_some() && !format.is_legs() {
+        return Err(format!(
+            "--visiting writes legs in csv, geojson, kml or gpx, not in {}",
+            format.extension()
+        )
+        .into());
+    }
+    if cli.compare.is_some() && format.is_legs() {
+        return Err(format!(
+            "--compare reports in markdown or json, not in {}",
+            format.extension()
+        )
+        .into());
+    }
+
+    let remote: Option<Box<dyn fs::BlobStorageProvider>> = match cli.backend {
+        Backend::Disk => None,
+        Backend::Remote => {
+            let remote = flights::fs_s3::ContainerClientBuilder::default()
+                .anonymous()
+                .retry_policy(cli.retry_policy())
+                .build()
+                .await;
+            match &cli.write_bucket {
+                Some(bucket) => {
+                    // both are required by `--write-bucket`
+                    let writable = flights::fs_s3::ContainerClientBuilder::default()
+                        .bucket(bucket)
+                        .credentials(
+                            cli.write_access_key.clone().unwrap(),
+                            cli.write_secret_access_key.clone().unwrap(),
+                        )
+                        .retry_policy(cli.retry_policy())
+                        .compression(cli.write_compression)
+                        .build()
+                        .await;
+                    Some(Box::new(fs::Layered::new(remote, writable)))
+                }
+                None => Some(Box::new(remote)),
+            }
+        }
+    };
+    let disk = cli.disk_root.clone().map(fs::Disk::new);
+    let client = match (&remote, &disk) {
+        (Some(remote), _) => remote.as_ref(),
+        (None, Some(disk)) => disk,
+        (None, None) => &fs::LocalDisk,
+    };
+    if let Some(Command::ListAircraft(args)) = &cli.command {
+        return list_aircraft(args, &cli, client).await;
+    }
+    // retries wait without holding one of the `--max-requests`
+    let fetcher = &Retry::new(
+        Limited::new(AdsbExchange, cli.max_requests),
+        cli.retry_policy(),
+    );
+
+    if let Some(jobs) = &cli.jobs {
+        let jobs = flights::batch::read_jobs(&std::fs::read(jobs)?)?;
+        let cli = &cli;
+        let statuses = flights::batch::run(jobs, |job: Job| async move {
+            let format = Format::from_path(&job.output)?;
+            let reported = report(
+                job.country.as_deref(),
+                job.from,
+                job.to,
+                cli,
+                client,
+                fetcher,
+                None,
+            )
+            .await?;
+            std::fs::write(&job.output, reported.export(format)?)?;
+            Result::<_, Box<dyn Error>>::Ok(())
+        })
+        .await;
+
+        let failed = statuses
+            .iter()
+            .filter(|(_, status)| status.is_err())
+            .count();
+        for (job, status) in &statuses {
+            match status {
+                Ok(()) => log::info!("{:?}: ok", job.output),
+                Err(e) => log::error!("{:?}: failed: {e}", job.output),
+            }
+        }
+        return if failed == 0 {
+            Ok(())
+        } else {
+            Err(format!("{failed} of {} jobs failed", statuses.len()).into())
+        };
+    }
+
+    // both are required without `--jobs`
+    let (from, to) = (cli.from.unwrap(), cli.to.unwrap());
+    if let Some(airport) = &cli.visiting {
+        let data = visiting(airport, format, from, to, &cli, client, fetcher).await?;
+        match &cli.output {
+            Some(path) => std::fs::write(path, data)?,
+            None => println!("{}", String::from_utf8(data)?),
+        }
+        return Ok(());
+    }
+    let country = cli.country.clone();
+    if let (Some(country), Some(other)) = (&country, &cli.compare) {
+        // the private jets of both countries, loaded once
+        let (_, period_to, start) = period(from, to, &cli)?;
+        let registry = private_jets(start, period_to, &filter(None, &cli)?, &cli, client).await?;
+        let mut countries = vec![];
+        for country in [country, other] {
+            let report = report(
+                Some(country),
+                from,
+                to,
+                &cli,
+                client,
+                fetcher,
+                Some(&registry),
+            );
+            countries.push(CountryReport {
+                country: country.clone(),
+                report: report.await?.report,
+            });
+        }
+        let data = flights::export::export_comparison(format, &Comparison { countries })?;
+        match &cli.output {
+            Some(path) => std::fs::write(path, data)?,
+            None => println!("{}", String::from_utf8(data)?),
+        }
+        return Ok(());
+    }
+
+    let reported = report(country.as_deref(), from, to, &cli, client, fetcher, None).await?;
+    if let Some(path) = &cli.output_jsonld {
+        std::fs::write(
+            path,
+            flights::export::to_jsonld(&reported.report, from, to)?,
+        )?;
+    }
+    let data = reported.export(format)?;
+    match cli.report_output() {
+        Some(path) => std::fs::write(path, data)?,
+        None => println!("{}", String::from_utf8(data)?),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config() {
+        let config = r#"
+country = "Denmark"
+from = 2024-01-01
+to = "2024-02-01"
+tail_prefix = ["OY-", "OZ-"]
+include_state = true
+well_to_wake = false
+top = 5
+"#;
+        let args = |args: &[&str]| {
+            let args = std::iter::once("report")
+                .chain(args.iter().copied())
+                .map(str::to_string)
+                .collect();
+            Cli::try_parse_from(with_config(config, args).unwrap()).unwrap()
+        };
+
+        let cli = args(&[]);
+        assert_eq!(cli.country.as_deref(), Some("Denmark"));
+        assert_eq!(cli.from, Some(time::macros::date!(2024 - 01 - 01)));
+        assert_eq!(cli.to, Some(time::macros::date!(2024 - 02 - 01)));
+        assert_eq!(cli.tail_prefix, vec!["OY-", "OZ-"]);
+        assert!(cli.include_state);
+        assert!(!cli.well_to_wake);
+        assert_eq!(cli.top, 5);
+
+        // arguments take precedence
+        let cli = args(&["--country", "Sweden", "--tail-prefix=SE-", "--top", "3"]);
+        assert_eq!(cli.country.as_deref(), Some("Sweden"));
+        assert_eq!(cli.tail_prefix, vec!["SE-"]);
+        assert_eq!(cli.top, 3);
+        assert_eq!(cli.from, Some(time::macros::date!(2024 - 01 - 01)));
+
+        let args = ["report", "--config", "a.toml", "--to=2024-03-01"].map(str::to_string);
+        assert_eq!(argument(&args[1..], "config"), Some("a.toml"));
+        assert_eq!(argument(&args[1..], "to"), Some("2024-03-01"));
+        assert_eq!(argument(&args[1..], "from"), None);
+        assert!(with_config("country = { name = \"Denmark\" }", vec![]).is_err());
+    }
+
+    #[test]
+    fn retry_policy() {
+        let cli = Cli::try_parse_from(["report", "--from=2024-01-01", "--to=2024-02-01"]).unwrap();
+        assert_eq!(cli.retry_policy(), RetryPolicy::default());
+
+        let cli = Cli::try_parse_from([
+            "report",
+            "--from=2024-01-01",
+            "--to=2024-02-01",
+            "--retry-attempts=5",
+            "--retry-base-delay=0.5",
+            "--retry-jitter=0",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.retry_policy(),
+            RetryPolicy {
+                max_attempts: 5,
+                base_delay: std::time::Duration::from_millis(500),
+                jitter: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn output_format() {
+        let args = |args: &[&str]| {
+            Cli::try_parse_from(
+                ["report", "--from=2024-01-01", "--to=2024-02-01"]
+                    .iter()
+                    .chain(args),
+            )
+            .unwrap()
+        };
+
+        // markdown to stdout by default
+        let cli = args(&["--country=Denmark"]);
+        assert_eq!(cli.output_format(), Ok(Format::Markdown));
+        assert_eq!(cli.report_output(), None);
+
+        // json of a country to its story
+        let cli = args(&["--country=United Kingdom", "--format=json"]);
+        assert_eq!(cli.output_format(), Ok(Format::Json));
+        assert_eq!(
+            cli.report_output(),
+            Some(PathBuf::from("united_kingdom_story.json"))
+        );
+
+        // `--output` takes precedence
+        let cli = args(&["--country=Denmark", "--format=json", "--output=a.md"]);
+        assert_eq!(cli.output_format(), Ok(Format::Json));
+        assert_eq!(cli.report_output(), Some(PathBuf::from("a.md")));
+        let cli = args(&["--country=Denmark", "--output=a.json"]);
+        assert_eq!(cli.output_format(), Ok(Format::Json));
+
+        let cli = args(&["--country=Denmark", "--visiting=LSZS"]);
+        assert_eq!(cli.output_format(), Ok(Format::Csv));
+    }
+
+    #[test]
+    fn legs_options() {
+        let cli = Cli::try_parse_from(["report", "--from=2024-01-01", "--to=2024-02-01"]).unwrap();
+        assert_eq!(cli.legs_options(), AircraftLegsOptions::default());
+
+        let cli = Cli::try_parse_from([
+            "report",
+            "--from=2024-01-01",
+            "--to=2024-02-01",
+            "--concurrency=2",
+            "--smooth-window=5",
+            "--max-speed=1000",
+        ])
+        .unwrap();
+        let options = cli.legs_options();
+        assert_eq!(options.concurrency, 2);
+        assert_eq!(options.smooth_window, Some(5));
+        assert_eq!(options.legs.max_speed, 1000.0);
+    }
+
+    #[test]
+    fn country_code() {
+        let args = |args: &[&str]| {
+            let mut cli = Cli::try_parse_from(
+                ["report", "--from=2024-01-01", "--to=2024-02-01"]
+                    .iter()
+                    .chain(args),
+            )
+            .unwrap();
+            cli.resolve_country_code().map(|_| cli)
+        };
+
+        let cli = args(&["--country-code=dk"]).unwrap();
+        assert_eq!(cli.country.as_deref(), Some("Denmark"));
+        assert_eq!(cli.tail_prefix, vec!["OY-"]);
+        let filter = filter(cli.country.as_deref(), &cli).unwrap();
+        assert_eq!(filter.tail_prefixes, vec!["OY-"]);
+
+        // `--tail-prefix` takes precedence
+        let cli = args(&["--country-code=DK", "--tail-prefix=OZ-"]).unwrap();
+        assert_eq!(cli.country.as_deref(), Some("Denmark"));
+        assert_eq!(cli.tail_prefix, vec!["OZ-"]);
+
+        assert!(args(&["--country-code=XX"]).is_err());
+        let cli = args(&["--country=Denmark"]).unwrap();
+        assert!(cli.tail_prefix.is_empty());
+    }
+
+    #[test]
+    fn list_aircraft() {
+        let aircraft = |icao_number: &str, tail_number: &str, model: &str, owner: &str| {
+            let aircraft = Aircraft {
+                icao_number: icao_number.into(),
+                tail_number: tail_number.to_string(),
+                type_designator: "".to_string(),
+                model: model.to_string(),
+                country: None,
+                military: false,
+                owner: Some(owner.to_string()),
+            };
+            (aircraft.icao_number.clone(), aircraft)
+        };
+        let aircrafts = || {
+            Aircrafts::from([
+                aircraft("45b2c1", "OY-CKK", "GULFSTREAM 5", "Private Air"),
+                aircraft("459cd3", "OY-GFS", "FALCON 2000", "Jet Charter"),
+                aircraft("4ca7b4", "EI-DLO", "FALCON 2000", "Jet Charter"),
+                aircraft("45ac45", "OY-JRJ", "BOEING 737", "Jet Charter"),
+            ])
+        };
+        let models = ["GULFSTREAM 5", "FALCON 2000"]
+            .map(|model| {
+                let model = flights::model::AircraftModel {
+                    model: model.to_string(),
+                    gph: 400,
+                    source: "".to_string(),
+                    date: "".to_string(),
+                };
+                (model.model.clone(), Arc::new(model))
+            })
+            .into();
+        let list = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["report"].iter().chain(args)).unwrap();
+            // no period is required
+            assert!(matches!(cli.command, Some(Command::ListAircraft(_))));
+            let filter = filter(cli.country.as_deref(), &cli).unwrap();
+            matching_aircraft(aircrafts(), &models, &filter)
+                .iter()
+                .map(|aircraft| aircraft.icao_number.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // only private jet models
+        assert_eq!(list(&["list-aircraft"]), ["459cd3", "45b2c1", "4ca7b4"]);
+        assert_eq!(
+            list(&["--tail-prefix=OY-", "list-aircraft"]),
+            ["459cd3", "45b2c1"]
+        );
+        assert_eq!(
+            list(&["--tail-prefix=OY-", "--owner=jet charter", "list-aircraft"]),
+            ["459cd3"]
+        );
+
+        let cli =
+            Cli::try_parse_from(["report", "list-aircraft", "--output=aircraft.json"]).unwrap();
+        let Some(Command::ListAircraft(args)) = cli.command else {
+            panic!("list-aircraft is a subcommand")
+        };
+        assert_eq!(args.output, Some(PathBuf::from("aircraft.json")));
+    }
+}
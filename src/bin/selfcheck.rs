@@ -0,0 +1,39 @@
+use std::{error::Error, path::PathBuf};
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+use flights::selfcheck::Summary;
+
+const ABOUT: &str = r#"Runs the full analysis against a committed fixture (a few aircraft and a couple of months of traces),
+without network, and checks that the result matches the expected summary of the fixture.
+It fails when the methodology drifted, in which case the expected summary must be reviewed.
+"#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    /// The directory of the fixture
+    #[arg(long, default_value = "tests/fixtures/selfcheck")]
+    fixture: PathBuf,
+    /// The maximum relative difference between computed and expected numbers
+    #[arg(long, default_value_t = 0.001)]
+    tolerance: f64,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Warn)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let expected: Summary =
+        serde_json::from_slice(&std::fs::read(cli.fixture.join("expected.json"))?)?;
+    let actual = flights::selfcheck::run(&cli.fixture).await?;
+    flights::selfcheck::check(&actual, &expected, cli.tolerance)?;
+    println!("{}", serde_json::to_string_pretty(&actual)?);
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use std::{collections::HashMap, sync::Arc};
 
+use crate::aircraft::Aircraft;
+
 #[derive(Debug, serde::Deserialize, Clone)]
 struct CountryRange {
     country: String,
@@ -19,12 +21,13 @@ impl IcaoRange {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct CountryIcaoRanges(HashMap<Arc<str>, IcaoRange>);
+pub struct CountryIcaoRanges(HashMap<Arc<str>, Vec<IcaoRange>>);
 
 impl CountryIcaoRanges {
     /// Returns a new [`CountryRanges`] based on ICAO's mandatory guidelines,
     /// https://www.icao.int/Meetings/AMC/MA/NACC_DCA03_2008/naccdca3wp05.pdf
-    /// Countries names are in ISO 3166.
+    /// Countries names are in ISO 3166. A country may have more than one range
+    /// (e.g. Bermuda), in which case an icao number is of the country if it is in any of them.
     pub fn new() -> Self {
         // note: the country.json was extracted from
         // https://globe.adsbexchange.com/adsbx_comb_index_tarmisc_min_fc01616f370a6163a397b31cbee9dcd9.js on 2024-02-10
@@ -34,15 +37,15 @@ impl CountryIcaoRanges {
         let value: Vec<CountryRange> =
             serde_json::from_slice(&data).expect("src/country.json to be deserializable");
 
-        Self(
-            value
-                .into_iter()
-                .map(|range| (range.country.into(), IcaoRange(range.start, range.end)))
-                .collect(),
-        )
+        Self(value.into_iter().fold(HashMap::new(), |mut acc, range| {
+            acc.entry(range.country.into())
+                .or_default()
+                .push(IcaoRange(range.start, range.end));
+            acc
+        }))
     }
 
-    /// Returns the country (ISO 3166) of the icao_number.
+    /// Returns the country (ISO 3166) of the icao_number, from the narrowest range containing it.
     /// `O(N)` where N is the number of countries in https://www.icao.int/Meetings/AMC/MA/NACC_DCA03_2008/naccdca3wp05.pdf
     pub fn country(&self, icao_number: &str) -> Result<Option<&Arc<str>>, String> {
         let Ok(icao_number_u32) = u32::from_str_radix(icao_number, 16) else {
@@ -51,7 +54,67 @@ impl CountryIcaoRanges {
         Ok(self
             .0
             .iter()
-            .find_map(|(c, range)| range.contains(icao_number_u32).then_some(c)))
+            .flat_map(|(c, ranges)| ranges.iter().map(move |range| (c, range)))
+            .filter(|(_, range)| range.contains(icao_number_u32))
+            // ranges may be nested (e.g. Bermuda within United Kingdom); the narrowest is the most specific
+            .min_by_key(|(_, range)| range.1 - range.0)
+            .map(|(c, _)| c))
+    }
+}
+
+/// A country, as described in `src/countries.json` (or another file of the same format)
+#[derive(Debug, serde::Deserialize, Clone, PartialEq, Eq)]
+pub struct Country {
+    /// The code in ISO 3166-1 alpha-2 (e.g. `DK`)
+    pub code: String,
+    /// The name, as in the database of aircrafts (e.g. `Denmark`)
+    pub name: String,
+    /// The plural of its citizens (e.g. `Danes`)
+    pub plural: String,
+    /// The possessive (e.g. `Danish`)
+    pub possessive: String,
+    /// The prefixes of the tail numbers of aircraft registered in it (e.g. `OY-`).
+    /// Some countries have more than one (e.g. Bermuda, `VP-B` and `VQ-B`)
+    pub tail_prefixes: Vec<String>,
+}
+
+impl Country {
+    /// Returns whether `aircraft` is registered in this country, i.e. its tail number
+    /// starts with any of [`Country::tail_prefixes`]
+    pub fn is_registered(&self, aircraft: &Aircraft) -> bool {
+        aircraft.has_tail_prefix(&self.tail_prefixes)
+    }
+}
+
+/// The known [`Country`]s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Countries(Vec<Country>);
+
+impl Countries {
+    /// Returns the [`Countries`] of `src/countries.json`
+    pub fn new() -> Self {
+        let data = std::fs::read("src/countries.json").expect("src/countries.json to exist");
+        Self::from_slice(&data).expect("src/countries.json to be deserializable")
+    }
+
+    /// Returns the [`Countries`] of a JSON array of [`Country`]
+    /// # Error
+    /// Errors when `data` is not a JSON array of [`Country`]
+    pub fn from_slice(data: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(data).map(Self)
+    }
+
+    /// Returns the [`Country`] of the ISO 3166-1 alpha-2 `code` (case insensitive), if known
+    pub fn get(&self, code: &str) -> Option<&Country> {
+        self.0
+            .iter()
+            .find(|country| country.code.eq_ignore_ascii_case(code))
+    }
+}
+
+impl Default for Countries {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -59,6 +122,47 @@ impl CountryIcaoRanges {
 mod test {
     use super::*;
 
+    #[test]
+    fn countries() {
+        let countries = Countries::new();
+        let denmark = countries.get("dk").unwrap();
+        assert_eq!(denmark.name, "Denmark");
+        assert_eq!(denmark.tail_prefixes, vec!["OY-".to_string()]);
+        assert_eq!(countries.get("DE").unwrap().plural, "Germans");
+        assert!(countries.get("XX").is_none());
+
+        let custom = br#"[{"code": "IT", "name": "Italy", "plural": "Italians", "possessive": "Italian", "tail_prefixes": ["I-"]}]"#;
+        let custom = Countries::from_slice(custom).unwrap();
+        assert_eq!(
+            custom.get("it").unwrap().tail_prefixes,
+            vec!["I-".to_string()]
+        );
+        assert!(Countries::from_slice(b"{}").is_err());
+    }
+
+    #[test]
+    fn multiple_tail_prefixes() {
+        let aircraft = |tail_number: &str| Aircraft {
+            icao_number: "424200".into(),
+            tail_number: tail_number.into(),
+            type_designator: "GLF5".into(),
+            model: "GULFSTREAM 5".into(),
+            country: Some("Bermuda".into()),
+            military: false,
+            owner: None,
+        };
+        let countries = Countries::new();
+        let bermuda = countries.get("BM").unwrap();
+        // only matches the second prefix
+        assert!(bermuda.is_registered(&aircraft("VQ-BAA")));
+        assert!(bermuda.is_registered(&aircraft("VP-BAA")));
+        assert!(!bermuda.is_registered(&aircraft("VP-CAA")));
+
+        let denmark = countries.get("DK").unwrap();
+        assert!(denmark.is_registered(&aircraft("OY-GFS")));
+        assert!(!denmark.is_registered(&aircraft("VQ-BAA")));
+    }
+
     #[test]
     fn positive() {
         assert_eq!(
@@ -67,6 +171,17 @@ mod test {
         );
     }
 
+    #[test]
+    fn multiple_ranges() {
+        let ranges = CountryIcaoRanges::new();
+        // Bermuda has 3 ranges
+        for icao_number in ["400000", "424200", "43BEFF"] {
+            assert_eq!(ranges.country(icao_number), Ok(Some(&"Bermuda".into())));
+        }
+        // and is within the range of the United Kingdom
+        assert_eq!(ranges.country("400200"), Ok(Some(&"United Kingdom".into())));
+    }
+
     #[test]
     fn negative() {
         // exists in ads-b, but can't be assigned
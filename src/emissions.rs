@@ -1,12 +1,234 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{legs::Leg, model::AircraftModels};
+
 static LITER_PER_GALON: f64 = 3.78541;
 static KG_PER_LITER: f64 = 0.8;
-static EMISSIONS_PER_KG: f64 = 3.16;
+/// The CO2 in kg emitted by combusting one kg of Jet-A fuel
+/// (the stoichiometric factor used by ICAO and the EEA for kerosene)
+pub static EMISSIONS_PER_KG: f64 = 3.16;
 
 /// Returns the total CO2 emissions in kg of a private jet with a given
 /// consumption (in GPH) of Jet-A fuel flying for a given amount of time.
 pub fn leg_co2_kg(consumption: f64, duration: time::Duration) -> f64 {
     let hours = duration.as_seconds_f64() / 60.0 / 60.0;
-    consumption * hours * LITER_PER_GALON * KG_PER_LITER * EMISSIONS_PER_KG
+    fuel_co2_kg(consumption * hours * LITER_PER_GALON * KG_PER_LITER)
+}
+
+/// Returns the CO2 emissions in kg of combusting `fuel_kg` kg of Jet-A fuel (see [`EMISSIONS_PER_KG`])
+pub fn fuel_co2_kg(fuel_kg: f64) -> f64 {
+    fuel_kg * EMISSIONS_PER_KG
+}
+
+/// The fuel burnt taxiing and climbing to cruise altitude in excess of cruising, in hours of
+/// the cruise consumption of the model. Climbing burns considerably more than cruising, which
+/// dominates the fuel of short legs.
+pub static TAXI_CLIMB_HOURS: f64 = 0.2;
+
+/// Returns the fuel in kg of Jet-A burnt by a private jet with a given consumption (in GPH) at cruise
+/// flying for a given amount of time, including taxiing and climbing (see [`TAXI_CLIMB_HOURS`]).
+pub fn fuel_kg(consumption: f64, duration: time::Duration) -> f64 {
+    let hours = duration.as_seconds_f64() / 60.0 / 60.0 + TAXI_CLIMB_HOURS;
+    consumption * hours * LITER_PER_GALON * KG_PER_LITER
+}
+
+/// Returns the fuel in kg of Jet-A burnt by a private jet of `model` flying `leg` (see [`fuel_kg`]).
+/// Its CO2 emissions are [`fuel_co2_kg`] of it.
+/// # Implementation
+/// Uses the consumption of `model` in `models`, falling back to [`DEFAULT_GPH`] when `model` is unknown.
+pub fn fuel_burn_kg(leg: &Leg, model: &str, models: &AircraftModels) -> f64 {
+    let consumption = models
+        .get(model)
+        .map(|model| model.gph.into())
+        .unwrap_or(DEFAULT_GPH);
+    fuel_kg(consumption, leg.duration())
+}
+
+/// The consumption (in GPH) assumed for models without a known consumption,
+/// the median consumption of the models in `src/models.csv`
+pub static DEFAULT_GPH: f64 = 280.0;
+
+/// Returns the total CO2 emissions in kg of a private jet of `model` flying for a given amount of time.
+/// # Implementation
+/// Uses the consumption of `model` in `models`, falling back to [`DEFAULT_GPH`] when `model` is unknown.
+pub fn model_leg_co2_kg(model: &str, models: &AircraftModels, duration: time::Duration) -> f64 {
+    let consumption = models
+        .get(model)
+        .map(|model| model.gph.into())
+        .unwrap_or(DEFAULT_GPH);
+    leg_co2_kg(consumption, duration)
+}
+
+/// The emissions of producing and transporting Jet-A ("well-to-tank") relative to the CO2 of its combustion
+/// (about 0.54 vs 2.54 kg CO2e per liter in the UK government GHG conversion factors for aviation turbine fuel)
+pub static WELL_TO_TANK: f64 = 0.21;
+
+/// Options to report CO2 equivalent (CO2e) emissions instead of the CO2 of combusting fuel.
+/// The [`Default`] reports CO2 only, i.e. [`EmissionsOptions::co2e_kg`] returns its input.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EmissionsOptions {
+    /// The radiative forcing index multiplying the emissions of combustion to account for the
+    /// non-CO2 effects of flying at altitude (e.g. contrails). Commonly between 2 and 3.
+    pub rfi: Option<f64>,
+    /// Whether to add the emissions of producing the fuel (see [`WELL_TO_TANK`])
+    pub well_to_wake: bool,
+}
+
+impl EmissionsOptions {
+    /// Returns the CO2e emissions in kg corresponding to `co2_kg` of CO2 emitted by combustion.
+    /// # Implementation
+    /// The radiative forcing index only applies to combustion, as upstream emissions happen on the ground.
+    pub fn co2e_kg(&self, co2_kg: f64) -> f64 {
+        let upstream = if self.well_to_wake { WELL_TO_TANK } else { 0.0 };
+        co2_kg * (self.rfi.unwrap_or(1.0) + upstream)
+    }
+
+    /// Returns a description of the assumptions of these options, or `None` when they report CO2 only
+    pub fn assumptions(&self) -> Option<String> {
+        let mut assumptions = vec![];
+        if let Some(rfi) = self.rfi {
+            assumptions.push(format!("radiative forcing index of {rfi}"));
+        }
+        if self.well_to_wake {
+            assumptions.push(format!(
+                "well-to-tank emissions of {:.0}% of combustion (UK government GHG conversion factors)",
+                WELL_TO_TANK * 100.0
+            ));
+        }
+        (!assumptions.is_empty()).then(|| assumptions.join(" and "))
+    }
+}
+
+/// The CO2 emissions in kg per passenger and km of an economy seat of a commercial long-haul flight
+/// (about 0.08 in the UK government GHG conversion factors, without radiative forcing)
+pub static ECONOMY_KG_PER_PASSENGER_KM: f64 = 0.08;
+
+/// The class of a seat of a commercial flight, to compare the emissions of private jets to
+/// those of a passenger flying the same distance commercially
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    Economy,
+    Business,
+    First,
+}
+
+impl Class {
+    /// The emissions of a seat of this class relative to an economy seat, i.e. the floor area it occupies
+    /// (the weighting of long-haul classes of the UK government GHG conversion factors)
+    pub fn factor(&self) -> f64 {
+        match self {
+            Class::Economy => 1.0,
+            Class::Business => 2.9,
+            Class::First => 4.0,
+        }
+    }
+
+    /// Returns the CO2 emissions in kg of a passenger of this class flying `distance` km commercially
+    pub fn co2_kg(&self, distance: f64) -> f64 {
+        distance * ECONOMY_KG_PER_PASSENGER_KM * self.factor()
+    }
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Class::Economy => "economy",
+            Class::Business => "business",
+            Class::First => "first",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::str::FromStr for Class {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "economy" => Ok(Class::Economy),
+            "business" => Ok(Class::Business),
+            "first" => Ok(Class::First),
+            _ => Err(format!(
+                "Class \"{s}\" is not known. Known classes: economy, business, first"
+            )),
+        }
+    }
+}
+
+/// The great-circle distance in km between London Heathrow and New York JFK
+static LONDON_NEW_YORK_KM: f64 = 5540.0;
+
+/// The annual CO2 emissions in tons of a typical petrol passenger car
+/// (4.6 in "Greenhouse Gas Emissions from a Typical Passenger Vehicle" of the US EPA, 2023)
+pub static CAR_TONS_PER_YEAR: f64 = 4.6;
+
+/// CO2 emissions compared to an activity, e.g. driving a car for a year
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Equivalent {
+    /// The activity (e.g. `petrol cars driven for a year`)
+    pub activity: String,
+    /// The CO2 emissions in tons of one activity
+    pub tons_co2: f64,
+    /// The number of activities whose emissions equal the compared emissions
+    pub count: f64,
+    /// The source of the emissions of the activity, including its year
+    pub source: String,
+}
+
+/// CO2 emissions compared to several relatable activities (see [`equivalents`])
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Equivalents {
+    /// Return economy flights between London and New York
+    pub flights: Equivalent,
+    /// Petrol cars driven for a year
+    pub cars: Equivalent,
+}
+
+/// Returns `tons_co2` of CO2 emissions compared to relatable activities
+pub fn equivalents(tons_co2: f64) -> Equivalents {
+    let flight = Class::Economy.co2_kg(2.0 * LONDON_NEW_YORK_KM) / 1000.0;
+    Equivalents {
+        flights: Equivalent {
+            activity: "return economy flights between London and New York".to_string(),
+            tons_co2: flight,
+            count: tons_co2 / flight,
+            source: "UK government GHG conversion factors (2023)".to_string(),
+        },
+        cars: Equivalent {
+            activity: "petrol cars driven for a year".to_string(),
+            tons_co2: CAR_TONS_PER_YEAR,
+            count: tons_co2 / CAR_TONS_PER_YEAR,
+            source: "US EPA, Greenhouse Gas Emissions from a Typical Passenger Vehicle (2023)"
+                .to_string(),
+        },
+    }
+}
+
+/// The annual CO2 emissions per capita of a country on a year, as published by a source
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CitizenEmissions {
+    /// The country (in ISO 3166)
+    pub country: String,
+    pub year: i32,
+    /// The CO2 emissions per capita in tons
+    pub tons_co2: f64,
+    /// The source of the figure (e.g. an url)
+    pub source: String,
+}
+
+/// Returns the per capita emissions of `country` on `year` from `table`, falling back to
+/// the latest year available of `country` when `year` is not in `table`.
+pub fn citizen_emissions<'a>(
+    table: &'a [CitizenEmissions],
+    country: &str,
+    year: i32,
+) -> Option<&'a CitizenEmissions> {
+    let of_country = table.iter().filter(|x| x.country == country);
+    of_country
+        .clone()
+        .find(|x| x.year == year)
+        .or_else(|| of_country.max_by_key(|x| x.year))
 }
 
 #[cfg(test)]
@@ -20,4 +242,121 @@ mod test {
             5358.929228800001
         );
     }
+
+    #[test]
+    fn per_model() -> Result<(), Box<dyn std::error::Error>> {
+        let models = crate::model::load_private_jet_models()?;
+        let duration = time::Duration::hours(2);
+
+        let heavy = model_leg_co2_kg("GULFSTREAM G650", &models, duration);
+        let light = model_leg_co2_kg("CESSNA 510 Citation Mustang", &models, duration);
+        assert!(heavy > 5.0 * light);
+        assert_eq!(light, leg_co2_kg(90.0, duration));
+
+        let unknown = model_leg_co2_kg("UNKNOWN", &models, duration);
+        assert_eq!(unknown, leg_co2_kg(DEFAULT_GPH, duration));
+        Ok(())
+    }
+
+    #[test]
+    fn options() {
+        let co2 = 1000.0;
+        assert_eq!(EmissionsOptions::default().co2e_kg(co2), co2);
+        assert_eq!(EmissionsOptions::default().assumptions(), None);
+
+        let unchanged = EmissionsOptions {
+            rfi: Some(1.0),
+            well_to_wake: false,
+        };
+        assert_eq!(unchanged.co2e_kg(co2), co2);
+
+        let rfi = EmissionsOptions {
+            rfi: Some(2.7),
+            well_to_wake: false,
+        };
+        assert_eq!(rfi.co2e_kg(co2), 2700.0);
+        assert!(rfi.assumptions().unwrap().contains("2.7"));
+
+        let both = EmissionsOptions {
+            rfi: Some(2.7),
+            well_to_wake: true,
+        };
+        assert!((both.co2e_kg(co2) - 2910.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn classes() {
+        let distance = 1000.0;
+        let first = Class::First.co2_kg(distance);
+        let business = Class::Business.co2_kg(distance);
+        let economy = Class::Economy.co2_kg(distance);
+        assert!(first > business && business > economy);
+        assert_eq!(economy, 80.0);
+
+        assert_eq!("Business".parse::<Class>(), Ok(Class::Business));
+        assert_eq!(Class::First.to_string().parse::<Class>(), Ok(Class::First));
+        assert!("premium".parse::<Class>().is_err());
+    }
+
+    #[test]
+    fn citizen() {
+        let table = crate::csv::deserialize::<CitizenEmissions>(
+            b"country,year,tons_co2,source\nDK,2021,5.1,a\nDK,2022,4.9,a\nDK,2023,4.6,a\nPT,2022,4.0,a\n",
+        )
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+        assert_eq!(citizen_emissions(&table, "DK", 2022).unwrap().tons_co2, 4.9);
+        // falls back to the latest year
+        assert_eq!(citizen_emissions(&table, "DK", 2024).unwrap().year, 2023);
+        assert!(citizen_emissions(&table, "US", 2022).is_none());
+    }
+
+    #[test]
+    fn fuel_burn() -> Result<(), Box<dyn std::error::Error>> {
+        let models = crate::model::load_private_jet_models()?;
+        let model = "GULFSTREAM G650";
+        let leg = |minutes: i64| {
+            let position = |minutes: i64, altitude: Option<f64>| crate::Position {
+                datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+                latitude: 55.6,
+                longitude: 12.6,
+                altitude,
+            };
+            Leg::new(vec![
+                position(0, None),
+                position(minutes / 2, Some(30000.0)),
+                position(minutes, None),
+            ])
+        };
+
+        // a short hop: taxiing and climbing dominate
+        let hop = leg(10);
+        let cruise = fuel_kg(models[model].gph.into(), hop.duration())
+            - fuel_kg(models[model].gph.into(), time::Duration::ZERO);
+        let fuel = fuel_burn_kg(&hop, model, &models);
+        assert!(fuel > 2.0 * cruise);
+
+        // a long cruise: the penalty is marginal
+        let long = leg(8 * 60);
+        let fuel = fuel_burn_kg(&long, model, &models);
+        let cruise_co2 = leg_co2_kg(models[model].gph.into(), long.duration());
+        assert!(fuel_co2_kg(fuel) > cruise_co2);
+        assert!(fuel_co2_kg(fuel) < 1.03 * cruise_co2);
+
+        assert_eq!(
+            fuel_burn_kg(&long, "UNKNOWN", &models),
+            fuel_kg(DEFAULT_GPH, long.duration())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn equivalent() {
+        let equivalents = equivalents(1000.0);
+        assert!((equivalents.flights.tons_co2 - 0.8864).abs() < 1e-9);
+        assert!((equivalents.flights.count - 1128.2).abs() < 0.1);
+        assert!((equivalents.cars.count - 217.4).abs() < 0.1);
+        assert!(equivalents.cars.source.contains("EPA"));
+    }
 }
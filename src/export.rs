@@ -0,0 +1,1095 @@
+//! Contains the serialization of [`Report`]s to the different output formats.
+use std::{collections::BTreeMap, io::Write, path::Path, sync::Arc};
+
+use serde::Serialize;
+use serde_json::json;
+use time::Date;
+
+use crate::{
+    legs::Leg,
+    report::{AircraftLegs, Comparison, Fleet, Report, TimeZone},
+};
+
+/// The source of the positions from which reports are computed
+static SOURCE: &str = "https://globe.adsbexchange.com";
+
+/// The meters in a foot, to convert altitudes (in feet) to the unit of GPX and KML
+static METERS_PER_FOOT: f64 = 0.3048;
+
+/// The output formats, selected by the extension of the output path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `.md`: the report as a markdown document
+    Markdown,
+    /// `.json`: the report as JSON
+    Json,
+    /// `.csv`: the legs as CSV (see [`legs_to_csv`])
+    Csv,
+    /// `.geojson`: the legs as GeoJSON (see [`legs_to_geojson`])
+    GeoJson,
+    /// `.kml`: the legs as KML (see [`legs_to_kml`])
+    Kml,
+    /// `.gpx`: the legs as GPX (see [`legs_to_gpx`])
+    Gpx,
+}
+
+impl Format {
+    /// All supported formats
+    pub const ALL: [Format; 6] = [
+        Format::Markdown,
+        Format::Json,
+        Format::Csv,
+        Format::GeoJson,
+        Format::Kml,
+        Format::Gpx,
+    ];
+
+    /// The file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::GeoJson => "geojson",
+            Format::Kml => "kml",
+            Format::Gpx => "gpx",
+        }
+    }
+
+    /// Whether the format is of the legs (see [`export_legs`]) rather than of the report (see [`export`])
+    pub fn is_legs(&self) -> bool {
+        !matches!(self, Format::Markdown | Format::Json)
+    }
+
+    /// Returns the [`Format`] of `path` based on its extension.
+    /// # Error
+    /// Errors when the extension is not supported, listing the supported ones
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("");
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+            .ok_or_else(|| {
+                let supported = Self::ALL.map(|format| format.extension()).join(", ");
+                format!("Extension \"{extension}\" of {path:?} is not supported. Supported extensions: {supported}")
+            })
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    /// Returns the [`Format`] of its name (e.g. `markdown`) or extension (e.g. `md`), case insensitive
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "markdown" => Ok(Format::Markdown),
+            extension => Self::ALL
+                .into_iter()
+                .find(|format| format.extension() == extension)
+                .ok_or_else(|| {
+                    let supported = Self::ALL.map(|format| format.extension()).join(", ");
+                    format!(
+                        "Format \"{s}\" is not supported. Supported formats: markdown, {supported}"
+                    )
+                }),
+        }
+    }
+}
+
+fn to_markdown(report: &Report) -> String {
+    let mut md = "# Private jets\n\n".to_string();
+    md.push_str(&format!("* Private jets: {}\n", report.private_jets));
+    md.push_str(&format!("* Legs: {}\n", report.legs));
+    md.push_str(&format!("* Distance: {:.0} km\n", report.distance));
+    md.push_str(&format!("* Flight hours: {:.0}\n", report.flight_hours));
+    md.push_str(&format!(
+        "* CO2 emissions: {:.1} tons\n",
+        report.co2_emissions / 1000.0
+    ));
+    md.push_str(&format!(
+        "  * of passenger legs: {:.1} tons\n",
+        report.passenger_co2 / 1000.0
+    ));
+    md.push_str(&format!(
+        "  * of positioning legs: {:.1} tons\n",
+        report.positioning_co2 / 1000.0
+    ));
+    if let Some(co2e) = &report.co2e {
+        md.push_str(&format!(
+            "* CO2e emissions: {:.1} tons (assuming {})\n",
+            co2e.co2e_emissions / 1000.0,
+            co2e.source
+        ));
+    }
+    if let Some(commercial) = &report.commercial {
+        md.push_str(&format!(
+            "* A passenger flying the same legs commercially in {} class would have emitted {:.1} tons of CO2 (assuming {})\n",
+            commercial.class,
+            commercial.co2_emissions / 1000.0,
+            commercial.source
+        ));
+    }
+    if let Some(citizen) = &report.citizen_emissions {
+        md.push_str(&format!(
+            "* Equivalent to the annual CO2 emissions of {:.0} citizens of {} in {} (source: {})\n",
+            citizen.citizens, citizen.country, citizen.year, citizen.source
+        ));
+    }
+    if let Some(equivalents) = &report.equivalents {
+        for equivalent in [&equivalents.flights, &equivalents.cars] {
+            md.push_str(&format!(
+                "* Equivalent to the CO2 emissions of {:.0} {} (source: {})\n",
+                equivalent.count, equivalent.activity, equivalent.source
+            ));
+        }
+    }
+    if let Some(short) = &report.short_legs {
+        md.push_str(&format!(
+            "* Legs shorter than {:.0} km: {} ({:.0}% of all legs), emitting {:.1} tons of CO2\n",
+            short.threshold,
+            short.legs,
+            short.fraction * 100.0,
+            short.co2_emissions / 1000.0
+        ));
+    }
+    if let Some(leg) = &report.longest_leg {
+        md.push_str(&format!(
+            "* Longest leg: {} ({}) from {} to {}, {:.0} km and {:.1} tons of CO2\n",
+            leg.tail_number,
+            leg.icao_number,
+            leg.from,
+            leg.to,
+            leg.distance,
+            leg.co2_emissions / 1000.0
+        ));
+    }
+    md.push_str(&format!(
+        "* CO2 emissions avoidable by consolidating legs: {:.1} tons\n",
+        report.consolidation_savings / 1000.0
+    ));
+    if let Some(breakdown) = &report.foreign_countries {
+        md.push_str("\n## Foreign countries\n\n");
+        md.push_str("| Country | Legs to | Legs from | CO2 (tons) |\n");
+        md.push_str("|---|---|---|---|\n");
+        for country in breakdown.ranking.iter().take(10) {
+            md.push_str(&format!(
+                "| {} | {} | {} | {:.1} |\n",
+                country.country,
+                country.legs_to,
+                country.legs_from,
+                country.co2_emissions / 1000.0
+            ));
+        }
+        md.push_str(&format!(
+            "\n{} legs were excluded because their country could not be resolved.\n",
+            breakdown.unresolved_legs
+        ));
+    }
+    if let Some(changes) = &report.activity_changes {
+        let list = |ids: &[crate::report::AircraftId]| {
+            ids.iter()
+                .map(|id| format!("{} ({})", id.tail_number, id.icao_number))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        md.push_str("\n## Changes since the previous period\n\n");
+        md.push_str(&format!(
+            "* Newly active ({}): {}\n",
+            changes.newly_active.len(),
+            list(&changes.newly_active)
+        ));
+        md.push_str(&format!(
+            "* Newly dormant ({}): {}\n",
+            changes.newly_dormant.len(),
+            list(&changes.newly_dormant)
+        ));
+    }
+    if let Some(departures) = &report.departures {
+        let time_zone = match departures.time_zone {
+            TimeZone::Offset(_) => format!("UTC{}", departures.time_zone),
+            TimeZone::Iana(_) => departures.time_zone.to_string(),
+        };
+        md.push_str(&format!("\n## Departures ({time_zone})\n\n"));
+        md.push_str("| Mon | Tue | Wed | Thu | Fri | Sat | Sun |\n");
+        md.push_str("|---|---|---|---|---|---|---|\n");
+        let row = departures.by_weekday.map(|count| count.to_string());
+        md.push_str(&format!("| {} |\n\n", row.join(" | ")));
+        md.push_str("| 00-04 | 04-08 | 08-12 | 12-16 | 16-20 | 20-24 |\n");
+        md.push_str("|---|---|---|---|---|---|\n");
+        let row = departures
+            .by_hour
+            .chunks(4)
+            .map(|hours| hours.iter().sum::<usize>().to_string())
+            .collect::<Vec<_>>();
+        md.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    if let Some(bands) = &report.distance_bands {
+        md.push_str("\n## Emissions by distance\n\n");
+        md.push_str("| Distance | Legs | CO2 (tons) |\n|---|---|---|\n");
+        for band in bands {
+            let distance = match band.to {
+                Some(to) if band.from == 0.0 => format!("< {to:.0} km"),
+                Some(to) => format!("{:.0} - {to:.0} km", band.from),
+                None => format!(">= {:.0} km", band.from),
+            };
+            md.push_str(&format!(
+                "| {distance} | {} | {:.1} |\n",
+                band.legs,
+                band.co2_emissions / 1000.0
+            ));
+        }
+    }
+    if !report.ranking.is_empty() {
+        md.push_str("\n## Largest emitters\n\n");
+        md.push_str(
+            "| Tail number | Legs | Flight hours | CO2 (tons) | CO2 per flight hour (kg) |\n",
+        );
+        md.push_str("|---|---|---|---|---|\n");
+        for aircraft in &report.ranking {
+            let per_hour = aircraft
+                .kg_co2_per_flight_hour
+                .map(|x| format!("{x:.0}"))
+                .unwrap_or_else(|| "-".to_string());
+            md.push_str(&format!(
+                "| {} | {} | {:.1} | {:.1} | {per_hour} |\n",
+                aircraft.tail_number,
+                aircraft.legs,
+                aircraft.flight_hours,
+                aircraft.co2_emissions / 1000.0
+            ));
+        }
+    }
+    md
+}
+
+/// Returns the name, value and unit of each numeric fact of `report`
+fn facts(report: &Report) -> Vec<(&'static str, f64, &'static str)> {
+    let mut facts = vec![
+        ("Private jets", report.private_jets as f64, "aircraft"),
+        ("Legs", report.legs as f64, "legs"),
+        ("Distance", report.distance, "km"),
+        ("CO2 emissions", report.co2_emissions, "kg"),
+        (
+            "CO2 emissions of passenger legs",
+            report.passenger_co2,
+            "kg",
+        ),
+        (
+            "CO2 emissions of positioning legs",
+            report.positioning_co2,
+            "kg",
+        ),
+        ("Flight hours", report.flight_hours, "hours"),
+    ];
+    if let Some(commercial) = &report.commercial {
+        facts.push((
+            "CO2 emissions of a commercial passenger",
+            commercial.co2_emissions,
+            "kg",
+        ));
+    }
+    if let Some(co2e) = &report.co2e {
+        facts.push(("CO2e emissions", co2e.co2e_emissions, "kg"));
+    }
+    if let Some(short) = &report.short_legs {
+        facts.push(("Short legs", short.legs as f64, "legs"));
+    }
+    if let Some(leg) = &report.longest_leg {
+        facts.push(("Longest leg distance", leg.distance, "km"));
+        facts.push(("Longest leg CO2 emissions", leg.co2_emissions, "kg"));
+    }
+    facts.push((
+        "CO2 emissions avoidable by consolidating legs",
+        report.consolidation_savings,
+        "kg",
+    ));
+    facts
+}
+
+/// Returns `report` of the period between `from` (inclusive) and `to` (exclusive) as a
+/// [schema.org](https://schema.org) [`Dataset`](https://schema.org/Dataset) in JSON-LD, with one
+/// [`Claim`](https://schema.org/Claim) per numeric fact, each based on the source of the positions.
+pub fn to_jsonld(report: &Report, from: Date, to: Date) -> Result<Vec<u8>, std::io::Error> {
+    let period = format!("{from}/{to}");
+    let claims = facts(report)
+        .into_iter()
+        .map(|(name, value, unit)| {
+            json!({
+                "@type": "Claim",
+                "name": name,
+                "text": format!("{name} between {from} and {to}: {value:.1} {unit}"),
+                "temporalCoverage": period,
+                "isBasedOn": SOURCE,
+                "about": {
+                    "@type": "PropertyValue",
+                    "name": name,
+                    "value": value,
+                    "unitText": unit,
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    let dataset = json!({
+        "@context": "https://schema.org",
+        "@type": "Dataset",
+        "name": "Private jets",
+        "temporalCoverage": period,
+        "isBasedOn": SOURCE,
+        "hasPart": claims,
+    });
+    Ok(serde_json::to_vec_pretty(&dataset)?)
+}
+
+/// Returns `legs` as a GeoJSON `FeatureCollection` with one `LineString` feature per leg through
+/// all its positions, with the icao number, distance (in km) and duration (in seconds) of the leg as properties.
+/// # Implementation
+/// Coordinates are `[longitude, latitude]`, as mandated by GeoJSON (RFC 7946).
+/// Features are ordered by icao number and start of the leg.
+pub fn legs_to_geojson(legs: &AircraftLegs) -> serde_json::Value {
+    let legs = sorted_legs(legs);
+
+    let features = legs
+        .into_iter()
+        .map(|(icao_number, leg)| {
+            let coordinates = leg
+                .positions()
+                .iter()
+                .map(|position| [position.longitude(), position.latitude()])
+                .collect::<Vec<_>>();
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": coordinates,
+                },
+                "properties": {
+                    "icao_number": icao_number.as_ref(),
+                    "distance_km": leg.distance(),
+                    "duration_s": leg.duration().whole_seconds(),
+                },
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+/// Returns `legs` as a GPX 1.1 document with one track (`<trk>`) named after the icao number per leg,
+/// and one track point (`<trkpt>`) per position of the leg.
+/// # Implementation
+/// The elevation of a track point is the altitude of its position in meters (GPX's unit), and is omitted
+/// when the aircraft is on the ground. Tracks are ordered by icao number and start of the leg.
+pub fn legs_to_gpx(legs: &AircraftLegs) -> String {
+    let legs = sorted_legs(legs);
+
+    let mut gpx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="flights" xmlns="http://www.topografix.com/GPX/1/1">
+"#
+    .to_string();
+    for (icao_number, leg) in legs {
+        gpx.push_str(&format!(
+            "  <trk>\n    <name>{}</name>\n    <trkseg>\n",
+            escape(icao_number)
+        ));
+        for position in leg.positions() {
+            gpx.push_str(&format!(
+                r#"      <trkpt lat="{}" lon="{}">"#,
+                position.latitude(),
+                position.longitude()
+            ));
+            if let Some(altitude) = position.altitude() {
+                gpx.push_str(&format!("<ele>{:.1}</ele>", altitude * METERS_PER_FOOT));
+            }
+            let time = position
+                .datetime()
+                .format(&time::format_description::well_known::Rfc3339)
+                .expect("all datetimes to be formattable as RFC3339");
+            gpx.push_str(&format!("<time>{time}</time></trkpt>\n"));
+        }
+        gpx.push_str("    </trkseg>\n  </trk>\n");
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+/// Returns `legs` as a KML 2.2 document with one placemark (`<Placemark>`) named after the icao number per leg,
+/// whose path (`<LineString>`) has one coordinate per position of the leg, extruded to the ground.
+/// # Implementation
+/// Coordinates are at the altitude of their position in meters (`altitudeMode` `absolute`); positions
+/// on the ground are clamped to the ground (altitude `0`). Each leg is colored from a fixed palette, so that
+/// consecutive legs are distinguishable. Placemarks are ordered by icao number and start of the leg.
+pub fn legs_to_kml(legs: &AircraftLegs) -> String {
+    // KML colors are `aabbggrr`
+    static COLORS: [&str; 4] = ["ff0000ff", "ffff0000", "ff00a5ff", "ff800080"];
+
+    let legs = sorted_legs(legs);
+
+    let mut kml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+  <Document>
+"#
+    .to_string();
+    for (index, (icao_number, leg)) in legs.into_iter().enumerate() {
+        kml.push_str(&format!(
+            "    <Placemark>\n      <name>{}</name>\n",
+            escape(icao_number)
+        ));
+        kml.push_str(&format!(
+            "      <Style><LineStyle><color>{}</color><width>2</width></LineStyle></Style>\n",
+            COLORS[index % COLORS.len()]
+        ));
+        kml.push_str("      <LineString>\n        <extrude>1</extrude>\n        <altitudeMode>absolute</altitudeMode>\n        <coordinates>");
+        let coordinates = leg
+            .positions()
+            .iter()
+            .map(|position| {
+                let altitude = position.altitude().unwrap_or(0.0) * METERS_PER_FOOT;
+                format!(
+                    "{},{},{altitude:.1}",
+                    position.longitude(),
+                    position.latitude()
+                )
+            })
+            .collect::<Vec<_>>();
+        kml.push_str(&coordinates.join(" "));
+        kml.push_str("</coordinates>\n      </LineString>\n    </Placemark>\n");
+    }
+    kml.push_str("  </Document>\n</kml>\n");
+    kml
+}
+
+/// Returns the legs of `legs` with their icao number, ordered by icao number and start of the leg
+fn sorted_legs(legs: &AircraftLegs) -> Vec<(&Arc<str>, &Leg)> {
+    let mut legs = legs
+        .iter()
+        .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+        .collect::<Vec<_>>();
+    legs.sort_unstable_by_key(|(icao_number, leg)| (*icao_number, leg.start()));
+    legs
+}
+
+/// Returns `text` with the characters reserved in XML escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A row of [`legs_to_csv`]
+#[derive(Serialize)]
+struct LegRow<'a> {
+    icao: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    start_time: time::OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    end_time: time::OffsetDateTime,
+    from_lat: f64,
+    from_lon: f64,
+    to_lat: f64,
+    to_lon: f64,
+    distance_km: f64,
+    duration_min: f64,
+    emissions_tons: f64,
+    fuel_kg: f64,
+    fuel_emissions_tons: f64,
+}
+
+/// Writes one CSV row per leg of `legs` to `writer`, with columns
+/// `icao,start_time,end_time,from_lat,from_lon,to_lat,to_lon,distance_km,duration_min,emissions_tons,fuel_kg,fuel_emissions_tons`.
+/// Emissions are computed from the models of the private jets in `fleet`; `fuel_kg` and the emissions of
+/// combusting it also account for taxiing and climbing (see [`crate::emissions::fuel_kg`]).
+/// Rows are ordered by icao number and start of the leg.
+/// # Error
+/// Errors when `writer` errors
+pub fn legs_to_csv<W: Write>(
+    writer: W,
+    fleet: &Fleet,
+    legs: &AircraftLegs,
+) -> Result<(), std::io::Error> {
+    let legs = sorted_legs(legs);
+
+    let mut writer = csv::Writer::from_writer(writer);
+    for (icao_number, leg) in legs {
+        let fuel_kg = fleet.leg_fuel_kg(icao_number, leg);
+        writer.serialize(LegRow {
+            icao: icao_number,
+            start_time: leg.start(),
+            end_time: leg.end(),
+            from_lat: leg.from().latitude(),
+            from_lon: leg.from().longitude(),
+            to_lat: leg.to().latitude(),
+            to_lon: leg.to().longitude(),
+            distance_km: leg.distance(),
+            duration_min: leg.duration().as_seconds_f64() / 60.0,
+            emissions_tons: fleet.leg_co2_kg(icao_number, leg) / 1000.0,
+            fuel_kg,
+            fuel_emissions_tons: crate::emissions::fuel_co2_kg(fuel_kg) / 1000.0,
+        })?;
+    }
+    writer.flush()
+}
+
+/// Writes one CSV row per month of `monthly` (see [`crate::report::monthly_emissions`]) to `writer`,
+/// with columns `month,co2_emissions_tons` and months as `yyyy-mm`, ordered by month.
+/// # Error
+/// Errors when `writer` errors
+pub fn monthly_emissions_to_csv<W: Write>(
+    mut writer: W,
+    monthly: &BTreeMap<(i32, time::Month), f64>,
+) -> Result<(), std::io::Error> {
+    writeln!(writer, "month,co2_emissions_tons")?;
+    for ((year, month), kg) in monthly {
+        writeln!(writer, "{year}-{:02},{}", *month as u8, kg / 1000.0)?;
+    }
+    writer.flush()
+}
+
+/// Returns the markdown of `comparison`: a table with the facts of each country side by side,
+/// followed by the report of each country (see [`export`])
+fn comparison_to_markdown(comparison: &Comparison) -> String {
+    let countries = comparison
+        .countries
+        .iter()
+        .map(|country| country.country.as_str())
+        .collect::<Vec<_>>();
+    let mut md = format!("# Private jets of {}\n\n", countries.join(" and "));
+    md.push_str(&format!("| | {} |\n", countries.join(" | ")));
+    md.push_str(&format!("|---|{}\n", "---|".repeat(countries.len())));
+    let row = |name: &str, value: &dyn Fn(&Report) -> String| {
+        let values = comparison
+            .countries
+            .iter()
+            .map(|country| value(&country.report))
+            .collect::<Vec<_>>();
+        format!("| {name} | {} |\n", values.join(" | "))
+    };
+    md.push_str(&row("Private jets", &|r| r.private_jets.to_string()));
+    md.push_str(&row("Legs", &|r| r.legs.to_string()));
+    md.push_str(&row("Distance (km)", &|r| format!("{:.0}", r.distance)));
+    md.push_str(&row("Flight hours", &|r| format!("{:.0}", r.flight_hours)));
+    md.push_str(&row("CO2 emissions (tons)", &|r| {
+        format!("{:.1}", r.co2_emissions / 1000.0)
+    }));
+    md.push_str(&row("Citizen-years of CO2", &|r| {
+        r.citizen_emissions
+            .as_ref()
+            .map(|citizen| format!("{:.0}", citizen.citizens))
+            .unwrap_or_else(|| "-".to_string())
+    }));
+
+    for country in &comparison.countries {
+        md.push_str(&format!("\n## {}\n\n", country.country));
+        // without its title, and with its headings nested under the country
+        let report = to_markdown(&country.report);
+        let lines = report
+            .lines()
+            .skip_while(|line| line.starts_with("# ") || line.is_empty());
+        for line in lines {
+            if line.starts_with('#') {
+                md.push('#');
+            }
+            md.push_str(line);
+            md.push('\n');
+        }
+    }
+    md
+}
+
+/// Returns `comparison` serialized in `format`
+/// # Error
+/// Errors when `format` is of legs (see [`Format::is_legs`])
+pub fn export_comparison(
+    format: Format,
+    comparison: &Comparison,
+) -> Result<Vec<u8>, std::io::Error> {
+    match format {
+        Format::Markdown => Ok(comparison_to_markdown(comparison).into_bytes()),
+        Format::Json => Ok(serde_json::to_vec_pretty(comparison)?),
+        format => Err(not_a_report(format)),
+    }
+}
+
+fn not_a_report(format: Format) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!(
+            "Format \"{}\" is of legs, not of reports. Supported formats: md, json",
+            format.extension()
+        ),
+    )
+}
+
+/// Returns `report` serialized in `format`
+/// # Error
+/// Errors when `format` is of legs (see [`Format::is_legs`] and [`export_legs`])
+pub fn export(format: Format, report: &Report) -> Result<Vec<u8>, std::io::Error> {
+    match format {
+        Format::Markdown => Ok(to_markdown(report).into_bytes()),
+        Format::Json => Ok(serde_json::to_vec_pretty(report)?),
+        format => Err(not_a_report(format)),
+    }
+}
+
+/// Returns `legs` of the private jets of `fleet` serialized in `format`
+/// # Error
+/// Errors when `format` is of reports (see [`Format::is_legs`] and [`export`])
+pub fn export_legs(
+    format: Format,
+    fleet: &Fleet,
+    legs: &AircraftLegs,
+) -> Result<Vec<u8>, std::io::Error> {
+    match format {
+        Format::Csv => {
+            let mut data = vec![];
+            legs_to_csv(&mut data, fleet, legs)?;
+            Ok(data)
+        }
+        Format::GeoJson => Ok(serde_json::to_vec_pretty(&legs_to_geojson(legs))?),
+        Format::Kml => Ok(legs_to_kml(legs).into_bytes()),
+        Format::Gpx => Ok(legs_to_gpx(legs).into_bytes()),
+        format => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Format \"{}\" is of reports, not of legs. Supported formats: csv, geojson, kml, gpx",
+                format.extension()
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{CitizenFact, CountryReport};
+
+    fn report() -> Report {
+        Report {
+            private_jets: 1,
+            legs: 2,
+            distance: 300.0,
+            flight_hours: 1.5,
+            co2_emissions: 2000.0,
+            passenger_co2: 1500.0,
+            positioning_co2: 500.0,
+            longest_leg: None,
+            consolidation_savings: 0.0,
+            short_legs: None,
+            distance_bands: None,
+            ranking: vec![],
+            foreign_countries: None,
+            activity_changes: None,
+            citizen_emissions: None,
+            co2e: None,
+            equivalents: None,
+            commercial: None,
+            departures: None,
+        }
+    }
+
+    fn legs() -> AircraftLegs {
+        let positions = [(0, 55.6, 12.6), (1800, 56.2, 10.2), (3600, 57.1, 9.9)]
+            .into_iter()
+            .map(|(t, latitude, longitude)| crate::Position {
+                datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+                latitude,
+                longitude,
+                altitude: (t != 0).then_some(30000.0),
+            })
+            .collect();
+        AircraftLegs::from([("45860d".into(), vec![crate::legs::Leg::new(positions)])])
+    }
+
+    /// A [`Fleet`] of `icao_numbers`, all of a model burning 500 gallons per hour
+    fn fleet(icao_numbers: &[&str]) -> Fleet {
+        let model = std::sync::Arc::new(crate::model::AircraftModel {
+            model: "GULFSTREAM G650".to_string(),
+            gph: 500,
+            source: "".to_string(),
+            date: "".to_string(),
+        });
+        icao_numbers
+            .iter()
+            .copied()
+            .map(|icao_number| {
+                let aircraft = crate::aircraft::Aircraft {
+                    icao_number: icao_number.into(),
+                    tail_number: "OY-GFS".to_string(),
+                    type_designator: "GLF6".to_string(),
+                    model: model.model.clone(),
+                    country: None,
+                    military: false,
+                    owner: None,
+                };
+                (icao_number.into(), (aircraft.into(), model.clone()))
+            })
+            .collect::<crate::PrivateJets>()
+            .into()
+    }
+
+    #[test]
+    fn csv() {
+        let mut legs = legs();
+        legs.insert("4596b2".into(), legs["45860d"].clone());
+        let fleet = fleet(&["45860d", "4596b2"]);
+
+        let mut data = vec![];
+        legs_to_csv(&mut data, &fleet, &legs).unwrap();
+
+        let mut reader = ::csv::Reader::from_reader(data.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "icao",
+                "start_time",
+                "end_time",
+                "from_lat",
+                "from_lon",
+                "to_lat",
+                "to_lon",
+                "distance_km",
+                "duration_min",
+                "emissions_tons",
+                "fuel_kg",
+                "fuel_emissions_tons"
+            ]
+        );
+        let rows = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(&rows[0][0], "45860d");
+        assert_eq!(&rows[1][0], "4596b2");
+        assert_eq!(&rows[0][1], "1970-01-01T00:00:00Z");
+        assert_eq!(&rows[0][2], "1970-01-01T01:00:00Z");
+        assert_eq!(&rows[0][3], "55.6");
+        assert_eq!(&rows[0][6], "9.9");
+        assert_eq!(&rows[0][8], "60.0");
+        let tons: f64 = rows[0][9].parse().unwrap();
+        assert_eq!(
+            tons,
+            crate::emissions::leg_co2_kg(500.0, time::Duration::hours(1)) / 1000.0
+        );
+        let fuel: f64 = rows[0][10].parse().unwrap();
+        assert_eq!(
+            fuel,
+            crate::emissions::fuel_kg(500.0, time::Duration::hours(1))
+        );
+    }
+
+    #[test]
+    fn gpx() {
+        let gpx = legs_to_gpx(&legs());
+
+        let mut depth = 0i32;
+        let mut points = 0;
+        let mut elevations = 0;
+        for token in xmlparser::Tokenizer::from(gpx.as_str()) {
+            match token.unwrap() {
+                xmlparser::Token::ElementStart { local, .. } => {
+                    depth += 1;
+                    points += (local.as_str() == "trkpt") as usize;
+                    elevations += (local.as_str() == "ele") as usize;
+                }
+                xmlparser::Token::ElementEnd { end, .. } => match end {
+                    xmlparser::ElementEnd::Open => {}
+                    _ => depth -= 1,
+                },
+                _ => {}
+            }
+        }
+        assert_eq!(depth, 0);
+        assert_eq!(points, 3);
+        // the first position is on the ground
+        assert_eq!(elevations, 2);
+        assert!(gpx.contains(r#"<trkpt lat="55.6" lon="12.6"><time>1970-01-01T00:00:00Z</time>"#));
+        assert!(gpx.contains("<ele>9144.0</ele>"));
+    }
+
+    #[test]
+    fn comparison() {
+        let denmark = report();
+        let sweden = Report {
+            private_jets: 3,
+            legs: 7,
+            co2_emissions: 9000.0,
+            citizen_emissions: Some(CitizenFact {
+                country: "Sweden".to_string(),
+                year: 2022,
+                tons_co2_per_capita: 3.6,
+                citizens: 2.5,
+                source: "source (2022)".to_string(),
+            }),
+            ..report()
+        };
+        let comparison = Comparison {
+            countries: vec![
+                CountryReport {
+                    country: "Denmark".to_string(),
+                    report: denmark.clone(),
+                },
+                CountryReport {
+                    country: "Sweden".to_string(),
+                    report: sweden.clone(),
+                },
+            ],
+        };
+
+        let md =
+            String::from_utf8(export_comparison(Format::Markdown, &comparison).unwrap()).unwrap();
+        assert!(md.starts_with("# Private jets of Denmark and Sweden\n"));
+        assert!(md.contains("| | Denmark | Sweden |\n|---|---|---|\n"));
+        assert!(md.contains("| Private jets | 1 | 3 |\n"));
+        assert!(md.contains("| Legs | 2 | 7 |\n"));
+        assert!(md.contains("| Flight hours | 2 | 2 |\n"));
+        assert!(md.contains("| CO2 emissions (tons) | 2.0 | 9.0 |\n"));
+        assert!(md.contains("| Citizen-years of CO2 | - | 2 |\n"));
+        // followed by the report of each country
+        let (_, sections) = md.split_once("\n## Denmark\n").unwrap();
+        let (denmark_md, sweden_md) = sections.split_once("\n## Sweden\n").unwrap();
+        assert!(denmark_md.contains("* Legs: 2\n"));
+        assert!(denmark_md.contains("* Flight hours: 2\n"));
+        assert!(sweden_md.contains("* Legs: 7\n"));
+        assert_eq!(md.matches("\n# ").count(), 0);
+
+        let json = export_comparison(Format::Json, &comparison).unwrap();
+        let json: Comparison = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json, comparison);
+    }
+
+    #[test]
+    fn distance_bands() {
+        let band = |from, to, legs, co2_emissions| crate::report::DistanceBand {
+            from,
+            to,
+            legs,
+            co2_emissions,
+        };
+        let banded = Report {
+            distance_bands: Some(vec![
+                band(0.0, Some(200.0), 3, 1500.0),
+                band(200.0, Some(500.0), 0, 0.0),
+                band(500.0, None, 1, 4000.0),
+            ]),
+            ..report()
+        };
+        let md = to_markdown(&banded);
+        assert!(md.contains(
+            "| Distance | Legs | CO2 (tons) |\n|---|---|---|\n| < 200 km | 3 | 1.5 |\n| 200 - 500 km | 0 | 0.0 |\n| >= 500 km | 1 | 4.0 |\n"
+        ));
+        assert!(!to_markdown(&report()).contains("Emissions by distance"));
+    }
+
+    #[test]
+    fn monthly_csv() {
+        let monthly = BTreeMap::from([
+            ((2023, time::Month::December), 1500.0),
+            ((2024, time::Month::January), 250.0),
+        ]);
+        let mut csv = vec![];
+        monthly_emissions_to_csv(&mut csv, &monthly).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "month,co2_emissions_tons\n2023-12,1.5\n2024-01,0.25\n"
+        );
+    }
+
+    #[test]
+    fn kml() {
+        let kml = legs_to_kml(&legs());
+
+        let mut depth = 0i32;
+        let mut placemarks = 0;
+        let mut coordinates = vec![];
+        let mut in_coordinates = false;
+        for token in xmlparser::Tokenizer::from(kml.as_str()) {
+            match token.unwrap() {
+                xmlparser::Token::ElementStart { local, .. } => {
+                    depth += 1;
+                    placemarks += (local.as_str() == "Placemark") as usize;
+                    in_coordinates = local.as_str() == "coordinates";
+                }
+                xmlparser::Token::ElementEnd { end, .. } => match end {
+                    xmlparser::ElementEnd::Open => {}
+                    _ => depth -= 1,
+                },
+                xmlparser::Token::Text { text } if in_coordinates => {
+                    coordinates.extend(text.as_str().split(' ').map(str::to_string));
+                    in_coordinates = false;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(depth, 0);
+        assert_eq!(placemarks, 1);
+        assert!(kml.contains("<altitudeMode>absolute</altitudeMode>"));
+        // the first position is on the ground; the others fly at 30000 feet
+        assert_eq!(coordinates.len(), 3);
+        assert_eq!(coordinates[0], "12.6,55.6,0.0");
+        assert!(coordinates[1..].iter().all(|c| c.ends_with(",9144.0")));
+    }
+
+    #[test]
+    fn geojson() {
+        let legs = legs();
+
+        let geojson = legs_to_geojson(&legs);
+        // round-trips as JSON
+        let geojson: serde_json::Value =
+            serde_json::from_slice(&serde_json::to_vec(&geojson).unwrap()).unwrap();
+
+        assert_eq!(geojson["type"], "FeatureCollection");
+        let feature = &geojson["features"][0];
+        assert_eq!(feature["type"], "Feature");
+        assert_eq!(feature["geometry"]["type"], "LineString");
+        // [longitude, latitude]
+        assert_eq!(feature["geometry"]["coordinates"][0], json!([12.6, 55.6]));
+        assert_eq!(feature["geometry"]["coordinates"][2], json!([9.9, 57.1]));
+        assert_eq!(feature["properties"]["icao_number"], "45860d");
+        assert_eq!(feature["properties"]["duration_s"], 3600);
+    }
+
+    #[test]
+    fn from_path() {
+        assert_eq!(
+            Format::from_path(Path::new("a/story.md")),
+            Ok(Format::Markdown)
+        );
+        assert_eq!(Format::from_path(Path::new("a.JSON")), Ok(Format::Json));
+        assert_eq!(
+            Format::from_path(Path::new("a/legs.geojson")),
+            Ok(Format::GeoJson)
+        );
+        let error = Format::from_path(Path::new("a.txt")).unwrap_err();
+        assert!(error.contains("md, json, csv, geojson, kml, gpx"));
+        assert!(Format::from_path(Path::new("a")).is_err());
+    }
+
+    #[test]
+    fn jsonld() {
+        let (from, to) = (
+            time::macros::date!(2024 - 01 - 01),
+            time::macros::date!(2024 - 02 - 01),
+        );
+        let jsonld = to_jsonld(&report(), from, to).unwrap();
+        let jsonld = serde_json::from_slice::<serde_json::Value>(&jsonld).unwrap();
+
+        assert_eq!(jsonld["@type"], "Dataset");
+        let claims = jsonld["hasPart"].as_array().unwrap();
+        assert_eq!(claims.len(), facts(&report()).len());
+        assert!(claims
+            .iter()
+            .all(|claim| claim["@type"] == "Claim" && claim["isBasedOn"] == SOURCE));
+        assert_eq!(claims[3]["about"]["value"], 2000.0);
+        assert_eq!(claims[6]["about"]["value"], 1.5);
+    }
+
+    #[test]
+    fn leaderboard() {
+        let aircraft = |tail_number: &str, legs, co2_emissions| crate::report::AircraftFact {
+            icao_number: tail_number.into(),
+            tail_number: tail_number.to_string(),
+            legs,
+            flight_hours: 1.0,
+            co2_emissions,
+            kg_co2_per_flight_hour: Some(co2_emissions),
+        };
+        let report = Report {
+            ranking: vec![
+                aircraft("OY-A", 3, 9000.0),
+                aircraft("OY-B", 1, 5000.0),
+                aircraft("OY-C", 2, 1000.0),
+            ],
+            ..report()
+        };
+        let md = to_markdown(&report);
+        let rows = md
+            .lines()
+            .filter(|line| line.starts_with("| OY-"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                "| OY-A | 3 | 1.0 | 9.0 | 9000 |",
+                "| OY-B | 1 | 1.0 | 5.0 | 5000 |",
+                "| OY-C | 2 | 1.0 | 1.0 | 1000 |"
+            ]
+        );
+    }
+
+    #[test]
+    fn departures() {
+        let mut by_hour = [0; 24];
+        by_hour[8] = 2;
+        by_hour[13] = 1;
+        let report = Report {
+            departures: Some(crate::report::DepartureStats {
+                time_zone: TimeZone::Offset(time::macros::offset!(+1)),
+                by_hour,
+                by_weekday: [2, 0, 1, 0, 0, 0, 0],
+            }),
+            ..report()
+        };
+        let md = to_markdown(&report);
+        assert!(md.contains("## Departures (UTC+01:00)"));
+        assert!(md.contains("| 2 | 0 | 1 | 0 | 0 | 0 | 0 |"));
+        assert!(md.contains("| 0 | 0 | 2 | 1 | 0 | 0 |"));
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("markdown".parse::<Format>(), Ok(Format::Markdown));
+        assert_eq!("MD".parse::<Format>(), Ok(Format::Markdown));
+        assert_eq!("json".parse::<Format>(), Ok(Format::Json));
+        assert_eq!("KML".parse::<Format>(), Ok(Format::Kml));
+        assert!("html".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn dispatch() {
+        let md = export(Format::from_path(Path::new("a.md")).unwrap(), &report()).unwrap();
+        assert!(String::from_utf8(md).unwrap().starts_with("# Private jets"));
+
+        let json = export(Format::from_path(Path::new("a.json")).unwrap(), &report()).unwrap();
+        assert_eq!(serde_json::from_slice::<Report>(&json).unwrap(), report());
+
+        // formats of legs are not formats of reports
+        let error = export(Format::GeoJson, &report()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    /// Returns the legs of [`legs`] exported to a file with extension `extension`
+    fn export_to(extension: &str) -> String {
+        let format = Format::from_path(Path::new(&format!("a.{extension}"))).unwrap();
+        assert!(format.is_legs());
+        let data = export_legs(format, &fleet(&["45860d"]), &legs()).unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn dispatch_csv() {
+        let mut expected = vec![];
+        legs_to_csv(&mut expected, &fleet(&["45860d"]), &legs()).unwrap();
+        assert_eq!(export_to("csv").into_bytes(), expected);
+    }
+
+    #[test]
+    fn dispatch_geojson() {
+        let geojson = serde_json::from_str::<serde_json::Value>(&export_to("geojson")).unwrap();
+        assert_eq!(geojson, legs_to_geojson(&legs()));
+    }
+
+    #[test]
+    fn dispatch_kml() {
+        assert_eq!(export_to("kml"), legs_to_kml(&legs()));
+    }
+
+    #[test]
+    fn dispatch_gpx() {
+        assert_eq!(export_to("gpx"), legs_to_gpx(&legs()));
+
+        // formats of reports are not formats of legs
+        let error = export_legs(Format::Json, &fleet(&["45860d"]), &legs()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
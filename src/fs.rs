@@ -1,8 +1,16 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
 use async_trait::async_trait;
 
-static ROOT: &'static str = "database/";
+static ROOT: &str = "database/";
+/// The environment variable overriding the root directory of [`LocalDisk`] (`database/` by default)
+pub static ROOT_ENV: &str = "FLIGHTS_DATABASE";
+/// Extension of files being written by [`LocalDisk`], that are not yet blobs
+static IN_PROGRESS: &str = "tmp";
 
 /// An object that can be used to get and put blobs.
 #[async_trait]
@@ -12,6 +20,11 @@ pub trait BlobStorageProvider {
     async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error>;
     async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error>;
 
+    /// Returns whether `blob_name` exists, without reading it when the provider allows it
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        Ok(self.maybe_get(blob_name).await?.is_some())
+    }
+
     fn can_put(&self) -> bool;
 }
 
@@ -33,45 +46,169 @@ fn visit_dirs<P: AsRef<Path>>(
     Ok(())
 }
 
-/// A [`BlobStorageProvider`] for local disk
-pub struct LocalDisk;
+/// A [`BlobStorageProvider`] for local disk, storing each blob at its name relative to a root directory,
+/// i.e. with the same layout as the remote storage, so that blobs can be copied between them as is.
+/// # Implementation
+/// Writes are atomic: contents are written to a unique temporary file that is then renamed to the blob,
+/// so that concurrent writers and readers (of the same or other processes) never observe a partial blob.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disk {
+    root: PathBuf,
+}
+
+impl Disk {
+    /// Returns a new [`Disk`] storing blobs under `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Returns the path of `blob_name`
+    pub fn path(&self, blob_name: &str) -> PathBuf {
+        self.root.join(Path::new(blob_name))
+    }
+}
 
 #[async_trait]
-impl BlobStorageProvider for LocalDisk {
-    #[must_use]
+impl BlobStorageProvider for Disk {
     async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
-        let path = PathBuf::from(ROOT).join(Path::new(blob_name));
-        if path.try_exists()? {
-            Ok(Some(std::fs::read(path)?))
-        } else {
-            Ok(None)
+        match std::fs::read(self.path(blob_name)) {
+            Ok(data) => Ok(Some(data)),
+            // a blob that does not exist (yet) is a miss
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
         }
     }
 
-    #[must_use]
     async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
-        let path = PathBuf::from(ROOT).join(Path::new(blob_name));
+        let path = self.path(blob_name);
         let mut dir = path.clone();
         dir.pop();
         std::fs::create_dir_all(dir)?;
-        std::fs::write(path, &contents)?;
+
+        let mut temp = path.clone().into_os_string();
+        temp.push(format!(
+            ".{}-{:x}.{IN_PROGRESS}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::write(&temp, &contents)?;
+        if let Err(e) = std::fs::rename(&temp, path) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
         Ok(())
     }
 
-    #[must_use]
     async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
-        let path = PathBuf::from(ROOT).join(Path::new(prefix));
         let mut paths = vec![];
-        visit_dirs(path, &mut |dir| {
-            let path = dir.path().to_str().unwrap().to_string();
-            paths.push(path[ROOT.len()..].to_string())
+        visit_dirs(self.path(prefix), &mut |dir| {
+            let path = dir.path();
+            if path.extension().and_then(|x| x.to_str()) == Some(IN_PROGRESS) {
+                return;
+            }
+            let relative = path.strip_prefix(&self.root).expect("to be under the root");
+            // blob names are separated by `/` regardless of the platform
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            paths.push(name)
         })?;
         Ok(paths)
     }
 
-    #[must_use]
-    async fn delete(&self, _prefix: &str) -> Result<(), std::io::Error> {
-        todo!()
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        match tokio::fs::remove_file(self.path(blob_name)).await {
+            // a blob that does not exist is already deleted
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
+
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        Ok(self.path(blob_name).is_file())
+    }
+
+    fn can_put(&self) -> bool {
+        true
+    }
+}
+
+/// A [`Disk`] rooted at the directory of the environment variable [`ROOT_ENV`], or at `database/`
+pub struct LocalDisk;
+
+impl LocalDisk {
+    fn disk() -> Disk {
+        Disk::new(std::env::var_os(ROOT_ENV).unwrap_or_else(|| ROOT.into()))
+    }
+}
+
+#[async_trait]
+impl BlobStorageProvider for LocalDisk {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        Self::disk().maybe_get(blob_name).await
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        Self::disk().put(blob_name, contents).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        Self::disk().list(prefix).await
+    }
+
+    async fn delete(&self, prefix: &str) -> Result<(), std::io::Error> {
+        Self::disk().delete(prefix).await
+    }
+
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        Self::disk().exists(blob_name).await
+    }
+
+    fn can_put(&self) -> bool {
+        true
+    }
+}
+
+/// A [`BlobStorageProvider`] in memory, e.g. to run the pipeline without touching the disk
+#[derive(Default)]
+pub struct InMemory(Mutex<HashMap<String, Vec<u8>>>);
+
+#[async_trait]
+impl BlobStorageProvider for InMemory {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        Ok(self.0.lock().unwrap().get(blob_name).cloned())
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(blob_name.to_string(), contents);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut blobs = self
+            .0
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|blob_name| blob_name.starts_with(prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        blobs.sort();
+        Ok(blobs)
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.0.lock().unwrap().remove(blob_name);
+        Ok(())
+    }
+
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        Ok(self.0.lock().unwrap().contains_key(blob_name))
     }
 
     fn can_put(&self) -> bool {
@@ -79,20 +216,283 @@ impl BlobStorageProvider for LocalDisk {
     }
 }
 
+/// The compression of blobs written by [`Compressed`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Blobs are written as is
+    #[default]
+    None,
+    /// Blobs are written gzipped, with the extension `.gz`
+    Gzip,
+    /// Blobs are written with zstd, with the extension `.zst`
+    Zstd,
+}
+
+impl Compression {
+    /// All compressions, in the order in which blobs of other compressions are read by [`Compressed`]
+    const ALL: [Compression; 3] = [Compression::None, Compression::Gzip, Compression::Zstd];
+
+    /// The extension of the blobs of this compression, e.g. `.gz`
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    fn compress(&self, contents: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Compression::None => Ok(contents),
+            Compression::Gzip => gzip(&contents),
+            Compression::Zstd => zstd::encode_all(contents.as_slice(), 0),
+        }
+    }
+
+    fn decompress(&self, contents: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Compression::None => Ok(contents),
+            Compression::Gzip => gunzip(&contents),
+            Compression::Zstd => zstd::decode_all(contents.as_slice()),
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    /// Returns the [`Compression`] of its name (`none`, `gzip` or `zstd`), case insensitive
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!(
+                "Compression \"{s}\" is not supported. Supported compressions: none, gzip, zstd"
+            )),
+        }
+    }
+}
+
+/// A [`BlobStorageProvider`] that compresses blobs of `P` according to a [`Compression`].
+/// # Implementation
+/// Compressed blobs are stored with the extension of their compression (e.g. `data.json.gz`), so that
+/// blobs written with another compression (e.g. before, uncompressed) are still read:
+/// [`BlobStorageProvider::maybe_get`] reads the blob of its compression and falls back to the other ones,
+/// and [`BlobStorageProvider::list`] lists all without the extension.
+pub struct Compressed<P> {
+    provider: P,
+    compression: Compression,
+}
+
+impl<P: BlobStorageProvider> Compressed<P> {
+    /// Returns a new [`Compressed`] writing blobs to `provider` with `compression`
+    pub fn new(provider: P, compression: Compression) -> Self {
+        Self {
+            provider,
+            compression,
+        }
+    }
+}
+
+fn gzip(contents: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    encoder.write_all(contents)?;
+    encoder.finish()
+}
+
+fn gunzip(contents: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    use std::io::Read;
+    let mut data = vec![];
+    flate2::read::GzDecoder::new(contents).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+impl<P> Compressed<P> {
+    /// The compressions to read blobs of, starting with its own as the most likely to exist
+    fn compressions(&self) -> impl Iterator<Item = Compression> + '_ {
+        std::iter::once(self.compression).chain(
+            Compression::ALL
+                .into_iter()
+                .filter(|compression| *compression != self.compression),
+        )
+    }
+}
+
+#[async_trait]
+impl<P: BlobStorageProvider + Sync + Send> BlobStorageProvider for Compressed<P> {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        for compression in self.compressions() {
+            let blob = format!("{blob_name}{}", compression.extension());
+            if let Some(data) = self.provider.maybe_get(&blob).await? {
+                return compression.decompress(data).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        let blob = format!("{blob_name}{}", self.compression.extension());
+        let contents = self.compression.compress(contents)?;
+        self.provider.put(&blob, contents).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut blobs = self
+            .provider
+            .list(prefix)
+            .await?
+            .into_iter()
+            .map(|blob| {
+                let stripped = Compression::ALL
+                    .iter()
+                    .filter(|compression| **compression != Compression::None)
+                    .find_map(|compression| blob.strip_suffix(compression.extension()));
+                match stripped {
+                    Some(blob) => blob.to_string(),
+                    None => blob,
+                }
+            })
+            .collect::<Vec<_>>();
+        blobs.sort();
+        blobs.dedup();
+        Ok(blobs)
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        for compression in Compression::ALL {
+            let blob = format!("{blob_name}{}", compression.extension());
+            if self.provider.exists(&blob).await? {
+                self.provider.delete(&blob).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        for compression in self.compressions() {
+            let blob = format!("{blob_name}{}", compression.extension());
+            if self.provider.exists(&blob).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn can_put(&self) -> bool {
+        self.provider.can_put()
+    }
+}
+
+/// A [`BlobStorageProvider`] reading from a `primary` and falling back to a `secondary`, and writing to the `secondary`,
+/// e.g. to read the public (anonymous) database while caching newly computed blobs in a container owned by the user.
+/// # Implementation
+/// [`BlobStorageProvider::list`] lists both, and [`BlobStorageProvider::delete`] deletes from both, so that a deleted
+/// blob is not read from the other, except from a `primary` that can't be written to (e.g. anonymous).
+pub struct Layered<P, S> {
+    primary: P,
+    secondary: S,
+}
+
+impl<P: BlobStorageProvider, S: BlobStorageProvider> Layered<P, S> {
+    /// Returns a new [`Layered`] reading from `primary` and `secondary`, and writing to `secondary`
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self { primary, secondary }
+    }
+
+    /// Returns the provider read first
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    /// Returns the provider read on misses of the primary and written to
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+}
+
+#[async_trait]
+impl<P, S> BlobStorageProvider for Layered<P, S>
+where
+    P: BlobStorageProvider + Sync + Send,
+    S: BlobStorageProvider + Sync + Send,
+{
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        if let Some(data) = self.primary.maybe_get(blob_name).await? {
+            return Ok(Some(data));
+        }
+        self.secondary.maybe_get(blob_name).await
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.secondary.put(blob_name, contents).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut blobs = self.primary.list(prefix).await?;
+        blobs.extend(self.secondary.list(prefix).await?);
+        blobs.sort();
+        blobs.dedup();
+        Ok(blobs)
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.secondary.delete(blob_name).await?;
+        if self.primary.can_put() {
+            self.primary.delete(blob_name).await?;
+        }
+        Ok(())
+    }
+
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        Ok(self.primary.exists(blob_name).await? || self.secondary.exists(blob_name).await?)
+    }
+
+    fn can_put(&self) -> bool {
+        self.secondary.can_put()
+    }
+}
+
+/// How [`cached`] and [`cached_call`] use the cache
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CacheAction {
+    /// Reads from the cache; on a miss, fetches and writes the result.
+    /// Used for data that no longer changes (e.g. a past month).
     ReadFetchWrite,
+    /// Reads from the cache; on a miss, fetches without writing the result.
     ReadFetch,
-    #[allow(dead_code)]
+    /// Always fetches and writes the result, replacing any cached data.
     FetchWrite,
+    /// Always fetches, without reading nor writing the cache.
+    /// Used for data that is still changing (e.g. the current month), so that
+    /// a partial result is neither served stale nor cached forever.
+    Fetch,
 }
 
 impl CacheAction {
+    /// Returns the [`CacheAction`] for data that is complete once `date` (exclusive) is past:
+    /// * [`CacheAction::Fetch`] if `date` is today (UTC) or in the future, as the data is still changing
+    /// * [`CacheAction::ReadFetchWrite`] otherwise, as the data is immutable
     pub fn from_date(date: &time::Date) -> Self {
-        let now = time::OffsetDateTime::now_utc().date();
-        (date >= &now)
-            .then_some(Self::ReadFetch)
-            .unwrap_or(Self::ReadFetchWrite)
+        Self::from_date_at(date, time::OffsetDateTime::now_utc().date())
+    }
+
+    /// Like [`CacheAction::from_date`], with `today` instead of the current date (UTC)
+    pub fn from_date_at(date: &time::Date, today: time::Date) -> Self {
+        if date >= &today {
+            Self::Fetch
+        } else {
+            Self::ReadFetchWrite
+        }
+    }
+
+    fn reads(&self) -> bool {
+        matches!(self, Self::ReadFetchWrite | Self::ReadFetch)
+    }
+
+    fn writes(&self) -> bool {
+        matches!(self, Self::ReadFetchWrite | Self::FetchWrite)
     }
 }
 
@@ -111,17 +511,15 @@ where
     E: std::error::Error + Send + Sync + 'static,
     F: futures::Future<Output = Result<Vec<u8>, E>>,
 {
-    match action {
-        CacheAction::FetchWrite => miss(blob_name, fetch, provider, action).await,
-        _ => {
-            log::info!("Fetch {blob_name}");
-            if let Some(data) = provider.maybe_get(blob_name).await? {
-                log::info!("{blob_name} - cache hit");
-                Ok(data)
-            } else {
-                miss(blob_name, fetch, provider, action).await
-            }
-        }
+    if !action.reads() {
+        return miss(blob_name, fetch, provider, action).await;
+    }
+    log::info!("Fetch {blob_name}");
+    if let Some(data) = provider.maybe_get(blob_name).await? {
+        log::info!("{blob_name} - cache hit");
+        Ok(data)
+    } else {
+        miss(blob_name, fetch, provider, action).await
     }
 }
 
@@ -141,7 +539,7 @@ where
 {
     log::info!("{blob_name} - cache miss");
     let contents = fetch.await.map_err(std::io::Error::other)?;
-    if action == CacheAction::ReadFetch || !provider.can_put() {
+    if !action.writes() || !provider.can_put() {
         log::info!("{blob_name} - cache do not write");
         return Ok(contents);
     };
@@ -153,18 +551,240 @@ where
 /// * read from remote
 /// * if not found and can't write to remote => read disk and write to disk
 /// * if not found and can write to remote => fetch and write
+///
+/// The remote is not read when `action` does not read (e.g. [`CacheAction::Fetch`]).
 pub(crate) async fn cached_call<F: futures::Future<Output = Result<Vec<u8>, std::io::Error>>>(
     blob_name: &str,
     fetch: F,
     client: &dyn BlobStorageProvider,
     action: crate::fs::CacheAction,
 ) -> Result<Vec<u8>, std::io::Error> {
-    let Some(data) = client.maybe_get(blob_name).await? else {
+    let cached = if action.reads() {
+        client.maybe_get(blob_name).await?
+    } else {
+        None
+    };
+    let Some(data) = cached else {
         if !client.can_put() {
-            return crate::fs::cached(&blob_name, fetch, &crate::fs::LocalDisk, action).await;
+            return crate::fs::cached(blob_name, fetch, &crate::fs::LocalDisk, action).await;
         } else {
-            return crate::fs::cached(&blob_name, fetch, client, action).await;
+            return crate::fs::cached(blob_name, fetch, client, action).await;
         };
     };
     Ok(data)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_puts() {
+        let root = std::env::temp_dir().join(format!("flights-{:x}", rand::random::<u64>()));
+        let disk = std::sync::Arc::new(Disk::new(&root));
+        let blob_name = "test/concurrent_puts/data.csv";
+        let a = vec![b'a'; 1 << 20];
+        let b = vec![b'b'; 1 << 20];
+        for _ in 0..10 {
+            let put = |data: &Vec<u8>| {
+                let (disk, data) = (disk.clone(), data.clone());
+                tokio::spawn(async move { disk.put(blob_name, data).await })
+            };
+            let (r_a, r_b) = tokio::join!(put(&a), put(&b));
+            r_a.unwrap().unwrap();
+            r_b.unwrap().unwrap();
+
+            let data = disk.maybe_get(blob_name).await.unwrap().unwrap();
+            assert!(data == a || data == b);
+        }
+        assert_eq!(
+            disk.list("test/concurrent_puts/").await.unwrap(),
+            vec![blob_name.to_string()]
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn disk_delete() {
+        let root = std::env::temp_dir().join(format!("flights-{:x}", rand::random::<u64>()));
+        let disk = Disk::new(&root);
+        disk.put("a/data.json", vec![1]).await.unwrap();
+        assert!(disk.exists("a/data.json").await.unwrap());
+
+        disk.delete("a/data.json").await.unwrap();
+        assert!(!disk.exists("a/data.json").await.unwrap());
+        assert_eq!(disk.maybe_get("a/data.json").await.unwrap(), None);
+        // deleting a blob that does not exist is not an error
+        disk.delete("a/data.json").await.unwrap();
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn compressed() {
+        let contents = serde_json::to_vec(&vec![[1.0, 55.6, 12.6]; 1000]).unwrap();
+        let client = Compressed::new(InMemory::default(), Compression::Gzip);
+        client.put("a/data.json", contents.clone()).await.unwrap();
+
+        // stored compressed, read decompressed
+        let stored = client.provider.maybe_get("a/data.json.gz").await.unwrap();
+        assert!(stored.unwrap().len() < contents.len() / 10);
+        assert_eq!(
+            client.maybe_get("a/data.json").await.unwrap(),
+            Some(contents.clone())
+        );
+
+        // blobs written uncompressed are still read
+        client.provider.put("b/data.json", vec![1]).await.unwrap();
+        assert_eq!(
+            client.maybe_get("b/data.json").await.unwrap(),
+            Some(vec![1])
+        );
+        assert_eq!(
+            client.list("").await.unwrap(),
+            vec!["a/data.json".to_string(), "b/data.json".to_string()]
+        );
+
+        assert!(client.exists("a/data.json").await.unwrap());
+        client.delete("a/data.json").await.unwrap();
+        assert_eq!(client.maybe_get("a/data.json").await.unwrap(), None);
+        assert!(!client.exists("a/data.json").await.unwrap());
+
+        // blobs written compressed are read without compression
+        let uncompressed = Compressed::new(client.provider, Compression::None);
+        uncompressed.put("c/data.json", vec![2]).await.unwrap();
+        assert_eq!(
+            uncompressed.maybe_get("b/data.json").await.unwrap(),
+            Some(vec![1])
+        );
+        uncompressed
+            .provider
+            .put("d/data.json.gz", gzip(&contents).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            uncompressed.maybe_get("d/data.json").await.unwrap(),
+            Some(contents)
+        );
+        assert!(uncompressed.provider.exists("c/data.json").await.unwrap());
+        assert_eq!("GZIP".parse::<Compression>(), Ok(Compression::Gzip));
+        assert_eq!("zstd".parse::<Compression>(), Ok(Compression::Zstd));
+        assert!("brotli".parse::<Compression>().is_err());
+    }
+
+    #[tokio::test]
+    async fn compressed_zstd() {
+        let contents = serde_json::to_vec(&vec![[1.0, 55.6, 12.6]; 1000]).unwrap();
+        let client = Compressed::new(InMemory::default(), Compression::Zstd);
+        client.put("a/data.json", contents.clone()).await.unwrap();
+
+        // stored compressed, read decompressed
+        let stored = client.provider.maybe_get("a/data.json.zst").await.unwrap();
+        assert!(stored.unwrap().len() < contents.len() / 10);
+        assert_eq!(
+            client.maybe_get("a/data.json").await.unwrap(),
+            Some(contents.clone())
+        );
+        assert_eq!(client.list("").await.unwrap(), vec!["a/data.json"]);
+
+        // blobs written with zstd are read with other compressions, and vice versa
+        let gzipped = Compressed::new(client.provider, Compression::Gzip);
+        gzipped.put("b/data.json", vec![1]).await.unwrap();
+        assert_eq!(
+            gzipped.maybe_get("a/data.json").await.unwrap(),
+            Some(contents)
+        );
+        let client = Compressed::new(gzipped.provider, Compression::Zstd);
+        assert_eq!(
+            client.maybe_get("b/data.json").await.unwrap(),
+            Some(vec![1])
+        );
+
+        client.delete("a/data.json").await.unwrap();
+        assert!(!client.exists("a/data.json").await.unwrap());
+        assert!(!client.provider.exists("a/data.json.zst").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn missing_is_none() {
+        assert!(LocalDisk
+            .maybe_get("test/missing_is_none/data.csv")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn layered() {
+        let primary = InMemory::default();
+        primary.put("a", b"primary".to_vec()).await.unwrap();
+        let client = Layered::new(primary, InMemory::default());
+        let unreachable =
+            async { Result::<Vec<u8>, std::io::Error>::Err(std::io::Error::other("fetched")) };
+
+        // reads hit the primary
+        let data = cached_call("a", unreachable, &client, CacheAction::ReadFetchWrite)
+            .await
+            .unwrap();
+        assert_eq!(data, b"primary");
+
+        // misses are fetched and written to the secondary
+        let fetch = async { Result::<_, std::io::Error>::Ok(b"fetched".to_vec()) };
+        let data = cached_call("b", fetch, &client, CacheAction::ReadFetchWrite)
+            .await
+            .unwrap();
+        assert_eq!(data, b"fetched");
+        assert_eq!(client.primary().maybe_get("b").await.unwrap(), None);
+        assert_eq!(
+            client.secondary().maybe_get("b").await.unwrap(),
+            Some(b"fetched".to_vec())
+        );
+
+        // and are then read from the secondary
+        let unreachable =
+            async { Result::<Vec<u8>, std::io::Error>::Err(std::io::Error::other("fetched")) };
+        let data = cached_call("b", unreachable, &client, CacheAction::ReadFetchWrite)
+            .await
+            .unwrap();
+        assert_eq!(data, b"fetched");
+        assert_eq!(client.list("").await.unwrap(), vec!["a", "b"]);
+
+        // deletes from both
+        client
+            .secondary()
+            .put("a", b"secondary".to_vec())
+            .await
+            .unwrap();
+        client.delete("a").await.unwrap();
+        client.delete("b").await.unwrap();
+        assert_eq!(client.maybe_get("a").await.unwrap(), None);
+        assert!(!client.primary().exists("a").await.unwrap());
+        assert!(!client.secondary().exists("a").await.unwrap());
+        assert!(client.list("").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cached_call_compressed() {
+        let client = Compressed::new(InMemory::default(), Compression::Gzip);
+        let contents = serde_json::to_vec(&vec![[1.0, 55.6, 12.6]; 1000]).unwrap();
+        let fetch = async { Result::<_, std::io::Error>::Ok(contents.clone()) };
+        let data = cached_call("a/data.json", fetch, &client, CacheAction::ReadFetchWrite)
+            .await
+            .unwrap();
+        assert_eq!(data, contents);
+
+        // written compressed, read back decompressed
+        assert!(client.provider.exists("a/data.json.gz").await.unwrap());
+        assert!(!client.provider.exists("a/data.json").await.unwrap());
+        let unreachable =
+            async { Result::<Vec<u8>, std::io::Error>::Err(std::io::Error::other("fetched")) };
+        let data = cached_call(
+            "a/data.json",
+            unreachable,
+            &client,
+            CacheAction::ReadFetchWrite,
+        )
+        .await
+        .unwrap();
+        assert_eq!(data, contents);
+    }
+}
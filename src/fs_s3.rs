@@ -1,4 +1,4 @@
-use std::io::Error;
+use std::{io::Error, sync::Arc};
 
 use aws_credential_types::provider::ProvideCredentials;
 use aws_sdk_s3::{
@@ -6,11 +6,60 @@ use aws_sdk_s3::{
     primitives::ByteStream, types::ObjectCannedAcl,
 };
 
-use crate::fs::BlobStorageProvider;
+use tokio::sync::Semaphore;
+
+use crate::fs::{BlobStorageProvider, Compressed, Compression};
+use crate::http::RetryPolicy;
+
+/// The location of an S3-compatible bucket. Defaults to the public bucket of this project
+/// (on DigitalOcean Spaces).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    /// The endpoint of the service; `None` uses the AWS endpoint of `region`
+    pub endpoint_url: Option<String>,
+    pub region: String,
+    /// The name of the bucket
+    pub name: String,
+    /// Whether to address the bucket in the path rather than in the host (e.g. for localstack)
+    pub force_path_style: bool,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self {
+            endpoint_url: Some("https://fra1.digitaloceanspaces.com".to_string()),
+            region: "fra1".to_string(),
+            name: "private-jets".to_string(),
+            force_path_style: false,
+        }
+    }
+}
+
 pub struct ContainerClient {
     pub client: aws_sdk_s3::Client,
     pub bucket: String,
     can_put: bool,
+    retry_policy: RetryPolicy,
+    compression: Compression,
+    /// Limits the number of in-flight requests
+    permits: Arc<Semaphore>,
+}
+
+impl ContainerClient {
+    /// The [`Compression`] of the blobs it writes; blobs of any compression are read
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Returns its objects, compressed with its [`Compression`]
+    fn compressed(&self) -> Compressed<Objects<'_>> {
+        Compressed::new(Objects(self), self.compression)
+    }
+
+    /// The [`RetryPolicy`] of its requests, e.g. to also retry the fetches from adsbexchange with it
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
 }
 
 async fn get(client: &ContainerClient, blob_name: &str) -> Result<Option<Vec<u8>>, Error> {
@@ -46,10 +95,13 @@ async fn get(client: &ContainerClient, blob_name: &str) -> Result<Option<Vec<u8>
 
 async fn put(client: &ContainerClient, blob_name: &str, content: Vec<u8>) -> Result<(), Error> {
     let stream = ByteStream::from(content);
-    let content_type = blob_name
-        .ends_with(".json")
-        .then_some("application/json")
-        .unwrap_or("text/csv");
+    let content_type = if blob_name.ends_with(".json") {
+        "application/json"
+    } else if blob_name.ends_with(".gz") {
+        "application/gzip"
+    } else {
+        "text/csv"
+    };
 
     client
         .client
@@ -77,6 +129,21 @@ async fn delete(client: &ContainerClient, blob_name: &str) -> Result<(), Error>
         .map(|_| ())
 }
 
+async fn exists(client: &ContainerClient, blob_name: &str) -> Result<bool, Error> {
+    let head = client
+        .client
+        .head_object()
+        .bucket(&client.bucket)
+        .key(blob_name)
+        .send()
+        .await;
+    match head {
+        Ok(_) => Ok(true),
+        Err(SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(false),
+        Err(e) => Err(Error::other(e)),
+    }
+}
+
 #[derive(Debug)]
 struct Provider {
     access_key: String,
@@ -104,74 +171,228 @@ impl ProvideCredentials for Provider {
     }
 }
 
-/// Initialize a [`ContainerClient`] access key and secret access key
-pub async fn client(access_key: String, secret_access_key: String) -> ContainerClient {
-    let provider = Provider {
-        access_key,
-        secret_access_key,
+fn loader(bucket: &Bucket, retry_policy: Option<&RetryPolicy>) -> aws_config::ConfigLoader {
+    let loader = aws_config::ConfigLoader::default()
+        .behavior_version(aws_config::BehaviorVersion::latest())
+        .region(aws_config::Region::new(bucket.region.clone()));
+    // the SDK always adds jitter to its backoff
+    let loader = match retry_policy {
+        Some(policy) => loader.retry_config(
+            aws_config::retry::RetryConfig::standard()
+                .with_max_attempts(policy.max_attempts as u32)
+                .with_initial_backoff(policy.base_delay),
+        ),
+        None => loader,
     };
+    match &bucket.endpoint_url {
+        Some(endpoint_url) => loader.endpoint_url(endpoint_url),
+        None => loader,
+    }
+}
 
-    let config = aws_config::ConfigLoader::default()
-        .behavior_version(aws_config::BehaviorVersion::latest())
-        .region("fra1")
-        .endpoint_url("https://fra1.digitaloceanspaces.com")
-        .credentials_provider(provider)
-        .load()
-        .await;
-    let client = aws_sdk_s3::Client::new(&config);
+fn s3_client(bucket: &Bucket, config: &aws_config::SdkConfig) -> aws_sdk_s3::Client {
+    let config = aws_sdk_s3::config::Builder::from(config)
+        .force_path_style(bucket.force_path_style)
+        .build();
+    aws_sdk_s3::Client::from_conf(config)
+}
+
+/// Builder of a [`ContainerClient`]. Defaults to an anonymous client of [`Bucket::default`]
+/// with the retries of the SDK (and [`RetryPolicy::default`]), e.g.
+/// ```no_run
+/// # async fn example() {
+/// let client = flights::fs_s3::ContainerClientBuilder::default()
+///     .bucket("my-bucket")
+///     .credentials("access key".to_string(), "secret".to_string())
+///     .max_attempts(5)
+///     .concurrency(100)
+///     .compression(flights::fs::Compression::Gzip)
+///     .build()
+///     .await;
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ContainerClientBuilder {
+    location: Bucket,
+    credentials: Option<(String, String)>,
+    retry_policy: Option<RetryPolicy>,
+    compression: Compression,
+    concurrency: Option<usize>,
+}
+
+impl ContainerClientBuilder {
+    /// Sets the location of the bucket
+    pub fn location(mut self, location: Bucket) -> Self {
+        self.location = location;
+        self
+    }
+
+    /// Sets the endpoint of the service; `None` uses the AWS endpoint of the region
+    pub fn endpoint_url(mut self, endpoint_url: Option<String>) -> Self {
+        self.location.endpoint_url = endpoint_url;
+        self
+    }
+
+    /// Sets the region of the bucket (e.g. `fra1`)
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.location.region = region.into();
+        self
+    }
+
+    /// Sets the name of the bucket
+    pub fn bucket(mut self, name: impl Into<String>) -> Self {
+        self.location.name = name.into();
+        self
+    }
+
+    /// Sets whether to address the bucket in the path rather than in the host
+    pub fn force_path_style(mut self, force_path_style: bool) -> Self {
+        self.location.force_path_style = force_path_style;
+        self
+    }
+
+    /// Authenticates with an access key and secret access key, allowing writes
+    pub fn credentials(mut self, access_key: String, secret_access_key: String) -> Self {
+        self.credentials = Some((access_key, secret_access_key));
+        self
+    }
+
+    /// Does not authenticate, only allowing reads of public objects
+    pub fn anonymous(mut self) -> Self {
+        self.credentials = None;
+        self
+    }
+
+    /// Sets the maximum number of attempts of each request (including the first one),
+    /// keeping the other parameters of its [`RetryPolicy`]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.retry_policy
+            .get_or_insert_with(Default::default)
+            .max_attempts = max_attempts as usize;
+        self
+    }
+
+    /// Sets the [`RetryPolicy`] of its requests (see [`ContainerClient::retry_policy`])
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
 
-    ContainerClient {
-        client,
-        bucket: "private-jets".to_string(),
-        can_put: true,
+    /// Sets the maximum number of concurrent requests to the bucket; unlimited by default
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
     }
+
+    /// Sets the [`Compression`] of the blobs it writes (see [`ContainerClient::compression`])
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Returns the [`ContainerClient`]
+    pub async fn build(self) -> ContainerClient {
+        let loader = loader(&self.location, self.retry_policy.as_ref());
+        let (config, can_put) = match self.credentials {
+            Some((access_key, secret_access_key)) => {
+                let provider = Provider {
+                    access_key,
+                    secret_access_key,
+                };
+                (loader.credentials_provider(provider).load().await, true)
+            }
+            None => (loader.no_credentials().load().await, false),
+        };
+
+        ContainerClient {
+            client: s3_client(&self.location, &config),
+            bucket: self.location.name,
+            can_put,
+            retry_policy: self.retry_policy.unwrap_or_default(),
+            compression: self.compression,
+            permits: Arc::new(Semaphore::new(
+                self.concurrency.unwrap_or(Semaphore::MAX_PERMITS),
+            )),
+        }
+    }
+}
+
+/// Initialize a [`ContainerClient`] access key and secret access key
+pub async fn client(access_key: String, secret_access_key: String) -> ContainerClient {
+    client_in(&Bucket::default(), access_key, secret_access_key).await
+}
+
+/// Initialize a [`ContainerClient`] of `bucket` with access key and secret access key
+pub async fn client_in(
+    bucket: &Bucket,
+    access_key: String,
+    secret_access_key: String,
+) -> ContainerClient {
+    ContainerClientBuilder::default()
+        .location(bucket.clone())
+        .credentials(access_key, secret_access_key)
+        .build()
+        .await
 }
 
 /// Initialize an anonymous [`ContainerClient`]
 pub async fn anonymous_client() -> ContainerClient {
-    let config = aws_config::ConfigLoader::default()
-        .behavior_version(aws_config::BehaviorVersion::latest())
-        .region("fra1")
-        .endpoint_url("https://fra1.digitaloceanspaces.com")
-        .no_credentials()
-        .load()
-        .await;
-    let client = aws_sdk_s3::Client::new(&config);
+    anonymous_client_in(&Bucket::default()).await
+}
 
-    ContainerClient {
-        client,
-        bucket: "private-jets".to_string(),
-        can_put: false,
+/// Initialize an anonymous [`ContainerClient`] of `bucket`
+pub async fn anonymous_client_in(bucket: &Bucket) -> ContainerClient {
+    ContainerClientBuilder::default()
+        .location(bucket.clone())
+        .anonymous()
+        .build()
+        .await
+}
+
+/// The objects of a [`ContainerClient`], as stored (i.e. without decompressing them)
+struct Objects<'a>(&'a ContainerClient);
+
+impl Objects<'_> {
+    /// Waits until a request can be sent, returning the permit to release once the request completes
+    async fn permit(&self) -> tokio::sync::SemaphorePermit<'_> {
+        // the semaphore is never closed
+        self.0.permits.acquire().await.unwrap()
     }
 }
 
 #[async_trait::async_trait]
-impl BlobStorageProvider for ContainerClient {
-    #[must_use]
+impl BlobStorageProvider for Objects<'_> {
     async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
-        get(&self, blob_name).await.map_err(std::io::Error::other)
+        let _permit = self.permit().await;
+        get(self.0, blob_name).await.map_err(std::io::Error::other)
     }
 
-    #[must_use]
     async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
-        put(&self, blob_name, contents)
+        let _permit = self.permit().await;
+        put(self.0, blob_name, contents)
             .await
             .map_err(std::io::Error::other)
     }
 
-    #[must_use]
     async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
-        delete(&self, blob_name)
+        let _permit = self.permit().await;
+        delete(self.0, blob_name)
             .await
             .map_err(std::io::Error::other)
     }
 
-    #[must_use]
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        let _permit = self.permit().await;
+        exists(self.0, blob_name).await
+    }
+
     async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
-        Ok(self
+        let _permit = self.permit().await;
+        let client = self.0;
+        Ok(client
             .client
             .list_objects_v2()
-            .bucket(&self.bucket)
+            .bucket(&client.bucket)
             .prefix(prefix)
             .into_paginator()
             .send()
@@ -179,17 +400,44 @@ impl BlobStorageProvider for ContainerClient {
             .await
             .map_err(std::io::Error::other)?
             .into_iter()
-            .map(|response| {
+            .flat_map(|response| {
                 response
                     .contents()
                     .iter()
                     .filter_map(|blob| blob.key().map(|x| x.to_string()))
                     .collect::<Vec<_>>()
             })
-            .flatten()
             .collect())
     }
 
+    fn can_put(&self) -> bool {
+        self.0.can_put
+    }
+}
+
+/// Blobs are compressed according to its [`Compression`] (see [`Compressed`])
+#[async_trait::async_trait]
+impl BlobStorageProvider for ContainerClient {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        self.compressed().maybe_get(blob_name).await
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.compressed().put(blob_name, contents).await
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.compressed().delete(blob_name).await
+    }
+
+    async fn exists(&self, blob_name: &str) -> Result<bool, std::io::Error> {
+        self.compressed().exists(blob_name).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        self.compressed().list(prefix).await
+    }
+
     fn can_put(&self) -> bool {
         self.can_put
     }
@@ -222,7 +470,51 @@ mod test {
     #[tokio::test]
     async fn list_ok() {
         let client = super::anonymous_client().await;
-        assert!(client.list("leg/v1/all/year=2019/").await.unwrap().len() > 0);
+        assert!(!client
+            .list("leg/v1/all/year=2019/")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn builder() {
+        let client = super::ContainerClientBuilder::default()
+            .endpoint_url(Some("http://localhost:4566".to_string()))
+            .region("us-east-1")
+            .bucket("test")
+            .force_path_style(true)
+            .max_attempts(1)
+            .compression(crate::fs::Compression::Gzip)
+            .concurrency(8)
+            .build()
+            .await;
+        assert_eq!(client.bucket, "test");
+        assert_eq!(client.permits.available_permits(), 8);
+        assert_eq!(client.compression(), crate::fs::Compression::Gzip);
+        assert!(!client.can_put());
+        let config = client.client.config();
+        assert_eq!(config.region().unwrap().as_ref(), "us-east-1");
+        assert_eq!(config.retry_config().unwrap().max_attempts(), 1);
+        assert_eq!(client.retry_policy().max_attempts, 1);
+
+        let policy = crate::http::RetryPolicy {
+            max_attempts: 4,
+            base_delay: std::time::Duration::from_secs(2),
+            jitter: 0.0,
+        };
+        let client = super::ContainerClientBuilder::default()
+            .credentials("a".to_string(), "b".to_string())
+            .retry_policy(policy)
+            .build()
+            .await;
+        assert_eq!(client.bucket, "private-jets");
+        assert!(client.can_put());
+        assert_eq!(client.retry_policy(), policy);
+        assert_eq!(client.compression(), crate::fs::Compression::None);
+        let retry = client.client.config().retry_config().unwrap().clone();
+        assert_eq!(retry.max_attempts(), 4);
+        assert_eq!(retry.initial_backoff(), std::time::Duration::from_secs(2));
     }
 
     #[tokio::test]
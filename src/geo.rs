@@ -0,0 +1,103 @@
+//! Contains geographic computations over [`LatLon`]s.
+use crate::LatLon;
+
+/// Returns the great-circle distance between two points in km (haversine formula).
+/// Points are [`LatLon`]s rather than pairs so that latitude and longitude cannot be swapped.
+pub fn great_circle_distance_km(a: LatLon, b: LatLon) -> f64 {
+    let from = geoutils::Location::new(a.latitude, a.longitude);
+    let to = geoutils::Location::new(b.latitude, b.longitude);
+    from.haversine_distance_to(&to).meters() / 1000.0
+}
+
+/// A region between two corners, inclusive of its edges.
+/// Corners are [`LatLon`]s rather than pairs so that latitude and longitude cannot be swapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// The south-west corner
+    pub sw: LatLon,
+    /// The north-east corner
+    pub ne: LatLon,
+}
+
+/// The region of Davos (Switzerland), including the airfield of Samedan, e.g. to identify flights to the World Economic Forum
+pub static DAVOS: BoundingBox = BoundingBox {
+    sw: LatLon {
+        latitude: 46.5,
+        longitude: 9.5,
+    },
+    ne: LatLon {
+        latitude: 47.0,
+        longitude: 10.2,
+    },
+};
+
+impl BoundingBox {
+    /// Returns whether `pos` is inside the box or on its edges.
+    /// A box whose south-west longitude is east of its north-east longitude crosses the antimeridian.
+    pub fn contains(&self, pos: LatLon) -> bool {
+        let latitude = self.sw.latitude <= pos.latitude && pos.latitude <= self.ne.latitude;
+        let longitude = if self.sw.longitude <= self.ne.longitude {
+            self.sw.longitude <= pos.longitude && pos.longitude <= self.ne.longitude
+        } else {
+            self.sw.longitude <= pos.longitude || pos.longitude <= self.ne.longitude
+        };
+        latitude && longitude
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(latitude: f64, longitude: f64) -> LatLon {
+        LatLon {
+            latitude,
+            longitude,
+        }
+    }
+
+    #[test]
+    fn city_pairs() {
+        let jfk = at(40.6413, -73.7781);
+        let lhr = at(51.4700, -0.4543);
+        let cdg = at(49.0097, 2.5479);
+        let syd = at(-33.9399, 151.1753);
+
+        assert!((great_circle_distance_km(jfk, lhr) - 5550.0).abs() < 20.0);
+        assert!((great_circle_distance_km(lhr, cdg) - 348.0).abs() < 5.0);
+        assert!((great_circle_distance_km(lhr, syd) - 17020.0).abs() < 50.0);
+        assert_eq!(great_circle_distance_km(jfk, jfk), 0.0);
+        assert_eq!(
+            great_circle_distance_km(jfk, lhr),
+            great_circle_distance_km(lhr, jfk)
+        );
+    }
+
+    #[test]
+    fn bounding_box() {
+        let davos = at(46.8027, 9.8360);
+        let samedan = at(46.5341, 9.8841);
+        let zurich = at(47.4647, 8.5492);
+        assert!(DAVOS.contains(davos));
+        assert!(DAVOS.contains(samedan));
+        assert!(!DAVOS.contains(zurich));
+        // swapped latitude and longitude
+        assert!(!DAVOS.contains(at(davos.longitude, davos.latitude)));
+
+        // edges and corners are inside
+        assert!(DAVOS.contains(at(46.5, 9.8)));
+        assert!(DAVOS.contains(at(46.7, 10.2)));
+        assert!(DAVOS.contains(DAVOS.sw));
+        assert!(DAVOS.contains(DAVOS.ne));
+        assert!(!DAVOS.contains(at(46.4999, 9.8)));
+        assert!(!DAVOS.contains(at(46.7, 10.2001)));
+
+        let fiji = BoundingBox {
+            sw: at(-21.0, 177.0),
+            ne: at(-12.0, -178.0),
+        };
+        assert!(fiji.contains(at(-17.0, 179.5)));
+        assert!(fiji.contains(at(-17.0, -179.5)));
+        assert!(!fiji.contains(at(-17.0, 0.0)));
+    }
+}
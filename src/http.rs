@@ -0,0 +1,333 @@
+//! Contains the abstraction over HTTP requests, so that fetching data from the internet can be replaced (e.g. in tests).
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Semaphore;
+
+/// An object that can be used to fetch the contents of an url.
+#[async_trait]
+pub trait HttpFetcher: Sync {
+    /// Returns the body of a GET request to `url`.
+    /// # Error
+    /// Errors with [`std::io::ErrorKind::NotFound`] when `url` does not exist
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error>;
+}
+
+/// [`HttpFetcher`] of plain GET requests, e.g. of open datasets.
+/// It does not retry: wrap it in [`Retry`] to retry transient errors
+#[derive(Clone, Copy)]
+pub struct Get;
+
+#[async_trait]
+impl HttpFetcher for Get {
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+        let response = reqwest::get(url).await.map_err(to_io_error)?;
+        body(url, response).await
+    }
+}
+
+/// Returns the body of `response` to a request to `url`, or [`std::io::ErrorKind::NotFound`]
+/// on a 404 and a [`StatusError`] on any other unsuccessful status
+pub(crate) async fn body(
+    url: &str,
+    response: reqwest::Response,
+) -> Result<Vec<u8>, std::io::Error> {
+    let status = response.status();
+    if status == reqwest::StatusCode::OK {
+        Ok(response.bytes().await.map_err(to_io_error)?.to_vec())
+    } else if status == reqwest::StatusCode::NOT_FOUND {
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, url))
+    } else {
+        let body = response.text().await.map_err(to_io_error)?;
+        Err(StatusError {
+            status: status.as_u16(),
+            body,
+        }
+        .into())
+    }
+}
+
+/// Returns `error` as a [`std::io::Error`] whose kind distinguishes timeouts and connection errors,
+/// so that they are retried (see [`Retry`])
+pub(crate) fn to_io_error(error: reqwest::Error) -> std::io::Error {
+    let kind = if error.is_timeout() {
+        std::io::ErrorKind::TimedOut
+    } else if error.is_connect() {
+        std::io::ErrorKind::ConnectionRefused
+    } else {
+        std::io::ErrorKind::Other
+    };
+    std::io::Error::new(kind, error)
+}
+
+/// [`HttpFetcher`] that limits the number of in-flight requests of `F`.
+/// Clones share the same limit, so that all operations of a process using clones of it respect
+/// a single global limit.
+#[derive(Clone)]
+pub struct Limited<F> {
+    fetcher: F,
+    permits: Arc<Semaphore>,
+}
+
+impl<F: HttpFetcher> Limited<F> {
+    /// Returns a new [`Limited`] allowing at most `max_in_flight` concurrent requests of `fetcher`
+    pub fn new(fetcher: F, max_in_flight: usize) -> Self {
+        Self {
+            fetcher,
+            permits: Arc::new(Semaphore::new(max_in_flight)),
+        }
+    }
+}
+
+#[async_trait]
+impl<F: HttpFetcher + Send> HttpFetcher for Limited<F> {
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+        // the semaphore is never closed
+        let _permit = self.permits.acquire().await.unwrap();
+        self.fetcher.get_bytes(url).await
+    }
+}
+
+/// The policy of [`Retry`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts of a request, including the first
+    pub max_attempts: usize,
+    /// The delay before the first retry; it doubles on each subsequent retry
+    pub base_delay: std::time::Duration,
+    /// The maximum fraction of the delay added at random, so that concurrent retries spread out
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: std::time::Duration::from_secs(1),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the `retry`th retry (starting at 0)
+    fn delay(&self, retry: u32) -> std::time::Duration {
+        let delay = self.base_delay * 2u32.saturating_pow(retry);
+        delay.mul_f64(1.0 + self.jitter * rand::random::<f64>())
+    }
+}
+
+/// The error of a request answered with an unsuccessful HTTP status (other than 404, which is
+/// [`std::io::ErrorKind::NotFound`]), wrapped in a [`std::io::Error`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusError {
+    /// The HTTP status code, e.g. `503`
+    pub status: u16,
+    /// The body of the response
+    pub body: String,
+}
+
+impl std::fmt::Display for StatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP status {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+impl From<StatusError> for std::io::Error {
+    fn from(error: StatusError) -> Self {
+        std::io::Error::other(error)
+    }
+}
+
+/// Returns whether `error` may not happen again on a retry: a 429 or 5xx status (see [`StatusError`]),
+/// or a connection error or timeout, as opposed to e.g. a missing url, another 4xx or invalid data
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind::*;
+    if let Some(error) = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<StatusError>())
+    {
+        return error.status == 429 || (500..600).contains(&error.status);
+    }
+    matches!(
+        error.kind(),
+        ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | BrokenPipe
+            | TimedOut
+            | UnexpectedEof
+            | Interrupted
+    )
+}
+
+/// [`HttpFetcher`] that retries transient errors of `F` with exponential backoff according to a [`RetryPolicy`]
+#[derive(Clone)]
+pub struct Retry<F> {
+    fetcher: F,
+    policy: RetryPolicy,
+}
+
+impl<F: HttpFetcher> Retry<F> {
+    /// Returns a new [`Retry`] of `fetcher` with `policy`
+    pub fn new(fetcher: F, policy: RetryPolicy) -> Self {
+        Self { fetcher, policy }
+    }
+}
+
+#[async_trait]
+impl<F: HttpFetcher + Send> HttpFetcher for Retry<F> {
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+        let mut retry = 0;
+        loop {
+            match self.fetcher.get_bytes(url).await {
+                Err(e) if is_transient(&e) && (retry as usize) + 1 < self.policy.max_attempts => {
+                    log::warn!("{url} - {e}; retrying");
+                    tokio::time::sleep(self.policy.delay(retry)).await;
+                    retry += 1;
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::StreamExt;
+
+    use super::*;
+
+    /// [`HttpFetcher`] recording the maximum number of concurrent requests
+    #[derive(Default)]
+    struct Counting {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl HttpFetcher for Arc<Counting> {
+        async fn get_bytes(&self, _: &str) -> Result<Vec<u8>, std::io::Error> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(vec![])
+        }
+    }
+
+    /// [`HttpFetcher`] failing with the first errors before succeeding
+    struct Failing {
+        errors: std::sync::Mutex<Vec<std::io::Error>>,
+        attempts: AtomicUsize,
+    }
+
+    impl Failing {
+        fn new(errors: Vec<std::io::Error>) -> Self {
+            Self {
+                errors: std::sync::Mutex::new(errors),
+                attempts: AtomicUsize::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HttpFetcher for Arc<Failing> {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let mut errors = self.errors.lock().unwrap();
+            if errors.is_empty() {
+                Ok(url.as_bytes().to_vec())
+            } else {
+                Err(errors.remove(0))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn retry() {
+        let policy = RetryPolicy {
+            base_delay: std::time::Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        let status = |status| {
+            std::io::Error::from(StatusError {
+                status,
+                body: "".to_string(),
+            })
+        };
+        let timeout = || std::io::Error::from(std::io::ErrorKind::TimedOut);
+
+        // fails twice (transiently) then succeeds
+        let failing = Arc::new(Failing::new(vec![status(503), timeout()]));
+        let fetcher = Retry::new(failing.clone(), policy);
+        assert_eq!(fetcher.get_bytes("url").await.unwrap(), b"url");
+        assert_eq!(failing.attempts.load(Ordering::SeqCst), 3);
+
+        // gives up after the maximum attempts
+        let failing = Arc::new(Failing::new(vec![status(429), status(500), timeout()]));
+        let fetcher = Retry::new(failing.clone(), policy);
+        assert!(fetcher.get_bytes("url").await.is_err());
+        assert_eq!(failing.attempts.load(Ordering::SeqCst), 3);
+
+        // not found, other 4xx and other errors (e.g. parsing) are not retried
+        for error in [
+            std::io::Error::from(std::io::ErrorKind::NotFound),
+            status(403),
+            std::io::Error::other("invalid"),
+        ] {
+            let kind = error.kind();
+            let failing = Arc::new(Failing::new(vec![error]));
+            let fetcher = Retry::new(failing.clone(), policy);
+            let error = fetcher.get_bytes("url").await.unwrap_err();
+            assert_eq!(error.kind(), kind);
+            assert_eq!(failing.attempts.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(0), std::time::Duration::from_secs(1));
+        assert_eq!(policy.delay(2), std::time::Duration::from_secs(4));
+
+        let policy = RetryPolicy::default();
+        let delay = policy.delay(1);
+        assert!(delay >= std::time::Duration::from_secs(2));
+        assert!(delay <= std::time::Duration::from_secs(3));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn global_limit() {
+        let counting = Arc::new(Counting::default());
+        let fetcher = Limited::new(counting.clone(), 3);
+
+        // two operations, each with more concurrency than the limit
+        let operation = |fetcher: Limited<Arc<Counting>>| {
+            tokio::spawn(async move {
+                futures::stream::iter(0..20)
+                    .map(|_| fetcher.get_bytes("url"))
+                    .buffer_unordered(10)
+                    .collect::<Vec<_>>()
+                    .await
+            })
+        };
+        let (a, b) = (operation(fetcher.clone()), operation(fetcher));
+        a.await.unwrap();
+        b.await.unwrap();
+
+        assert!(counting.max_in_flight.load(Ordering::SeqCst) <= 3);
+        assert!(counting.max_in_flight.load(Ordering::SeqCst) > 0);
+    }
+}
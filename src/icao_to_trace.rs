@@ -1,13 +1,12 @@
 use rand::Rng;
 use reqwest::header;
-use reqwest::{self, StatusCode};
-use reqwest_middleware::ClientBuilder;
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use time::Date;
 use time::OffsetDateTime;
 
 use super::Position;
 use crate::fs;
+use crate::http::HttpFetcher;
+use crate::legs::{Leg, LegOptions};
 
 fn last_2(icao: &str) -> &str {
     let bytes = icao.as_bytes();
@@ -15,8 +14,8 @@ fn last_2(icao: &str) -> &str {
 }
 
 fn to_url(icao: &str, date: &time::Date) -> String {
-    let format = time::format_description::parse("[year]/[month]/[day]").unwrap();
-    let date = date.format(&format).unwrap();
+    let format = time::macros::format_description!("[year]/[month]/[day]");
+    let date = date.format(format).unwrap();
     let last_2 = last_2(icao);
     format!("https://globe.adsbexchange.com/globe_history/{date}/traces/{last_2}/trace_full_{icao}.json")
 }
@@ -38,62 +37,78 @@ fn adsbx_sid() -> String {
     format!("{time}_{random_chars}")
 }
 
-pub(crate) static DATABASE: &'static str = "globe_history";
+/// Returns the referer of a request to `url`: the globe centered on the aircraft of the trace
+/// (e.g. `.../trace_full_45860d.json`), as when browsing it, or the globe itself
+fn referer(url: &str) -> String {
+    match url
+        .rsplit_once("trace_full_")
+        .and_then(|(_, file)| file.strip_suffix(".json"))
+    {
+        Some(icao) => {
+            format!("https://globe.adsbexchange.com/?icao={icao}&lat=54.448&lon=10.602&zoom=7.0")
+        }
+        None => "https://globe.adsbexchange.com/".to_string(),
+    }
+}
+
+pub(crate) static DATABASE: &str = "globe_history";
 
 fn cache_file_path(icao: &str, date: &time::Date) -> String {
     format!("{DATABASE}/{date}/trace_full_{icao}.json")
 }
 
-async fn globe_history(icao: &str, date: &time::Date) -> Result<Vec<u8>, std::io::Error> {
+/// [`HttpFetcher`] of [adsbexchange](https://globe.adsbexchange.com).
+/// It does not retry: wrap it in [`crate::http::Retry`] to retry transient errors
+#[derive(Clone, Copy)]
+pub struct AdsbExchange;
+
+#[async_trait::async_trait]
+impl HttpFetcher for AdsbExchange {
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            "User-Agent",
+            "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:109.0) Gecko/20100101 Firefox/118.0"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert(
+            "Accept",
+            "application/json, text/javascript, */*; q=0.01"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert("Accept-Language", "en-US,en;q=0.5".parse().unwrap());
+        headers.insert("Accept-Encoding", "gzip, deflate, br".parse().unwrap());
+        headers.insert("X-Requested-With", "XMLHttpRequest".parse().unwrap());
+        headers.insert("Connection", "keep-alive".parse().unwrap());
+        headers.insert("Referer", referer(url).parse().unwrap());
+        headers.insert(header::COOKIE, adsbx_sid().parse().unwrap());
+        headers.insert("Sec-Fetch-Dest", "empty".parse().unwrap());
+        headers.insert("Sec-Fetch-Mode", "cors".parse().unwrap());
+        headers.insert("Sec-Fetch-Site", "same-origin".parse().unwrap());
+        headers.insert("TE", "trailers".parse().unwrap());
+
+        let response = reqwest::Client::new()
+            .get(url)
+            .headers(headers)
+            .send()
+            .await
+            .map_err(crate::http::to_io_error)?;
+        crate::http::body(url, response).await
+    }
+}
+
+async fn globe_history(
+    icao: &str,
+    date: &time::Date,
+    fetcher: &dyn HttpFetcher,
+) -> Result<Vec<u8>, std::io::Error> {
     log::info!("globe_history({icao},{date})");
-    let referer =
-        format!("https://globe.adsbexchange.com/?icao={icao}&lat=54.448&lon=10.602&zoom=7.0");
     let url = to_url(icao, date);
 
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "User-Agent",
-        "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:109.0) Gecko/20100101 Firefox/118.0"
-            .parse()
-            .unwrap(),
-    );
-    headers.insert(
-        "Accept",
-        "application/json, text/javascript, */*; q=0.01"
-            .parse()
-            .unwrap(),
-    );
-    headers.insert("Accept-Language", "en-US,en;q=0.5".parse().unwrap());
-    headers.insert("Accept-Encoding", "gzip, deflate, br".parse().unwrap());
-    headers.insert("X-Requested-With", "XMLHttpRequest".parse().unwrap());
-    headers.insert("Connection", "keep-alive".parse().unwrap());
-    headers.insert("Referer", referer.parse().unwrap());
-    headers.insert(header::COOKIE, adsbx_sid().parse().unwrap());
-    headers.insert("Sec-Fetch-Dest", "empty".parse().unwrap());
-    headers.insert("Sec-Fetch-Mode", "cors".parse().unwrap());
-    headers.insert("Sec-Fetch-Site", "same-origin".parse().unwrap());
-    headers.insert("TE", "trailers".parse().unwrap());
-
-    // Retry up to 5 times with increasing intervals between attempts.
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(5);
-    let client = ClientBuilder::new(reqwest::Client::new())
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
-
-    let response = client
-        .get(url)
-        .headers(headers)
-        .send()
-        .await
-        .map_err(std::io::Error::other)?;
-    if response.status() == StatusCode::OK {
-        Ok(response
-            .bytes()
-            .await
-            .map_err(std::io::Error::other)?
-            .to_vec())
-    } else if response.status() == StatusCode::NOT_FOUND {
-        Ok(format!(
+    match fetcher.get_bytes(&url).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(format!(
             r#"{{
             "icao": "{icao}",
             "noRegData": true,
@@ -101,9 +116,8 @@ async fn globe_history(icao: &str, date: &time::Date) -> Result<Vec<u8>, std::io
             "trace": []
         }}"#
         )
-        .into_bytes())
-    } else {
-        Err(std::io::Error::other(response.text().await.map_err(std::io::Error::other)?).into())
+        .into_bytes()),
+        other => other,
     }
 }
 
@@ -115,107 +129,233 @@ pub async fn globe_history_cached(
     icao: &str,
     date: &time::Date,
     client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+) -> Result<Vec<u8>, std::io::Error> {
+    globe_history_cached_with(
+        icao,
+        date,
+        fs::CacheAction::from_date(date),
+        client,
+        fetcher,
+    )
+    .await
+}
+
+/// Like [`globe_history_cached`], using the cache according to `action`
+async fn globe_history_cached_with(
+    icao: &str,
+    date: &time::Date,
+    action: fs::CacheAction,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
 ) -> Result<Vec<u8>, std::io::Error> {
     let blob_name = cache_file_path(icao, date);
-    let action = fs::CacheAction::from_date(&date);
-    let fetch = globe_history(&icao, date);
+    let fetch = globe_history(icao, date, fetcher);
+
+    fs::cached_call(&blob_name, fetch, client, action).await
+}
+
+/// An error parsing a trace of adsbexchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceParseError {
+    /// The trace is not an object with a `trace` key
+    MissingTrace,
+    /// The `trace` of the trace is not an array
+    InvalidTrace,
+    /// The `timestamp` of the trace is not a valid unix timestamp
+    InvalidTimestamp,
+    /// The entry at this index of the trace is not an array with a numeric time, latitude and longitude
+    InvalidEntry(usize),
+    /// The trace has no positions once entries whose altitude is unknown are ignored
+    NoPositions,
+}
 
-    Ok(fs::cached_call(&blob_name, fetch, client, action).await?)
+impl std::fmt::Display for TraceParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingTrace => write!(f, "the trace has no `trace` key"),
+            Self::InvalidTrace => write!(f, "the `trace` of the trace is not an array"),
+            Self::InvalidTimestamp => write!(f, "the timestamp of the trace is invalid"),
+            Self::InvalidEntry(index) => write!(f, "the entry {index} of the trace is invalid"),
+            Self::NoPositions => write!(f, "the trace has no positions"),
+        }
+    }
+}
+
+impl std::error::Error for TraceParseError {}
+
+impl From<TraceParseError> for std::io::Error {
+    fn from(error: TraceParseError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
 }
 
-fn compute_trace(data: &[u8]) -> Result<(f64, Vec<serde_json::Value>), std::io::Error> {
+/// Returns the timestamp and the entries of a trace. An empty trace needs no timestamp.
+fn trace_entries(
+    value: &serde_json::Value,
+) -> Result<(f64, &[serde_json::Value]), TraceParseError> {
+    let trace = value
+        .as_object()
+        .and_then(|obj| obj.get("trace"))
+        .ok_or(TraceParseError::MissingTrace)?
+        .as_array()
+        .ok_or(TraceParseError::InvalidTrace)?;
+    if trace.is_empty() {
+        return Ok((0.0, trace));
+    }
+    let timestamp = value["timestamp"]
+        .as_f64()
+        .ok_or(TraceParseError::InvalidTimestamp)?;
+
+    Ok((timestamp, trace))
+}
+
+/// Returns the positions of a trace of adsbexchange (`{"timestamp": .., "trace": [..]}`), ordered as in the trace,
+/// ignoring entries whose altitude is unknown. A trace without a `trace` array has no positions, since
+/// adsbexchange serves them for days without data.
+/// This is the single parser of traces, used by [`positions`] and [`trace_cached`].
+/// # Error
+/// Errors when the timestamp or any entry of the trace is invalid
+pub fn parse_trace(value: &serde_json::Value) -> Result<Vec<Position>, TraceParseError> {
+    match trace_entries(value) {
+        Err(TraceParseError::MissingTrace | TraceParseError::InvalidTrace) => Ok(vec![]),
+        entries => {
+            let (timestamp, entries) = entries?;
+            compute_positions(timestamp, entries)
+        }
+    }
+}
+
+/// Returns the [`Leg`]s of a trace of adsbexchange identified with `options` (see [`crate::legs::legs_with`]),
+/// e.g. to skip a malformed trace when iterating many of them.
+/// # Error
+/// Unlike [`parse_trace`], errors when the trace has no `trace` array or no positions
+pub fn trace_legs(
+    value: &serde_json::Value,
+    options: LegOptions,
+) -> Result<Vec<Leg>, TraceParseError> {
+    let (timestamp, entries) = trace_entries(value)?;
+    let positions = compute_positions(timestamp, entries)?;
+    if positions.is_empty() {
+        return Err(TraceParseError::NoPositions);
+    }
+    Ok(crate::legs::legs_with(positions.into_iter(), options).collect())
+}
+
+/// Like [`parse_trace`], of the bytes of a trace; an empty blob has no positions
+fn parse_trace_bytes(data: &[u8]) -> Result<Vec<Position>, std::io::Error> {
     if data.is_empty() {
-        return Ok((0.0, vec![]));
+        return Ok(vec![]);
     }
-    let mut value = serde_json::from_slice::<serde_json::Value>(&data)?;
-    let Some(obj) = value.as_object_mut() else {
-        return Ok((0.0, vec![]));
-    };
-    let Some(timestamp) = obj.get("timestamp") else {
-        return Ok((0.0, vec![]));
-    };
-    let timestamp = timestamp.as_f64().unwrap();
-    let Some(obj) = obj.get_mut("trace") else {
-        return Ok((0.0, vec![]));
-    };
-    let Some(trace) = obj.as_array_mut() else {
-        return Ok((0.0, vec![]));
-    };
-
-    Ok((timestamp, std::mem::take(trace)))
+    let value = serde_json::from_slice::<serde_json::Value>(data)?;
+    Ok(parse_trace(&value)?)
 }
 
-/// Returns the trace of the icao number of a given day from https://adsbexchange.com.
-/// * `icao` must be lowercased
-/// * `date` must be a valid ISO8601 date in format `yyyy-mm-dd` and cannot be today.
+/// Returns the positions of the trace of the icao number of a given day from https://adsbexchange.com
+/// (see [`parse_trace`]). `icao` must be lowercased.
 ///
-/// The returned value is a vector where with the following by index
-/// * `0` is time in seconds since midnight (f64)
+/// Each entry of the trace is an array with the following by index
+/// * `0` is time in seconds since the timestamp of the trace (f64)
 /// * `1` is latitude (f64)
 /// * `2` is longitude (f64)
 /// * `3` is either Baro. Altitude in feet (f32) or "ground" (String)
 /// # Implementation
 /// Because these are historical values, this function caches them the first time it is used
-/// by the two arguments
-async fn trace_cached(
+/// by the two arguments (except for today, see [`globe_history_cached`])
+/// # Error
+/// Errors with [`std::io::ErrorKind::InvalidData`] (of a [`TraceParseError`]) when the trace is malformed
+pub async fn trace_cached(
     icao: &str,
     date: &time::Date,
     client: &dyn fs::BlobStorageProvider,
-) -> Result<(f64, Vec<serde_json::Value>), std::io::Error> {
-    compute_trace(&globe_history_cached(icao, date, client).await?)
+    fetcher: &dyn HttpFetcher,
+) -> Result<Vec<Position>, std::io::Error> {
+    parse_trace_bytes(&globe_history_cached(icao, date, client, fetcher).await?)
+}
+
+/// The altitude of an entry of a trace
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AltitudeReading {
+    /// The aircraft reported being on the ground
+    Ground,
+    /// The barometric altitude in feet. It may be negative (e.g. near airports below sea level or
+    /// with a high atmospheric pressure), and the aircraft is nonetheless flying.
+    Baro(f64),
+    /// No (or an unrecognized) altitude, e.g. `null`
+    Unknown,
+}
+
+/// Returns the [`AltitudeReading`] of the altitude column of an entry of a trace.
+/// Whether the aircraft is on the ground is taken from the ground flag of ADS-B, not from the altitude.
+fn parse_altitude(value: &serde_json::Value) -> AltitudeReading {
+    match value {
+        serde_json::Value::String(x) if x == "ground" => AltitudeReading::Ground,
+        serde_json::Value::Number(x) => x
+            .as_f64()
+            .map(AltitudeReading::Baro)
+            .unwrap_or(AltitudeReading::Unknown),
+        _ => AltitudeReading::Unknown,
+    }
 }
 
-fn compute_positions(start_trace: (f64, Vec<serde_json::Value>)) -> impl Iterator<Item = Position> {
+/// Returns the positions of a trace, ignoring entries whose altitude is [`AltitudeReading::Unknown`].
+/// # Error
+/// Errors when the timestamp or any entry of the trace is invalid
+fn compute_positions(
+    start: f64,
+    trace: &[serde_json::Value],
+) -> Result<Vec<Position>, TraceParseError> {
     use time::ext::NumericalDuration;
 
-    let (start, trace) = start_trace;
-    let start = OffsetDateTime::from_unix_timestamp(start as i64).unwrap();
-
-    trace.into_iter().filter_map(move |entry| {
-        let delta = entry[0].as_f64().unwrap().seconds();
-        let datetime = start + delta;
-        let latitude = entry[1].as_f64().unwrap();
-        let longitude = entry[2].as_f64().unwrap();
-        entry[3]
-            .as_str()
-            .and_then(|x| {
-                (x == "ground").then_some(Position {
-                    datetime,
-                    latitude,
-                    longitude,
-                    altitude: None,
-                })
-            })
-            .or_else(|| {
-                entry[3].as_f64().and_then(|altitude| {
-                    Some(Position {
-                        datetime,
-                        latitude,
-                        longitude,
-                        altitude: Some(altitude),
-                    })
-                })
-            })
-    })
+    let start = OffsetDateTime::from_unix_timestamp(start as i64)
+        .map_err(|_| TraceParseError::InvalidTimestamp)?;
+
+    let mut positions = Vec::with_capacity(trace.len());
+    for (index, entry) in trace.iter().enumerate() {
+        let invalid = || TraceParseError::InvalidEntry(index);
+        let delta = entry[0].as_f64().ok_or_else(invalid)?.seconds();
+        let datetime = start.checked_add(delta).ok_or_else(invalid)?;
+        let latitude = entry[1].as_f64().ok_or_else(invalid)?;
+        let longitude = entry[2].as_f64().ok_or_else(invalid)?;
+        let altitude = match parse_altitude(&entry[3]) {
+            AltitudeReading::Ground => None,
+            AltitudeReading::Baro(altitude) => Some(altitude),
+            AltitudeReading::Unknown => continue,
+        };
+        positions.push(Position {
+            datetime,
+            latitude,
+            longitude,
+            altitude,
+        });
+    }
+    Ok(positions)
 }
 
 /// Returns an iterator of [`Position`] over the trace of `icao` on day `date` according
 /// to the [methodology `M-daily-adsb`](../methodology.md).
+/// # Error
+/// Errors with [`std::io::ErrorKind::InvalidData`] (of a [`TraceParseError`]) when the trace is malformed
 pub async fn positions(
     icao_number: &str,
     date: time::Date,
     client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
 ) -> Result<impl Iterator<Item = Position>, std::io::Error> {
-    trace_cached(icao_number, &date, client)
-        .await
-        .map(compute_positions)
+    Ok(trace_cached(icao_number, &date, client, fetcher)
+        .await?
+        .into_iter())
 }
 
+/// Returns the positions of each day between `from` (inclusive) and `to` (exclusive), like [`positions`].
+/// Traces of days before `today` are complete and are cached.
 pub(crate) fn cached_aircraft_positions<'a>(
     icao_number: &'a str,
     from: Date,
     to: Date,
+    today: Date,
     client: &'a dyn fs::BlobStorageProvider,
+    fetcher: &'a dyn HttpFetcher,
 ) -> impl Iterator<
     Item = impl futures::future::Future<Output = Result<Vec<Position>, std::io::Error>> + 'a,
 > + 'a {
@@ -225,10 +365,9 @@ pub(crate) fn cached_aircraft_positions<'a>(
         increment: time::Duration::days(1),
     }
     .map(move |date| async move {
-        Result::<_, std::io::Error>::Ok(
-            positions(icao_number, date, client)
-                .await?
-                .collect::<Vec<_>>(),
+        let action = fs::CacheAction::from_date_at(&date, today);
+        parse_trace_bytes(
+            &globe_history_cached_with(icao_number, &date, action, client, fetcher).await?,
         )
     })
 }
@@ -244,30 +383,256 @@ mod test {
     /// Compare against https://globe.adsbexchange.com/?icao=45860d&showTrace=2019-01-04&leg=1
     #[tokio::test]
     async fn work() {
-        let data = globe_history("45860d", &date!(2019 - 01 - 04))
+        let data = globe_history("45860d", &date!(2019 - 01 - 04), &AdsbExchange)
             .await
             .unwrap();
-        let first = compute_positions(compute_trace(&data).unwrap())
-            .next()
-            .unwrap();
+        let first = parse_trace_bytes(&data).unwrap()[0].clone();
         assert_eq!(first.datetime.hour(), 6);
         assert_eq!(first.datetime.minute(), 54);
-        assert_eq!(first.grounded(), true);
+        assert!(first.grounded());
+    }
+
+    struct Fixture;
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for Fixture {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            assert!(url.ends_with("trace_full_45860d.json"));
+            std::fs::read("tests/fixtures/trace_full_45860d.json")
+        }
+    }
+
+    struct NotFound;
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for NotFound {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            Err(std::io::Error::new(std::io::ErrorKind::NotFound, url))
+        }
+    }
+
+    #[tokio::test]
+    async fn mock_fetcher() {
+        let data = globe_history("45860d", &date!(2019 - 01 - 04), &Fixture)
+            .await
+            .unwrap();
+        let positions = parse_trace_bytes(&data).unwrap();
+        assert_eq!(positions.len(), 6);
+        assert_eq!(positions[0].datetime.hour(), 6);
+        assert_eq!(positions[0].datetime.minute(), 54);
+        assert!(positions[0].grounded());
+        assert!(positions[2].flying());
+
+        let data = globe_history("45860d", &date!(2019 - 01 - 04), &NotFound)
+            .await
+            .unwrap();
+        assert_eq!(parse_trace_bytes(&data).unwrap().len(), 0);
     }
 
     #[tokio::test]
     async fn edge_cases() {
         // https://globe.adsbexchange.com/globe_history/2022/10/21/traces/23/trace_full_a7e823.json
-        assert_eq!(compute_trace(b"").unwrap().1.len(), 0);
-        assert_eq!(compute_trace(b"[]").unwrap().1.len(), 0);
-        assert_eq!(compute_trace(b"{}").unwrap().1.len(), 0);
-        assert_eq!(compute_trace(b"{\"timestamp\": 1.0}").unwrap().1.len(), 0);
+        assert_eq!(parse_trace_bytes(b"").unwrap().len(), 0);
+        assert_eq!(parse_trace_bytes(b"[]").unwrap().len(), 0);
+        assert_eq!(parse_trace_bytes(b"{}").unwrap().len(), 0);
+        assert_eq!(parse_trace_bytes(b"{\"timestamp\": 1.0}").unwrap().len(), 0);
         assert_eq!(
-            compute_trace(b"{\"timestamp\": 1.0, \"trace\": {}}")
+            parse_trace_bytes(b"{\"timestamp\": 1.0, \"trace\": {}}")
                 .unwrap()
-                .1
                 .len(),
             0
         );
     }
+
+    #[test]
+    fn altitude() {
+        use serde_json::json;
+        assert_eq!(parse_altitude(&json!("ground")), AltitudeReading::Ground);
+        assert_eq!(parse_altitude(&json!(950.0)), AltitudeReading::Baro(950.0));
+        assert_eq!(parse_altitude(&json!(2000)), AltitudeReading::Baro(2000.0));
+        assert_eq!(parse_altitude(&json!(-75.0)), AltitudeReading::Baro(-75.0));
+        assert_eq!(parse_altitude(&json!(null)), AltitudeReading::Unknown);
+        assert_eq!(parse_altitude(&json!("air")), AltitudeReading::Unknown);
+
+        // unknown altitudes are skipped
+        let trace = json!([
+            [0.0, 1.0, 2.0, "ground"],
+            [1.0, 1.0, 2.0, null],
+            [2.0, 1.0, 2.0, -75.0]
+        ]);
+        let positions = compute_positions(0.0, trace.as_array().unwrap()).unwrap();
+        assert_eq!(positions.len(), 2);
+        assert!(positions[0].grounded());
+        assert_eq!(positions[1].altitude, Some(-75.0));
+    }
+
+    /// The columns 1 and 2 of traces are latitude and longitude: a leg
+    /// from Copenhagen (55.6, 12.6) to Aalborg (57.1, 9.8)
+    #[test]
+    fn lat_lon_convention() {
+        let data = br#"{"timestamp": 1546560000.0, "trace": [
+            [0.0, 55.618, 12.656, "ground"],
+            [240.0, 55.8, 12.2, 8000],
+            [480.0, 56.2, 11.4, 20000],
+            [1080.0, 56.8, 10.4, 20000],
+            [1320.0, 57.0, 10.0, 8000],
+            [1560.0, 57.093, 9.849, "ground"]
+        ]}"#;
+        let positions = parse_trace_bytes(data).unwrap();
+        let legs = crate::legs::legs(positions.into_iter()).collect::<Vec<_>>();
+        assert_eq!(legs.len(), 1);
+
+        let (from, to) = (legs[0].from(), legs[0].to());
+        assert!((from.latitude() - 55.618).abs() < 0.01);
+        assert!((from.longitude() - 12.656).abs() < 0.01);
+        assert!((to.latitude() - 57.093).abs() < 0.01);
+        assert!((to.longitude() - 9.849).abs() < 0.01);
+        assert_eq!(from.pos().latitude, from.latitude());
+        // Copenhagen to Aalborg is ~240 km; swapped, it would be ~350 km
+        assert!((legs[0].great_circle_distance() - 240.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn absolute_time() {
+        // the time of an entry is the timestamp of the trace plus its offset in seconds
+        let data = std::fs::read("tests/fixtures/trace_full_45860d.json").unwrap();
+        let positions = parse_trace_bytes(&data).unwrap();
+        assert_eq!(
+            positions[0].datetime(),
+            time::macros::datetime!(2019-01-04 06:54:00 UTC)
+        );
+        assert_eq!(
+            positions[2].datetime() - positions[0].datetime(),
+            time::Duration::seconds(180)
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let parse = parse_trace_bytes;
+
+        // days without positions
+        assert_eq!(
+            parse(b"{\"timestamp\": 1.0, \"trace\": []}").unwrap().len(),
+            0
+        );
+        assert_eq!(parse(b"{}").unwrap().len(), 0);
+
+        let error: std::io::Result<_> = parse(
+            b"{\"timestamp\": 1.0, \"trace\": [[0.0, 1.0, 2.0, \"ground\"], [1.0, \"1.0\", 2.0, \"ground\"]]}",
+        );
+        let error = error.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            error
+                .into_inner()
+                .unwrap()
+                .downcast::<TraceParseError>()
+                .ok(),
+            Some(Box::new(TraceParseError::InvalidEntry(1)))
+        );
+
+        let error = parse_trace_bytes(b"{\"timestamp\": \"a\", \"trace\": [[0.0, 1.0, 2.0, 3.0]]}")
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn trace_legs_errors() {
+        use serde_json::json;
+        let legs = |value| trace_legs(&value, LegOptions::default());
+
+        assert_eq!(legs(json!({})), Err(TraceParseError::MissingTrace));
+        assert_eq!(
+            legs(json!({"timestamp": 1.0, "trace": {}})),
+            Err(TraceParseError::InvalidTrace)
+        );
+        assert_eq!(
+            legs(json!({"timestamp": 1.0, "trace": [[0.0, "1.0", 2.0, "ground"]]})),
+            Err(TraceParseError::InvalidEntry(0))
+        );
+        // empty, or empty once entries of unknown altitude are ignored
+        assert_eq!(
+            legs(json!({"trace": []})),
+            Err(TraceParseError::NoPositions)
+        );
+        assert_eq!(
+            legs(json!({"timestamp": 1.0, "trace": [[0.0, 1.0, 2.0, null]]})),
+            Err(TraceParseError::NoPositions)
+        );
+
+        let data = std::fs::read("tests/fixtures/trace_full_45860d.json").unwrap();
+        let value = serde_json::from_slice::<serde_json::Value>(&data).unwrap();
+        let positions = parse_trace(&value).unwrap();
+        assert_eq!(
+            legs(value).unwrap(),
+            crate::legs::legs(positions.into_iter()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn same_positions() {
+        let data = std::fs::read("tests/fixtures/trace_full_45860d.json").unwrap();
+        let value = serde_json::from_slice::<serde_json::Value>(&data).unwrap();
+        let parsed = parse_trace(&value).unwrap();
+
+        let date = date!(2019 - 01 - 04);
+        let cached = trace_cached("45860d", &date, &fs::InMemory::default(), &Fixture)
+            .await
+            .unwrap();
+        let iterated = positions("45860d", date, &fs::InMemory::default(), &Fixture)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(parsed.len(), 6);
+        assert_eq!(parsed, cached);
+        assert_eq!(parsed, iterated);
+    }
+
+    #[tokio::test]
+    async fn aircraft_legs_cached() {
+        let (from, to) = (date!(2019 - 01 - 04), date!(2019 - 01 - 05));
+        let client = fs::InMemory::default();
+        let expected = aircraft_positions(from, to, "45860d", &client, &Fixture, 1)
+            .await
+            .unwrap();
+        let expected = crate::legs::legs(expected.into_iter()).collect::<Vec<_>>();
+
+        let legs = aircraft_legs(from, to, "45860d", &client, &Fixture, 1)
+            .await
+            .unwrap();
+        assert_eq!(legs, expected);
+        // served from the cache
+        let legs = aircraft_legs(from, to, "45860d", &client, &NotFound, 1)
+            .await
+            .unwrap();
+        assert_eq!(legs, expected);
+
+        // with options, e.g. smoothing the altitude
+        let positions = aircraft_positions(from, to, "45860d", &client, &NotFound, 1)
+            .await
+            .unwrap();
+        let smoothed = crate::legs::smooth_altitude(positions, 3);
+        let expected = crate::legs::legs(smoothed.into_iter()).collect::<Vec<_>>();
+        let options = AircraftLegsOptions {
+            smooth_window: Some(3),
+            ..Default::default()
+        };
+        let legs = aircraft_legs_with(from, to, "45860d", &client, &NotFound, &options)
+            .await
+            .unwrap();
+        assert_eq!(legs, expected);
+    }
+
+    #[test]
+    fn referer_of_trace() {
+        assert_eq!(
+            referer(&to_url("45860d", &date!(2019 - 01 - 04))),
+            "https://globe.adsbexchange.com/?icao=45860d&lat=54.448&lon=10.602&zoom=7.0"
+        );
+        assert_eq!(
+            referer("https://globe.adsbexchange.com/other.json"),
+            "https://globe.adsbexchange.com/"
+        );
+    }
 }
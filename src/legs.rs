@@ -1,4 +1,89 @@
-use crate::Position;
+use crate::{
+    airports::{Airport, Airports},
+    geo::BoundingBox,
+    LatLon, Position,
+};
+
+/// The maximum average speed (km/h) of a plausible leg. Legs above it are the result of
+/// ADS-B glitches (e.g. a position thousands of km away from its neighbours)
+pub static MAX_PLAUSIBLE_SPEED: f64 = 1300.0;
+
+/// The altitude (feet) below which a position is close to the ground, where ADS-B receivers
+/// often miss signals. Exclusive: a position at exactly this altitude is not close to the ground.
+/// An aircraft that was not close to the ground is only again once it descends below this altitude
+/// minus [`LOW_ALTITUDE_HYSTERESIS`].
+///
+/// Whether a position is on the ground is not derived from its altitude but from the ground flag
+/// of the ADS-B event (see [`Position::grounded`]), so there is no threshold on which it may flap.
+pub static LOW_ALTITUDE: f64 = 10000.0;
+
+/// The altitude (feet) an aircraft must descend below [`LOW_ALTITUDE`] to be close to the ground again,
+/// so that noisy altitudes around it (e.g. 9999.9, 10000.1, ...) do not flap between the two
+pub static LOW_ALTITUDE_HYSTERESIS: f64 = 500.0;
+
+/// The maximum time between a [`Leg`] ending airborne and the next starting airborne for them to be
+/// [stitched](stitch), since positions are contiguous across the boundary of consecutive months
+pub static MAX_STITCH_GAP: time::Duration = time::Duration::minutes(30);
+
+/// The version of the methodology to identify legs and compute their metrics (e.g. emissions),
+/// stamped in the `methodology` column of the legs stored in the database.
+/// It must be increased whenever the methodology changes, so that stored legs computed with an
+/// older version are invalidated instead of mixed with newer ones.
+pub static METHODOLOGY_VERSION: u32 = 5;
+
+/// The options to identify [`Leg`]s by [`legs_with`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LegOptions {
+    /// The altitude (feet) above the field below which a position is close to the ground (see [`LOW_ALTITUDE`])
+    pub low_altitude: f64,
+    /// The elevation (feet) of the fields the aircraft operates from, so that [`LegOptions::low_altitude`]
+    /// is relative to them (e.g. ~7800 for Aspen) instead of to the sea level
+    pub field_elevation: f64,
+    /// The altitude (feet) below [`LegOptions::low_altitude`] an aircraft must descend to be close to the
+    /// ground again (see [`LOW_ALTITUDE_HYSTERESIS`])
+    pub low_altitude_hysteresis: f64,
+    /// The speed (km/h) above which a position is a glitch and is ignored (see [`filter_glitches`])
+    pub max_speed: f64,
+}
+
+impl Default for LegOptions {
+    fn default() -> Self {
+        Self {
+            low_altitude: LOW_ALTITUDE,
+            field_elevation: 0.0,
+            low_altitude_hysteresis: LOW_ALTITUDE_HYSTERESIS,
+            max_speed: MAX_PLAUSIBLE_SPEED,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct Stamp {
+    #[serde(default)]
+    methodology: Option<u32>,
+}
+
+/// Returns the CSV of no legs (e.g. of a month the aircraft did not fly), stamped with [`METHODOLOGY_VERSION`]
+/// in its header since it has no rows with a `methodology` column (see [`is_current_methodology`]).
+pub fn empty_legs_csv() -> Vec<u8> {
+    format!("methodology={METHODOLOGY_VERSION}\n").into_bytes()
+}
+
+/// Returns whether all legs in `csv` (with a `methodology` column) were computed with
+/// [`METHODOLOGY_VERSION`], or whether `csv` is the [`empty_legs_csv`] of it. Any other CSV without legs,
+/// or legs without the column, are not, since it is unknown which version computed them.
+pub fn is_current_methodology(csv: &[u8]) -> bool {
+    let mut empty = true;
+    let current = crate::csv::deserialize::<Stamp>(csv).all(|stamp| {
+        empty = false;
+        stamp.is_ok_and(|stamp| stamp.methodology == Some(METHODOLOGY_VERSION))
+    });
+    if empty {
+        csv == empty_legs_csv()
+    } else {
+        current
+    }
+}
 
 /// Represents a leg, also known as a [non-stop flight](https://en.wikipedia.org/wiki/Non-stop_flight)
 /// between two positions.
@@ -10,43 +95,246 @@ pub struct Leg {
 }
 
 impl Leg {
+    pub(crate) fn new(positions: Vec<Position>) -> Self {
+        Self { positions }
+    }
+
     /// Positions of the leg
     pub fn positions(&self) -> &[Position] {
         &self.positions
     }
 
-    /// Leg geo distance in km
+    /// The great-circle distance in km between the start and end of the leg
+    pub fn direct_distance_km(&self) -> f64 {
+        self.from().distace(self.to())
+    }
+
+    /// The total two-dimensional length in km of the track of the leg, i.e. the sum of the great-circle
+    /// distances between its consecutive positions. Never shorter than [`Leg::direct_distance_km`].
+    pub fn track_distance_km(&self) -> f64 {
+        self.positions.windows(2).map(|w| w[0].distace(&w[1])).sum()
+    }
+
+    /// Alias of [`Leg::direct_distance_km`]
     pub fn great_circle_distance(&self) -> f64 {
-        self.from().distace(&self.to())
+        self.direct_distance_km()
     }
 
-    /// The total two-dimensional length of the leg in km
+    /// Alias of [`Leg::track_distance_km`], the distance used in reports
     pub fn distance(&self) -> f64 {
-        self.positions.windows(2).map(|w| w[0].distace(&w[1])).sum()
+        self.track_distance_km()
+    }
+
+    /// The time of the first position of the leg
+    pub fn start(&self) -> time::OffsetDateTime {
+        self.from().datetime()
+    }
+
+    /// The time of the last position of the leg
+    pub fn end(&self) -> time::OffsetDateTime {
+        self.to().datetime()
     }
 
     /// Leg duration
     pub fn duration(&self) -> time::Duration {
-        self.to().datetime() - self.from().datetime()
+        self.end() - self.start()
+    }
+
+    /// The time in hours flown above `altitude` (in feet), i.e. between consecutive positions both above it
+    pub fn hours_above(&self, altitude: f64) -> f64 {
+        self.positions
+            .windows(2)
+            .filter(|w| {
+                w[0].altitude().unwrap_or(0.0) > altitude
+                    && w[1].altitude().unwrap_or(0.0) > altitude
+            })
+            .map(|w| (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0)
+            .sum()
+    }
+
+    /// The fraction of the duration of the leg flown above `altitude` (in feet), between 0 and 1.
+    /// E.g. above ~26.000 feet, where contrails form, as a proxy of its non-CO2 climate impact.
+    /// Zero when the leg has no duration.
+    pub fn fraction_above(&self, altitude: f64) -> f64 {
+        let hours = self.duration().as_seconds_f64() / 60.0 / 60.0;
+        if hours > 0.0 {
+            self.hours_above(altitude) / hours
+        } else {
+            0.0
+        }
+    }
+
+    /// The ground speed in km/h between each pair of consecutive positions (see [`Position::speed`]),
+    /// i.e. one less than the number of positions. Pairs at the same time are skipped.
+    pub fn speeds(&self) -> Vec<f64> {
+        self.positions
+            .windows(2)
+            .filter_map(|w| w[1].speed(&w[0]))
+            .collect()
+    }
+
+    /// The maximum of [`Leg::speeds`] in km/h, e.g. to spot glitches that inflate its distance
+    pub fn max_speed(&self) -> Option<f64> {
+        self.speeds().into_iter().reduce(f64::max)
+    }
+
+    /// The average speed in km/h of the leg, i.e. its [`Leg::distance`] over its [`Leg::duration`],
+    /// or `None` when it has no duration
+    pub fn avg_speed(&self) -> Option<f64> {
+        let hours = self.duration().as_seconds_f64() / 60.0 / 60.0;
+        (hours > 0.0).then(|| self.distance() / hours)
     }
 
+    /// Whether the leg is plausible, i.e. its average speed is below [`MAX_PLAUSIBLE_SPEED`]
+    pub fn plausible(&self) -> bool {
+        self.avg_speed()
+            .is_some_and(|speed| speed < MAX_PLAUSIBLE_SPEED)
+    }
+
+    /// Whether the first position of the leg is flying, i.e. the take-off was not observed
+    /// (e.g. the trace starts mid-air, such as at the start of a month)
+    pub fn starts_airborne(&self) -> bool {
+        self.from().flying()
+    }
+
+    /// Whether the last position of the leg is flying, i.e. the landing was not observed
+    /// (e.g. the trace ends mid-air, such as at the end of a month, or the signal was lost)
+    pub fn ends_airborne(&self) -> bool {
+        self.to().flying()
+    }
+
+    /// Whether the leg is open-ended, i.e. it [starts](Leg::starts_airborne) or [ends](Leg::ends_airborne) airborne
+    pub fn partial(&self) -> bool {
+        self.starts_airborne() || self.ends_airborne()
+    }
+
+    /// The first position of the leg, e.g. where it took off. Never after [`Leg::to`].
+    /// ```
+    /// // positions as stored in the database
+    /// let positions: Vec<flights::Position> = serde_json::from_str(r#"[
+    ///     {"datetime": "2024-01-01T10:00:00Z", "latitude": 55.618, "longitude": 12.656},
+    ///     {"datetime": "2024-01-01T10:04:00Z", "latitude": 55.9, "longitude": 12.1, "altitude": 20000.0},
+    ///     {"datetime": "2024-01-01T10:08:00Z", "latitude": 56.6, "longitude": 10.8, "altitude": 20000.0},
+    ///     {"datetime": "2024-01-01T10:12:00Z", "latitude": 57.093, "longitude": 9.849}
+    /// ]"#).unwrap();
+    ///
+    /// let leg = flights::legs::legs(positions.into_iter()).next().unwrap();
+    /// assert_eq!(leg.from().pos().latitude, 55.618);
+    /// assert_eq!(leg.to().pos().longitude, 9.849);
+    /// assert!(leg.from().datetime() <= leg.to().datetime());
+    /// ```
     pub fn from(&self) -> &Position {
         self.positions.first().unwrap()
     }
 
+    /// The last position of the leg, e.g. where it landed. Never before [`Leg::from`].
     pub fn to(&self) -> &Position {
         self.positions.last().unwrap()
     }
+
+    /// Returns the nearest airport of `airports` to the start of the leg within `radius` km (e.g. [`DEFAULT_RADIUS`](crate::airports::DEFAULT_RADIUS)).
+    /// `None` when there is none, e.g. the leg [starts airborne](Leg::starts_airborne)
+    pub fn from_airport<'a>(&self, airports: &'a Airports, radius: f64) -> Option<&'a Airport> {
+        airports.nearest(self.from().pos(), radius)
+    }
+
+    /// Returns the nearest airport of `airports` to the end of the leg within `radius` km (e.g. [`DEFAULT_RADIUS`](crate::airports::DEFAULT_RADIUS)).
+    /// `None` when there is none, e.g. the leg [ends airborne](Leg::ends_airborne)
+    pub fn to_airport<'a>(&self, airports: &'a Airports, radius: f64) -> Option<&'a Airport> {
+        airports.nearest(self.to().pos(), radius)
+    }
+
+    /// Returns whether any position of the leg is inside `bbox`
+    pub fn intersects_box(&self, bbox: &BoundingBox) -> bool {
+        self.positions
+            .iter()
+            .any(|position| bbox.contains(position.pos()))
+    }
+
+    /// Returns the countries of the start and end of the leg according to `resolve`
+    pub fn route_countries<C>(
+        &self,
+        resolve: impl Fn(LatLon) -> Option<C>,
+    ) -> (Option<C>, Option<C>) {
+        (resolve(self.from().pos()), resolve(self.to().pos()))
+    }
+}
+
+/// Returns the leg resulting from joining `first` with the `second` that follows it, when `first`
+/// [ends airborne](Leg::ends_airborne) and `second` [starts airborne](Leg::starts_airborne) without
+/// the aircraft having landed in between (see [M-identify-legs](../methodology.md)) and within [`MAX_STITCH_GAP`],
+/// e.g. to join the legs of consecutive months of a flight spanning their boundary.
+/// `options` must be those with which the legs were identified (see [`legs_with`]), so that
+/// whether the aircraft landed in between is decided as when identifying them.
+pub fn stitch(first: &Leg, second: &Leg, options: &LegOptions) -> Option<Leg> {
+    // replay `first` so that the hysteresis of `close_to_ground` is as when identifying it
+    let previous = first
+        .positions()
+        .iter()
+        .fold(true, |was, p| close_to_ground(p.altitude(), was, options));
+    let close = CloseToGround {
+        previous,
+        current: close_to_ground(second.from().altitude(), previous, options),
+    };
+    let joinable = first.ends_airborne()
+        && second.starts_airborne()
+        && first.end() <= second.start()
+        && second.start() - first.end() <= MAX_STITCH_GAP
+        && !landed(first.to(), second.from(), close);
+    joinable.then(|| Leg::new([first.positions(), second.positions()].concat()))
+}
+
+/// Returns `legs` (sorted by time) with consecutive legs [stitched](stitch) when possible,
+/// e.g. to join the legs computed per month (as stored in the database) of flights spanning two months.
+/// `options` must be those with which the legs were identified (see [`stitch`]).
+pub fn stitch_legs(legs: &[Leg], options: &LegOptions) -> Vec<Leg> {
+    let mut stitched: Vec<Leg> = Vec::with_capacity(legs.len());
+    for leg in legs {
+        match stitched.last().and_then(|last| stitch(last, leg, options)) {
+            Some(joined) => *stitched.last_mut().expect("last to exist") = joined,
+            None => stitched.push(leg.clone()),
+        }
+    }
+    stitched
+}
+
+/// Returns a predicate of whether a [`Leg`] lasts at least `duration`, e.g. to filter out
+/// legs that are trace glitches with `legs.filter(min_duration(time::Duration::minutes(10)))`
+pub fn min_duration(duration: time::Duration) -> impl Fn(&Leg) -> bool {
+    move |leg| leg.duration() >= duration
 }
 
-fn grounded_heuristic(previous_position: &Position, position: &Position) -> bool {
+/// Returns whether a position at `altitude` is close to the ground (see [`LegOptions::low_altitude`]),
+/// given whether the previous position was (`was_close`), with hysteresis: an aircraft that was not close to
+/// the ground is only once it descends below [`LegOptions::low_altitude_hysteresis`] under the threshold.
+fn close_to_ground(altitude: Option<f64>, was_close: bool, options: &LegOptions) -> bool {
+    let low_altitude = options.field_elevation + options.low_altitude;
+    match altitude {
+        None => true,
+        Some(altitude) if was_close => altitude < low_altitude,
+        Some(altitude) => altitude < low_altitude - options.low_altitude_hysteresis,
+    }
+}
+
+/// Whether two consecutive positions are close to the ground (see [`close_to_ground`])
+#[derive(Debug, Clone, Copy)]
+struct CloseToGround {
+    previous: bool,
+    current: bool,
+}
+
+fn grounded_heuristic(
+    previous_position: &Position,
+    position: &Position,
+    close: CloseToGround,
+) -> bool {
     let is_flying = previous_position.flying() || position.flying();
     if !is_flying {
         return false;
     }
     let lost_close_to_ground = position.datetime() - previous_position.datetime()
         > time::Duration::minutes(5)
-        && (position.altitude() < 10000.0 || previous_position.altitude() < 10000.0);
+        && (close.current || close.previous);
 
     // lost signal for more than 10h => assume it landed somewhere
     let lost_somewhere =
@@ -56,35 +344,56 @@ fn grounded_heuristic(previous_position: &Position, position: &Position) -> bool
 }
 
 /// Implementation of the definition of landed in [M-identify-legs](../methodology.md).
-fn landed(previous_position: &Position, position: &Position) -> bool {
+fn landed(previous_position: &Position, position: &Position, close: CloseToGround) -> bool {
     (previous_position.flying() && position.grounded())
-        || grounded_heuristic(previous_position, position)
+        || grounded_heuristic(previous_position, position, close)
 }
 
-fn is_grounded(previous_position: &Position, position: &Position) -> bool {
+fn is_grounded(previous_position: &Position, position: &Position, close: CloseToGround) -> bool {
     (previous_position.grounded() && position.grounded())
-        || grounded_heuristic(previous_position, position)
+        || grounded_heuristic(previous_position, position, close)
+}
+
+/// The state of [`Legs`] between two positions
+#[derive(Debug)]
+enum State {
+    /// The aircraft is on the ground (or its last leg was closed)
+    OnGround,
+    /// The aircraft is flying the leg of the positions so far
+    Airborne(Vec<Position>),
 }
 
 /// Iterator returning [`Leg`] computed according to the [methodology `M-identify-legs`](../methodology.md).
 pub struct Legs<I: Iterator<Item = Position>> {
     positions: I,
     previous_position: Position,
-    sequence: Vec<Position>,
+    /// Whether `previous_position` is close to the ground (see [`close_to_ground`])
+    close_to_ground: bool,
+    state: State,
+    options: LegOptions,
 }
 
 impl<I: Iterator<Item = Position>> Legs<I> {
-    fn new(mut positions: I) -> Self {
+    #[cfg(test)]
+    fn new(positions: I) -> Self {
+        Self::with(positions, LegOptions::default())
+    }
+
+    fn with(mut positions: I, options: LegOptions) -> Self {
         let previous_position = positions.next().unwrap_or(Position {
             datetime: time::OffsetDateTime::from_unix_timestamp(0).unwrap(),
             latitude: 0.0,
             longitude: 0.0,
             altitude: None,
         });
+        // the first position is classified by the threshold alone, as if the aircraft came from the ground
+        let close_to_ground = close_to_ground(previous_position.altitude(), true, &options);
         Self {
             positions,
-            sequence: vec![],
+            state: State::OnGround,
             previous_position,
+            close_to_ground,
+            options,
         }
     }
 }
@@ -93,34 +402,145 @@ impl<I: Iterator<Item = Position>> Iterator for Legs<I> {
     type Item = Leg;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(position) = self.positions.next() {
-            if !is_grounded(&self.previous_position, &position) {
-                // it is flying -> add it to the sequence
-                if self.sequence.is_empty() {
-                    self.sequence.push(self.previous_position.clone());
+        for position in self.positions.by_ref() {
+            let previous = std::mem::replace(&mut self.previous_position, position.clone());
+            let close = CloseToGround {
+                previous: self.close_to_ground,
+                current: close_to_ground(position.altitude(), self.close_to_ground, &self.options),
+            };
+            self.close_to_ground = close.current;
+            let flying = !is_grounded(&previous, &position, close);
+            let landed = landed(&previous, &position, close);
+
+            let (state, leg) = match std::mem::replace(&mut self.state, State::OnGround) {
+                // took off (or the trace starts airborne) and landed right after
+                State::OnGround if flying && landed => {
+                    (State::OnGround, Some(vec![previous, position]))
                 }
-                self.sequence.push(position.clone());
-            }
-            if landed(&self.previous_position, &position) {
-                if !self.sequence.is_empty() {
-                    self.previous_position = position;
-                    return Some(Leg {
-                        positions: std::mem::take(&mut self.sequence),
-                    });
-                };
+                // took off (or the trace starts airborne)
+                State::OnGround if flying => (State::Airborne(vec![previous, position]), None),
+                State::OnGround => (State::OnGround, None),
+                State::Airborne(mut sequence) => {
+                    if flying {
+                        sequence.push(position);
+                    }
+                    if landed {
+                        (State::OnGround, Some(sequence))
+                    } else {
+                        (State::Airborne(sequence), None)
+                    }
+                }
+            };
+            self.state = state;
+            if let Some(leg) = leg {
+                return Some(Leg::new(leg));
             }
-            self.previous_position = position;
         }
-        (!self.sequence.is_empty()).then_some(Leg {
-            positions: std::mem::take(&mut self.sequence),
+        // the trace ends airborne
+        match std::mem::replace(&mut self.state, State::OnGround) {
+            State::Airborne(sequence) => Some(Leg::new(sequence)),
+            State::OnGround => None,
+        }
+    }
+}
+
+/// Returns `positions` with their altitude replaced by the (lower) median altitude of the `window`
+/// positions centered on it, where the ground counts as zero altitude.
+/// A position whose median is zero becomes grounded, so that isolated noisy readings near the ground
+/// (e.g. a ground flag flapping while taxiing) do not result in spurious legs when passed to [`legs`].
+/// A `window` of 0 or 1 returns `positions` unchanged.
+pub fn smooth_altitude(positions: Vec<Position>, window: usize) -> Vec<Position> {
+    let half = window / 2;
+    let altitudes = positions
+        .iter()
+        .map(|p| p.altitude().unwrap_or(0.0))
+        .collect::<Vec<_>>();
+    positions
+        .into_iter()
+        .enumerate()
+        .map(|(i, mut position)| {
+            let mut neighbours =
+                altitudes[i.saturating_sub(half)..(i + half + 1).min(altitudes.len())].to_vec();
+            neighbours.sort_unstable_by(f64::total_cmp);
+            let median = neighbours[(neighbours.len() - 1) / 2];
+            position.altitude = (median > 0.0).then_some(median);
+            position
         })
+        .collect()
+}
+
+/// Returns whether `position` was reached from `previous`, or left to `next`, at a speed above `max_speed` (km/h)
+fn too_fast(position: &Position, neighbour: Option<&Position>, max_speed: f64) -> bool {
+    neighbour
+        .and_then(|neighbour| position.speed(neighbour))
+        .is_some_and(|speed| speed > max_speed)
+}
+
+/// Returns `positions` without glitches, i.e. isolated positions (e.g. at `0,0` or thousands of km away
+/// from their neighbours) that imply a speed above `max_speed` (km/h, e.g. [`MAX_PLAUSIBLE_SPEED`])
+/// relative to their neighbours (see [`Position::speed`]), which would otherwise inflate the distance and
+/// emissions of the leg.
+/// # Implementation
+/// A position is a glitch when the speeds from its previous and to its next position are both above `max_speed`.
+/// The first and last positions, which only have one neighbour, are then compared to it.
+/// `positions` must be sorted by time.
+pub fn filter_glitches(positions: Vec<Position>, max_speed: f64) -> Vec<Position> {
+    let glitches = (0..positions.len())
+        .map(|i| {
+            i > 0
+                && too_fast(&positions[i], positions.get(i - 1), max_speed)
+                && too_fast(&positions[i], positions.get(i + 1), max_speed)
+        })
+        .collect::<Vec<_>>();
+    let mut positions = positions
+        .into_iter()
+        .zip(glitches)
+        .filter_map(|(position, glitch)| (!glitch).then_some(position))
+        .collect::<Vec<_>>();
+
+    if positions.len() > 2 {
+        let last = positions.len() - 1;
+        if too_fast(&positions[last], positions.get(last - 1), max_speed) {
+            positions.pop();
+        }
+        if too_fast(&positions[0], positions.get(1), max_speed) {
+            positions.remove(0);
+        }
     }
+    positions
+}
+
+/// Returns whether `positions` are sorted by time, the precondition of [`Legs`]
+pub fn ensure_sorted(positions: &[Position]) -> bool {
+    positions
+        .windows(2)
+        .all(|w| w[0].datetime() <= w[1].datetime())
 }
 
 /// Returns a set of [`Leg`]s from a sequence of [`Position`]s according
-/// to the [methodology `M-identify-legs`](../methodology.md).
+/// to the [methodology `M-identify-legs`](../methodology.md), with [`LegOptions::default`].
+/// See [`legs_with`].
 pub fn legs(positions: impl Iterator<Item = Position>) -> impl Iterator<Item = Leg> {
-    Legs::new(positions)
+    legs_with(positions, LegOptions::default())
+}
+
+/// Returns a set of [`Leg`]s from a sequence of [`Position`]s according
+/// to the [methodology `M-identify-legs`](../methodology.md) and `options`.
+///
+/// Legs are identified from consecutive positions, so they must be sorted by time.
+/// `positions` are (stably) sorted before, so that unsorted input does not result in wrong legs,
+/// and glitches are removed (see [`filter_glitches`]).
+pub fn legs_with(
+    positions: impl Iterator<Item = Position>,
+    options: LegOptions,
+) -> impl Iterator<Item = Leg> {
+    let mut positions = positions.collect::<Vec<_>>();
+    if cfg!(debug_assertions) && !ensure_sorted(&positions) {
+        log::warn!("Positions are not sorted by time; sorting them");
+    }
+    positions.sort_by_key(|p| p.datetime());
+    let positions = filter_glitches(positions, options.max_speed);
+    Legs::with(positions.into_iter(), options)
         // ignore legs that are too fast, as they are likely noise
         .filter(|leg| leg.duration() > time::Duration::minutes(5))
         // ignore legs that are too short, as they are likely noise
@@ -136,6 +556,91 @@ mod test {
         assert_eq!(Leg { positions: vec![] }.positions(), &[]);
     }
 
+    #[test]
+    fn methodology() {
+        let current = format!("icao_number,methodology\na,{METHODOLOGY_VERSION}\n");
+        assert!(is_current_methodology(current.as_bytes()));
+
+        // stamped with an older version => invalidated
+        let old = format!("icao_number,methodology\na,{METHODOLOGY_VERSION}\na,1\n");
+        assert!(!is_current_methodology(old.as_bytes()));
+        // not stamped => invalidated
+        assert!(!is_current_methodology(b"icao_number\na\n"));
+        assert!(!is_current_methodology(b""));
+
+        // no legs => stamped in the header
+        assert!(is_current_methodology(&empty_legs_csv()));
+        assert!(!is_current_methodology(b"methodology=1\n"));
+        assert_eq!(
+            crate::csv::deserialize::<Stamp>(&empty_legs_csv()).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn start_end() {
+        let pos = |t: i64| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t * 60).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude: Some(30000.0),
+        };
+        let leg = Leg::new(vec![pos(10), pos(30), pos(55)]);
+        assert_eq!(leg.start(), pos(10).datetime());
+        assert_eq!(leg.end(), pos(55).datetime());
+        assert_eq!(leg.duration(), time::Duration::minutes(45));
+
+        assert!(min_duration(time::Duration::minutes(45))(&leg));
+        assert!(!min_duration(time::Duration::minutes(46))(&leg));
+    }
+
+    #[test]
+    fn fraction_above() {
+        let pos = |t: i64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t * 60).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude,
+        };
+        // 20 minutes climbing, 60 minutes above 26.000 feet, 20 minutes descending
+        let leg = Leg::new(vec![
+            pos(0, None),
+            pos(20, Some(30000.0)),
+            pos(50, Some(35000.0)),
+            pos(80, Some(30000.0)),
+            pos(100, None),
+        ]);
+        assert!((leg.hours_above(26000.0) - 1.0).abs() < 1e-9);
+        assert!((leg.fraction_above(26000.0) - 0.6).abs() < 1e-9);
+        assert_eq!(leg.fraction_above(40000.0), 0.0);
+        assert_eq!(Leg::new(vec![pos(0, None)]).fraction_above(26000.0), 0.0);
+    }
+
+    #[test]
+    fn unsorted() {
+        let pos = |t: i64, latitude: f64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t * 60).unwrap(),
+            latitude,
+            longitude: 0.0,
+            altitude,
+        };
+        let sorted = vec![
+            pos(0, 0.0, None),
+            pos(10, 0.5, Some(20000.0)),
+            pos(20, 1.0, Some(20000.0)),
+            pos(30, 1.5, None),
+        ];
+        let mut unsorted = sorted.clone();
+        unsorted.swap(0, 2);
+        unsorted.swap(1, 3);
+        assert!(ensure_sorted(&sorted));
+        assert!(!ensure_sorted(&unsorted));
+
+        let expected = legs(sorted.into_iter()).collect::<Vec<_>>();
+        assert_eq!(expected.len(), 1);
+        assert_eq!(legs(unsorted.into_iter()).collect::<Vec<_>>(), expected);
+    }
+
     #[test]
     fn empty_leg() {
         assert_eq!(Legs::new(vec![].into_iter()).count(), 0);
@@ -220,6 +725,194 @@ mod test {
         );
     }
 
+    #[test]
+    fn low_altitude_boundary() {
+        let delta = 5 * 60 + 1;
+        // below => lost close to the ground => new leg
+        let alt = LOW_ALTITUDE - 0.1;
+        test(
+            vec![
+                (0, None),
+                (10, Some(alt)),
+                (10 + delta, Some(alt)),
+                (11 + delta, Some(alt)),
+            ],
+            vec![
+                vec![(0, None), (10, Some(alt))],
+                vec![(10 + delta, Some(alt)), (11 + delta, Some(alt))],
+            ],
+        );
+        // at and above => not close to the ground => same leg
+        for alt in [LOW_ALTITUDE, LOW_ALTITUDE + 0.1] {
+            test(
+                vec![(0, None), (10, Some(alt)), (10 + delta, Some(alt))],
+                vec![vec![(0, None), (10, Some(alt)), (10 + delta, Some(alt))]],
+            );
+        }
+    }
+
+    #[test]
+    fn low_altitude_hysteresis() {
+        let delta = 5 * 60 + 1;
+        let cruise = Some(30000.0);
+        // descending to around the threshold => within the hysteresis => same leg
+        for alt in [LOW_ALTITUDE - 0.1, LOW_ALTITUDE, LOW_ALTITUDE + 0.1] {
+            let positions = vec![
+                (0, None),
+                (10, cruise),
+                (20, Some(alt)),
+                (20 + delta, Some(alt)),
+            ];
+            test(positions.clone(), vec![positions]);
+        }
+        // descending below the hysteresis => close to the ground => new leg
+        let alt = LOW_ALTITUDE - LOW_ALTITUDE_HYSTERESIS - 0.1;
+        test(
+            vec![
+                (0, None),
+                (10, cruise),
+                (20, Some(alt)),
+                (20 + delta, Some(alt)),
+                (21 + delta, Some(alt)),
+            ],
+            vec![
+                vec![(0, None), (10, cruise), (20, Some(alt))],
+                vec![(20 + delta, Some(alt)), (21 + delta, Some(alt))],
+            ],
+        );
+    }
+
+    #[test]
+    fn flapping_around_low_altitude() {
+        let delta = 5 * 60 + 1;
+        let (above, below) = (Some(LOW_ALTITUDE + 0.1), Some(LOW_ALTITUDE - 0.1));
+        // noisy altitudes around the threshold, with a gap in coverage after one below it
+        let positions = vec![
+            (0, None),
+            (10, above),
+            (20, below),
+            (30, above),
+            (40, below),
+            (40 + delta, above),
+            (50 + delta, below),
+            (60 + delta, None),
+        ];
+        test(positions.clone(), vec![positions.clone()]);
+
+        // without hysteresis, the leg is cut at the gap
+        let options = LegOptions {
+            low_altitude_hysteresis: 0.0,
+            ..Default::default()
+        };
+        let positions = positions.into_iter().map(|(t, altitude)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude,
+        });
+        assert_eq!(Legs::with(positions, options).count(), 2);
+    }
+
+    #[test]
+    fn high_elevation_field() {
+        let minute = 60;
+        let pos = |t: i64, latitude: f64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t * minute).unwrap(),
+            latitude,
+            longitude: -106.0,
+            altitude,
+        };
+        // Denver -> Aspen (~7800 feet), whose landing and take off are not received
+        // on the ground, -> Boulder
+        let positions = vec![
+            pos(0, 39.86, None),
+            pos(3, 39.8, Some(9000.0)),
+            pos(6, 39.7, Some(20000.0)),
+            pos(26, 39.3, Some(20000.0)),
+            pos(30, 39.2, Some(11000.0)),
+            // signal lost for two hours at 3200 feet above Aspen
+            pos(150, 39.2, Some(11000.0)),
+            pos(154, 39.3, Some(20000.0)),
+            pos(174, 39.9, Some(20000.0)),
+            pos(177, 40.0, Some(9000.0)),
+            pos(180, 40.1, None),
+        ];
+
+        // relative to the sea level, 11000 feet is not close to the ground => a single leg
+        assert_eq!(legs(positions.clone().into_iter()).count(), 1);
+
+        let options = LegOptions {
+            field_elevation: 7800.0,
+            ..Default::default()
+        };
+        let legs = legs_with(positions.into_iter(), options).collect::<Vec<_>>();
+        assert_eq!(legs.len(), 2);
+        assert_eq!(legs[0].to().latitude(), 39.2);
+        assert_eq!(legs[1].from().latitude(), 39.2);
+    }
+
+    #[test]
+    fn flapping_ground_flag_is_ignored() {
+        let pos = |t: i64, longitude: f64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude: 0.0,
+            longitude,
+            altitude,
+        };
+        let positions = vec![
+            // ground flag flapping while taxiing
+            pos(0, 0.0, None),
+            pos(10, 0.0, Some(100.0)),
+            pos(20, 0.0, None),
+            pos(30, 0.0, Some(100.0)),
+            pos(40, 0.0, None),
+            // actual leg, at ~670 km/h
+            pos(100, 0.0, None),
+            pos(390, 0.5, Some(30000.0)),
+            pos(680, 1.0, Some(30000.0)),
+            pos(970, 1.5, None),
+        ];
+        let legs = legs(positions.clone().into_iter()).collect::<Vec<_>>();
+        assert_eq!(legs, vec![Leg::new(positions[5..].to_vec())]);
+    }
+
+    #[test]
+    fn smoothing() {
+        let positions = [
+            None,
+            None,
+            Some(50.0), // noise while taxiing
+            None,
+            None,
+            Some(2000.0),
+            Some(5000.0),
+            None, // noise while flying
+            Some(5000.0),
+            Some(2000.0),
+            None,
+            None,
+            Some(50.0), // noise while taxiing
+            None,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, altitude)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(i as i64 * 10).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude,
+        })
+        .collect::<Vec<_>>();
+
+        assert_eq!(Legs::new(positions.clone().into_iter()).count(), 4);
+        let smoothed = smooth_altitude(positions.clone(), 3);
+        let legs = Legs::new(smoothed.into_iter()).collect::<Vec<_>>();
+        assert_eq!(legs.len(), 1);
+        assert_eq!(legs[0].positions().len(), 7);
+
+        assert_eq!(smooth_altitude(positions.clone(), 1), positions);
+    }
+
     #[test]
     fn high_and_10h_is_new_leg() {
         // > 10k feet
@@ -243,4 +936,377 @@ mod test {
             ],
         );
     }
+
+    #[test]
+    fn single_hop() {
+        test(
+            vec![
+                (0, None),
+                (60, Some(30000.0)),
+                (120, Some(30000.0)),
+                (180, None),
+            ],
+            vec![vec![
+                (0, None),
+                (60, Some(30000.0)),
+                (120, Some(30000.0)),
+                (180, None),
+            ]],
+        );
+    }
+
+    #[test]
+    fn three_hops() {
+        let hop = |t: i64| {
+            vec![
+                (t, None),
+                (t + 60, Some(30000.0)),
+                (t + 120, Some(30000.0)),
+                (t + 180, None),
+            ]
+        };
+        // brief ground stops between hops
+        let positions = [hop(0), hop(240), hop(480)].concat();
+        test(positions, vec![hop(0), hop(240), hop(480)]);
+    }
+
+    #[test]
+    fn starts_airborne() {
+        test(
+            vec![
+                (0, Some(30000.0)),
+                (60, Some(30000.0)),
+                (120, None),
+                (180, None),
+            ],
+            vec![vec![(0, Some(30000.0)), (60, Some(30000.0)), (120, None)]],
+        );
+    }
+
+    #[test]
+    fn ends_airborne() {
+        test(
+            vec![
+                (0, None),
+                (60, Some(30000.0)),
+                (120, None),
+                (180, None),
+                (240, Some(30000.0)),
+                (300, Some(30000.0)),
+            ],
+            vec![
+                vec![(0, None), (60, Some(30000.0)), (120, None)],
+                vec![(180, None), (240, Some(30000.0)), (300, Some(30000.0))],
+            ],
+        );
+    }
+
+    #[test]
+    fn never_lands() {
+        test(
+            vec![
+                (0, Some(30000.0)),
+                (60, Some(30000.0)),
+                (120, Some(30000.0)),
+            ],
+            vec![vec![
+                (0, Some(30000.0)),
+                (60, Some(30000.0)),
+                (120, Some(30000.0)),
+            ]],
+        );
+    }
+
+    #[test]
+    fn month_boundary() {
+        // a flight departing on the 31st of January and landing after midnight
+        let departure = time::macros::datetime!(2023-01-31 23:40 UTC).unix_timestamp();
+        let position = |minutes: i64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(departure + minutes * 60).unwrap(),
+            latitude: 55.6 + minutes as f64 * 0.02,
+            longitude: 12.6,
+            altitude,
+        };
+        let positions = (0..=11)
+            .map(|i| position(i * 5, (i > 0 && i < 11).then_some(30000.0)))
+            .collect::<Vec<_>>();
+        let (january, february): (Vec<_>, Vec<_>) = positions
+            .iter()
+            .cloned()
+            .partition(|p| p.datetime().month() == time::Month::January);
+
+        let january = legs(january.into_iter()).collect::<Vec<_>>();
+        let february = legs(february.into_iter()).collect::<Vec<_>>();
+        assert_eq!((january.len(), february.len()), (1, 1));
+        assert!(!january[0].starts_airborne() && january[0].ends_airborne());
+        assert!(february[0].starts_airborne() && !february[0].ends_airborne());
+        assert!(january[0].partial() && february[0].partial());
+
+        let options = LegOptions::default();
+        let stitched = stitch(&january[0], &february[0], &options).unwrap();
+        assert!(!stitched.partial());
+        assert_eq!(
+            vec![stitched.clone()],
+            legs(positions.into_iter()).collect::<Vec<_>>()
+        );
+        // not in order
+        assert_eq!(stitch(&february[0], &january[0], &options), None);
+        // complete legs are not stitched
+        assert_eq!(stitch(&stitched, &february[0], &options), None);
+    }
+
+    #[test]
+    fn stitch_with_options() {
+        let position = |minutes: i64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+            latitude: 55.6 + minutes as f64 * 0.02,
+            longitude: 12.6,
+            altitude,
+        };
+        // the signal is lost for 10 minutes at 8000 feet
+        let first = Leg::new(vec![
+            position(0, None),
+            position(5, Some(8000.0)),
+            position(10, Some(8000.0)),
+        ]);
+        let second = Leg::new(vec![
+            position(20, Some(8000.0)),
+            position(25, Some(8000.0)),
+            position(30, None),
+        ]);
+
+        // close to the ground by default, so the aircraft may have landed in between
+        assert_eq!(stitch(&first, &second, &LegOptions::default()), None);
+        assert_eq!(
+            stitch_legs(&[first.clone(), second.clone()], &LegOptions::default()).len(),
+            2
+        );
+
+        // far from the ground with a lower threshold, as when identifying the legs with it
+        let options = LegOptions {
+            low_altitude: 5000.0,
+            ..Default::default()
+        };
+        assert!(stitch(&first, &second, &options).is_some());
+        assert_eq!(stitch_legs(&[first, second], &options).len(), 1);
+    }
+
+    #[test]
+    fn stitch_month_boundary() {
+        let midnight = time::macros::datetime!(2023-02-01 00:00 UTC).unix_timestamp();
+        let position = |minutes: i64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(midnight + minutes * 60).unwrap(),
+            latitude: 55.6 + minutes as f64 * 0.02,
+            longitude: 12.6,
+            altitude,
+        };
+        let hop = |start: i64| {
+            (0..=8).map(move |i| position(start + i * 5, (i > 0 && i < 8).then_some(30000.0)))
+        };
+        // a hop on January, a red-eye across midnight, and a hop on February
+        let positions = hop(-300)
+            .chain(hop(-20))
+            .chain(hop(120))
+            .collect::<Vec<_>>();
+        // split exactly at the month boundary, as cached
+        let (january, february): (Vec<_>, Vec<_>) = positions
+            .iter()
+            .cloned()
+            .partition(|p| p.datetime().unix_timestamp() < midnight);
+
+        let per_month = legs(january.into_iter())
+            .chain(legs(february.into_iter()))
+            .collect::<Vec<_>>();
+        assert_eq!(per_month.len(), 4);
+
+        let stitched = stitch_legs(&per_month, &LegOptions::default());
+        assert_eq!(stitched, legs(positions.into_iter()).collect::<Vec<_>>());
+        assert_eq!(stitched.len(), 3);
+        assert!(stitched.iter().all(|leg| !leg.partial()));
+    }
+
+    #[test]
+    fn airports() {
+        let airports = Airports::new();
+        let position = |t: i64, latitude, longitude, altitude| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude,
+            longitude,
+            altitude,
+        };
+        let leg = Leg::new(vec![
+            position(0, 55.6270, 12.6440, None),
+            position(1800, 56.5, 11.0, Some(30000.0)),
+            position(3600, 57.0950, 9.8600, None),
+        ]);
+        let radius = crate::airports::DEFAULT_RADIUS;
+        assert_eq!(leg.from_airport(&airports, radius).unwrap().icao, "EKCH");
+        assert_eq!(leg.to_airport(&airports, radius).unwrap().icao, "EKYT");
+        assert!(leg.to_airport(&airports, 0.1).is_none());
+    }
+
+    #[test]
+    fn intersects_box() {
+        let position = |t: i64, latitude, longitude| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude,
+            longitude,
+            altitude: Some(30000.0),
+        };
+        // Zurich to Samedan
+        let leg = Leg::new(vec![
+            position(0, 47.4647, 8.5492),
+            position(1200, 46.9, 9.3),
+            position(2400, 46.5341, 9.8841),
+        ]);
+        assert!(leg.intersects_box(&crate::geo::DAVOS));
+        assert!(!Leg::new(leg.positions()[..2].to_vec()).intersects_box(&crate::geo::DAVOS));
+    }
+
+    #[test]
+    fn positions_are_contiguous() {
+        let position = |minutes: i64, altitude: Option<f64>| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+            latitude: 55.6 + minutes as f64 * 0.02,
+            longitude: 12.6,
+            altitude,
+        };
+        // two hops with a ground stop in between
+        let positions = (0..30)
+            .map(|i| {
+                let flying = (1..12).contains(&i) || (16..28).contains(&i);
+                position(i * 2, flying.then_some(30000.0))
+            })
+            .collect::<Vec<_>>();
+
+        let legs = legs(positions.clone().into_iter()).collect::<Vec<_>>();
+        assert_eq!(legs.len(), 2);
+        for leg in &legs {
+            let leg = leg.positions();
+            // a contiguous slice of the positions, from take-off to landing
+            let start = positions.iter().position(|p| p == &leg[0]).unwrap();
+            assert_eq!(&positions[start..start + leg.len()], leg);
+            assert!(ensure_sorted(leg));
+            assert!(leg[0].grounded() && leg[leg.len() - 1].grounded());
+            assert!(leg[1..leg.len() - 1].iter().all(|p| p.flying()));
+        }
+    }
+
+    #[test]
+    fn direct_and_track_distance() {
+        let position = |t: i64, latitude, longitude| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude,
+            longitude,
+            altitude: Some(30000.0),
+        };
+        // Copenhagen to Aalborg via Gothenburg
+        let curved = Leg::new(vec![
+            position(0, 55.618, 12.656),
+            position(1800, 57.668, 12.292),
+            position(3600, 57.093, 9.849),
+        ]);
+        let direct = curved.direct_distance_km();
+        let track = curved.track_distance_km();
+        assert!((direct - 238.0).abs() < 5.0);
+        assert!(track > 1.5 * direct);
+        assert_eq!(curved.great_circle_distance(), direct);
+        assert_eq!(curved.distance(), track);
+
+        // a straight path
+        let straight = Leg::new(vec![
+            position(0, 55.0, 10.0),
+            position(1800, 55.5, 10.0),
+            position(3600, 56.0, 10.0),
+        ]);
+        assert!((straight.track_distance_km() - straight.direct_distance_km()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn speeds() {
+        // ~60 km north every 4 minutes, i.e. ~900 km/h
+        let position = |minutes: i64, latitude: f64| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+            latitude,
+            longitude: 12.6,
+            altitude: Some(30000.0),
+        };
+        let normal = (0..6)
+            .map(|i| position(i * 4, 50.0 + i as f64 * 0.54))
+            .collect::<Vec<_>>();
+        let leg = Leg::new(normal.clone());
+        assert_eq!(leg.speeds().len(), 5);
+        assert!(leg.speeds().iter().all(|s| (s - 900.0).abs() < 10.0));
+        assert!((leg.max_speed().unwrap() - 900.0).abs() < 10.0);
+        assert!((leg.avg_speed().unwrap() - 900.0).abs() < 10.0);
+        assert_eq!(filter_glitches(normal.clone(), MAX_PLAUSIBLE_SPEED), normal);
+
+        // a position ~3000 km away from its neighbours
+        let mut glitched = normal.clone();
+        glitched[3].latitude = 20.0;
+        let leg = Leg::new(glitched.clone());
+        assert!(leg.max_speed().unwrap() > 10.0 * MAX_PLAUSIBLE_SPEED);
+        assert!(!leg.plausible());
+        let cleaned = filter_glitches(glitched, MAX_PLAUSIBLE_SPEED);
+        assert_eq!(cleaned.len(), 5);
+        assert!(!cleaned.contains(&position(12, 20.0)));
+        let leg = Leg::new(cleaned);
+        assert!(leg.plausible());
+        assert!(leg.max_speed().unwrap() < MAX_PLAUSIBLE_SPEED);
+
+        assert_eq!(Leg::new(vec![position(0, 50.0)]).avg_speed(), None);
+    }
+
+    #[test]
+    fn glitches() {
+        // CPH -> AAL, with a sample at 0,0
+        let position = |minutes: i64, latitude: f64, longitude: f64| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+            latitude,
+            longitude,
+            altitude: Some(20000.0),
+        };
+        let clean = vec![
+            position(0, 55.6, 12.6),
+            position(4, 55.9, 12.1),
+            position(8, 56.3, 11.4),
+            position(12, 56.6, 10.8),
+            position(16, 57.0, 10.0),
+        ];
+        let mut glitched = clean.clone();
+        glitched.insert(2, position(6, 0.0, 0.0));
+        assert_eq!(
+            filter_glitches(glitched.clone(), MAX_PLAUSIBLE_SPEED),
+            clean
+        );
+
+        // at the ends
+        let mut glitched = clean.clone();
+        glitched.insert(0, position(-1, 0.0, 0.0));
+        glitched.push(position(17, 0.0, 0.0));
+        assert_eq!(
+            filter_glitches(glitched.clone(), MAX_PLAUSIBLE_SPEED),
+            clean
+        );
+
+        // legs ignore it, unless the threshold is too high to detect it
+        let glitched = [
+            clean[..2].to_vec(),
+            vec![position(6, 0.0, 0.0)],
+            clean[2..].to_vec(),
+        ]
+        .concat();
+        let distance = |options| {
+            legs_with(glitched.clone().into_iter(), options)
+                .map(|leg| leg.distance())
+                .sum::<f64>()
+        };
+        let expected = Leg::new(clean.clone()).distance();
+        assert!((distance(LegOptions::default()) - expected).abs() < 1e-6);
+        let options = LegOptions {
+            max_speed: f64::INFINITY,
+            ..Default::default()
+        };
+        assert!(distance(options) > 10.0 * expected);
+    }
 }
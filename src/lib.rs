@@ -1,19 +1,44 @@
 #[forbid(unsafe_code)]
 pub mod aircraft;
+pub mod airports;
+pub mod batch;
 pub(crate) mod country;
 pub mod csv;
 pub mod emissions;
+pub mod export;
 pub mod fs;
 pub mod fs_s3;
+pub mod geo;
+pub mod http;
 pub mod icao_to_trace;
 pub mod io;
 pub mod legs;
 pub mod model;
 mod private_jets_in_time;
+pub mod report;
+pub mod selfcheck;
 pub mod serde;
 mod trace_month;
 
-pub use private_jets_in_time::{private_jets_in_month, RequiredTasks};
+pub use country::{Countries, Country};
+pub use private_jets_in_time::{
+    private_jets, private_jets_in_month, private_jets_in_month_cached, sample_private_jets,
+    unmatched_models, PrivateJetFilter, PrivateJets, RequiredTasks, UnmatchedModel,
+};
+
+/// A point on Earth, in degrees. Named fields so that latitude and longitude cannot be swapped
+/// (e.g. GeoJSON orders coordinates as longitude, latitude).
+#[derive(Debug, Clone, Copy, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct LatLon {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl std::fmt::Display for LatLon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.latitude, self.longitude)
+    }
+}
 
 /// A position of an aircraft
 #[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
@@ -37,43 +62,78 @@ impl Position {
         self.altitude.is_none()
     }
 
+    /// The latitude in degrees (WGS 84), positive north of the equator
     pub fn latitude(&self) -> f64 {
         self.latitude
     }
 
+    /// The longitude in degrees (WGS 84), positive east of Greenwich
     pub fn longitude(&self) -> f64 {
         self.longitude
     }
 
-    pub fn pos(&self) -> (f64, f64) {
-        (self.latitude(), self.longitude())
+    pub fn pos(&self) -> LatLon {
+        LatLon {
+            latitude: self.latitude,
+            longitude: self.longitude,
+        }
     }
 
-    pub fn altitude(&self) -> f64 {
-        self.altitude.unwrap_or(0.0)
+    /// The barometric altitude in feet, or `None` when the aircraft is on the ground
+    pub fn altitude(&self) -> Option<f64> {
+        self.altitude
     }
 
+    /// The time of the position, i.e. the timestamp of its trace plus the offset of its entry
     pub fn datetime(&self) -> time::OffsetDateTime {
         self.datetime
     }
 
     /// Returns the distance to another [`Position`] in km
     pub fn distace(&self, other: &Self) -> f64 {
-        distance(self.pos(), other.pos())
+        geo::great_circle_distance_km(self.pos(), other.pos())
+    }
+
+    /// Returns the ground speed in km/h at which this position was reached from `previous`, derived from
+    /// their distance and timestamps, or `None` when they are at the same time
+    pub fn speed(&self, previous: &Self) -> Option<f64> {
+        let hours = (self.datetime() - previous.datetime())
+            .as_seconds_f64()
+            .abs()
+            / 60.0
+            / 60.0;
+        (hours > 0.0).then(|| self.distace(previous) / hours)
     }
 }
 
-/// Returns the distance between two geo-points in km
-fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
-    let from = geoutils::Location::new(from.0, from.1);
-    let to = geoutils::Location::new(to.0, to.1);
-    from.haversine_distance_to(&to).meters() / 1000.0
+/// Returns the period between `from` (inclusive) and `to` (exclusive), with `to` clamped to `today`
+/// since there is no data of days that have not yet completed.
+/// # Error
+/// Errors when `from` is after `to`, or when the period starts after `today`
+pub fn date_range(
+    from: time::Date,
+    to: time::Date,
+    today: time::Date,
+) -> Result<(time::Date, time::Date), String> {
+    if from > to {
+        return Err(format!("The start ({from}) is after the end ({to})"));
+    }
+    if from > today {
+        return Err(format!("The start ({from}) is in the future"));
+    }
+    if to > today {
+        log::warn!("The end ({to}) is in the future; using {today}");
+    }
+    Ok((from, to.min(today)))
 }
 
-/// An iterator between two [`time::Date`]s in increments
-/// The result is exclusive, i.e. the iterator has two items when increment is one day
-/// from 2022-01-01 and 2022-01-03
-#[derive(Clone, Copy)]
+/// An iterator between two [`time::Date`]s in increments.
+/// It yields `from`, `from + increment`, ... up to but not including `to`, i.e. the iterator has two items
+/// when increment is one day from 2022-01-01 and 2022-01-03, and none when `from >= to`.
+///
+/// Dates have no time, so the increment is its number of whole days (e.g. 36 hours is one day).
+/// Increments of less than one day (including negative ones) yield no dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DateIter {
     pub from: time::Date,
     pub to: time::Date,
@@ -84,20 +144,59 @@ impl Iterator for DateIter {
     type Item = time::Date;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.from >= self.to {
+        let days = self.increment.whole_days();
+        if self.from >= self.to || days < 1 {
             return None;
         }
         let maybe_next = self.from;
-        self.from = self.from.saturating_add(self.increment);
+        self.from = self.from.saturating_add(time::Duration::days(days));
         Some(maybe_next)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let days = self.increment.whole_days();
+        let remaining = (self.to - self.from).whole_days();
+        let len = if remaining < 1 || days < 1 {
+            0
+        } else {
+            // ceil(remaining / days)
+            ((remaining + days - 1) / days) as usize
+        };
+        (len, Some(len))
+    }
 }
 
+impl ExactSizeIterator for DateIter {}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use time::macros::date;
 
+    #[test]
+    fn date_ranges() {
+        let today = date!(2024 - 03 - 15);
+        assert_eq!(
+            date_range(date!(2024 - 01 - 01), date!(2024 - 02 - 01), today),
+            Ok((date!(2024 - 01 - 01), date!(2024 - 02 - 01)))
+        );
+        // empty, but valid
+        assert_eq!(
+            date_range(date!(2024 - 01 - 01), date!(2024 - 01 - 01), today),
+            Ok((date!(2024 - 01 - 01), date!(2024 - 01 - 01)))
+        );
+        // inverted
+        let error = date_range(date!(2024 - 02 - 01), date!(2024 - 01 - 01), today).unwrap_err();
+        assert!(error.contains("after"));
+        // a year in the future is clamped
+        assert_eq!(
+            date_range(date!(2024 - 03 - 01), date!(2025 - 03 - 15), today),
+            Ok((date!(2024 - 03 - 01), today))
+        );
+        // entirely in the future
+        assert!(date_range(date!(2025 - 01 - 01), date!(2025 - 03 - 15), today).is_err());
+    }
+
     #[test]
     fn work() {
         assert_eq!(
@@ -110,4 +209,105 @@ mod test {
             vec![date!(2022 - 01 - 01), date!(2022 - 01 - 02)]
         );
     }
+
+    #[test]
+    fn date_iter() {
+        let iter = |from, to, increment| DateIter {
+            from,
+            to,
+            increment,
+        };
+        let day = time::Duration::days(1);
+        let week = time::Duration::weeks(1);
+
+        let days = iter(date!(2024 - 02 - 27), date!(2024 - 03 - 02), day);
+        assert_eq!(days.len(), 4);
+        assert_eq!(days.last(), Some(date!(2024 - 03 - 01)));
+
+        let weeks = iter(date!(2024 - 01 - 01), date!(2024 - 01 - 16), week);
+        assert_eq!(weeks.len(), 3);
+        assert_eq!(
+            weeks.collect::<Vec<_>>(),
+            vec![
+                date!(2024 - 01 - 01),
+                date!(2024 - 01 - 08),
+                date!(2024 - 01 - 15)
+            ]
+        );
+        // `to` is exclusive
+        assert_eq!(
+            iter(date!(2024 - 01 - 01), date!(2024 - 01 - 15), week).len(),
+            2
+        );
+
+        // single day
+        let single = iter(date!(2024 - 01 - 01), date!(2024 - 01 - 02), week);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single.collect::<Vec<_>>(), vec![date!(2024 - 01 - 01)]);
+
+        // empty and inverted
+        assert_eq!(
+            iter(date!(2024 - 01 - 01), date!(2024 - 01 - 01), day).len(),
+            0
+        );
+        assert_eq!(
+            iter(date!(2024 - 01 - 02), date!(2024 - 01 - 01), day).count(),
+            0
+        );
+        // less than a day
+        let hours = iter(
+            date!(2024 - 01 - 01),
+            date!(2024 - 01 - 02),
+            time::Duration::hours(1),
+        );
+        assert_eq!((hours.len(), hours.count()), (0, 0));
+
+        // the length is exact while iterating
+        let mut days = iter(date!(2024 - 01 - 01), date!(2024 - 02 - 01), day);
+        days.next();
+        assert_eq!(days.len(), 30);
+        assert_eq!(days.count(), 30);
+
+        // saturates at the maximum date
+        let max = iter(
+            time::Date::MAX.previous_day().unwrap(),
+            time::Date::MAX,
+            week,
+        );
+        assert_eq!(max.count(), 1);
+    }
+
+    #[test]
+    fn lat_lon() {
+        let position = Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(0).unwrap(),
+            latitude: 55.618,
+            longitude: 12.656,
+            altitude: None,
+        };
+        let cph = LatLon {
+            latitude: 55.618,
+            longitude: 12.656,
+        };
+        assert_eq!(position.pos(), cph);
+        assert_eq!(position.latitude(), 55.618);
+        assert_eq!(position.longitude(), 12.656);
+        assert_eq!(position.altitude(), None);
+        let flying = Position {
+            altitude: Some(30000.0),
+            ..position.clone()
+        };
+        assert_eq!(flying.altitude(), Some(30000.0));
+        assert_eq!(
+            serde_json::to_value(cph).unwrap(),
+            serde_json::json!({"latitude": 55.618, "longitude": 12.656})
+        );
+
+        // Aalborg is ~220 km from Copenhagen; swapped, the coordinates would be in the Arabian Sea
+        let aal = LatLon {
+            latitude: 57.093,
+            longitude: 9.849,
+        };
+        assert!((geo::great_circle_distance_km(cph, aal) - 225.0).abs() < 25.0);
+    }
 }
@@ -1,4 +1,9 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::Path,
+    sync::Arc,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -25,10 +30,36 @@ pub struct AircraftModel {
 /// # Error
 /// Errors if the file cannot be read
 pub fn load_private_jet_models() -> Result<AircraftModels, Box<dyn Error>> {
-    let data = super::csv::load("src/models.csv", |a: AircraftModel| (a.clone(), a))?;
+    load_private_jet_models_from(Path::new("src/models.csv"))
+}
+
+/// Returns the set of all [`AircraftModel`] in the CSV at `path`, with the same columns as `src/models.csv`
+/// (`model,gph,source,date`), e.g. to include or exclude models from those of [`load_private_jet_models`].
+/// Models listed more than once (e.g. by different sources) have the average gph.
+/// # Error
+/// Errors if the file cannot be read, or when a row does not have these columns or has an empty model
+pub fn load_private_jet_models_from(path: &Path) -> Result<AircraftModels, Box<dyn Error>> {
+    let data = std::fs::read(path).map_err(|e| format!("{path:?} could not be read: {e}"))?;
+    let rows = super::csv::deserialize::<AircraftModel>(&data)
+        .enumerate()
+        .map(|(i, row)| {
+            // the header is line 1
+            let line = i + 2;
+            let row = row.map_err(|e| {
+                format!(
+                    "{path:?} line {line} is not a model with columns model,gph,source,date: {e}"
+                )
+            })?;
+            if row.model.trim().is_empty() {
+                return Err(format!("{path:?} line {line} has an empty model"));
+            }
+            Ok(row)
+        })
+        .collect::<Result<HashSet<_>, _>>()?;
 
-    let data = data
+    let data = rows
         .into_iter()
+        .map(|a| (a.clone(), a))
         .fold(
             HashMap::<String, (AircraftModel, u32)>::default(),
             |mut acc, (a, b)| {
@@ -73,4 +104,45 @@ mod test {
         let data_csv = crate::csv::serialize(models.into_iter());
         std::fs::write("models.csv", data_csv).unwrap();
     }
+
+    #[test]
+    fn custom() {
+        let dir = std::env::temp_dir().join(format!("models-{:x}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("models.csv");
+
+        std::fs::write(
+            &path,
+            "model,gph,source,date\nPILATUS PC-12,66,a,2024-01-01\nPILATUS PC-12,70,b,2024-01-02\n",
+        )
+        .unwrap();
+        let models = load_private_jet_models_from(&path).unwrap();
+        assert_eq!(models.len(), 1);
+        let model = &models["PILATUS PC-12"];
+        assert_eq!(model.gph, 68);
+        let mut sources = model.source.split(';').collect::<Vec<_>>();
+        sources.sort();
+        assert_eq!(sources, vec!["a", "b"]);
+
+        // a missing column
+        std::fs::write(&path, "model,gph\nPILATUS PC-12,66\n").unwrap();
+        let error = load_private_jet_models_from(&path).unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{error}");
+
+        // a gph that is not a number
+        std::fs::write(
+            &path,
+            "model,gph,source,date\nPILATUS PC-12,many,a,2024-01-01\n",
+        )
+        .unwrap();
+        assert!(load_private_jet_models_from(&path).is_err());
+
+        // an empty model
+        std::fs::write(&path, "model,gph,source,date\n ,66,a,2024-01-01\n").unwrap();
+        let error = load_private_jet_models_from(&path).unwrap_err().to_string();
+        assert!(error.contains("empty model"), "{error}");
+
+        assert!(load_private_jet_models_from(&dir.join("missing.csv")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
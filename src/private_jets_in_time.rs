@@ -1,14 +1,138 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+};
 
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use time::macros::date;
 use time::Date;
 
-use crate::{aircraft::Aircraft, fs::BlobStorageProvider, model::AircraftModel};
+use crate::{
+    aircraft::{Aircraft, Aircrafts, RegistryCache},
+    fs::BlobStorageProvider,
+    model::{AircraftModel, AircraftModels},
+};
 
 pub type RequiredTasks = HashMap<(Arc<str>, time::Date), (Arc<Aircraft>, Arc<AircraftModel>)>;
 
-/// Returns the map `(icao_number, month) -> `[`Aircraft`] for the given set of years and (optionally) countries.
+/// [`HashMap`] between icao number and the private jet ([`Aircraft`] and its [`AircraftModel`])
+pub type PrivateJets = HashMap<Arc<str>, (Arc<Aircraft>, Arc<AircraftModel>)>;
+
+/// The filters that select private jets, in addition to their model
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrivateJetFilter {
+    /// Optional country (in ISO 3166) the aircraft is registered in; defaults to whole world
+    pub country: Option<String>,
+    /// Optional tail number prefixes (e.g. `OY-`) of the aircraft, which override `country` when not empty
+    pub tail_prefixes: Vec<String>,
+    /// Whether to include military and other state aircraft ([`Aircraft::military`])
+    pub include_state: bool,
+    /// Optional owner or operator ([`Aircraft::owner`]) of the aircraft, case insensitive
+    pub owner: Option<String>,
+    /// Optional icao numbers (lowercase) of the aircraft, e.g. a curated list (see [`crate::aircraft::parse_icao_numbers`]),
+    /// which override all other filters when set
+    pub icao_numbers: Option<HashSet<Arc<str>>>,
+}
+
+impl PrivateJetFilter {
+    /// Returns whether `aircraft` passes the filter, regardless of its model
+    pub fn matches(&self, aircraft: &Aircraft) -> bool {
+        if let Some(icao_numbers) = &self.icao_numbers {
+            return icao_numbers.contains(&aircraft.icao_number);
+        }
+        let in_country = if !self.tail_prefixes.is_empty() {
+            aircraft.has_tail_prefix(&self.tail_prefixes)
+        } else {
+            self.country
+                .as_deref()
+                .map(|country| aircraft.country.as_deref() == Some(country))
+                .unwrap_or(true)
+        };
+        let of_owner = match &self.owner {
+            Some(owner) => aircraft
+                .owner
+                .as_deref()
+                .is_some_and(|x| x.eq_ignore_ascii_case(owner)),
+            None => true,
+        };
+        (self.include_state || !aircraft.military) && in_country && of_owner
+    }
+}
+
+/// Returns the subset of `aircrafts` that are private jets according to `models`
+/// and that match `filter`.
+pub fn private_jets(
+    aircrafts: Aircrafts,
+    models: &AircraftModels,
+    filter: &PrivateJetFilter,
+) -> PrivateJets {
+    aircrafts
+        .into_iter()
+        .filter(|(_, a)| filter.matches(a))
+        // filter for private jet models
+        .filter_map(|(icao_number, a)| {
+            models
+                .get(&a.model)
+                .map(|m| (icao_number, (Arc::new(a), m.clone())))
+        })
+        .collect()
+}
+
+/// A model of aircraft that is not in the private jet models, and the number of aircraft of it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UnmatchedModel {
+    /// The model as in the database of aircrafts (e.g. `BOEING 737`)
+    pub model: String,
+    /// The number of aircraft of this model
+    pub aircraft: usize,
+}
+
+/// Returns the distinct models of the aircraft of `aircrafts` matching `filter` that are not in `models`,
+/// i.e. those dropped by [`private_jets`], ordered by decreasing number of aircraft and then by model.
+/// Useful to find private jet models missing from `src/models.csv` (e.g. due to a different spelling).
+pub fn unmatched_models(
+    aircrafts: &Aircrafts,
+    models: &AircraftModels,
+    filter: &PrivateJetFilter,
+) -> Vec<UnmatchedModel> {
+    let counts = aircrafts
+        .values()
+        .filter(|a| filter.matches(a) && !models.contains_key(&a.model))
+        .counts_by(|a| a.model.as_str());
+    let mut unmatched = counts
+        .into_iter()
+        .map(|(model, aircraft)| UnmatchedModel {
+            model: model.to_string(),
+            aircraft,
+        })
+        .collect::<Vec<_>>();
+    unmatched.sort_unstable_by(|a, b| b.aircraft.cmp(&a.aircraft).then(a.model.cmp(&b.model)));
+    unmatched
+}
+
+/// Returns at most `max` of `private_jets`, e.g. for quick runs.
+///
+/// Without `seed`, these are the private jets with the smallest icao numbers. With `seed`, they are a
+/// random sample that is the same for the same `seed` (and version of this crate). In both cases the
+/// selection does not depend on the iteration order of `private_jets`.
+pub fn sample_private_jets(
+    private_jets: PrivateJets,
+    max: usize,
+    seed: Option<u64>,
+) -> PrivateJets {
+    use rand::{seq::SliceRandom, SeedableRng};
+
+    let mut private_jets = private_jets.into_iter().collect::<Vec<_>>();
+    private_jets.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    if let Some(seed) = seed {
+        private_jets.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+    }
+    private_jets.into_iter().take(max).collect()
+}
+
+/// Returns the map `(icao_number, month) -> `[`Aircraft`] for the given set of years of the private jets matching `filter`.
 /// The key is the specific `(icao_number, month)`, the value is the [`Aircraft`] associated with that icao_number at that month.
 ///
 /// ## Background
@@ -23,37 +147,48 @@ pub type RequiredTasks = HashMap<(Arc<str>, time::Date), (Arc<Aircraft>, Arc<Air
 /// ## Implementation
 /// This function fetches the set of aircrafts available in the database in time, and joins (in time) with the set of months in the requested years.
 ///
-/// It leverages these snapshots and the set of aircraft models to return the normalized set of months, aircrafts.
+/// It leverages these snapshots and the set of private jet models `models` (e.g. of
+/// [`crate::model::load_private_jet_models`]) to return the normalized set of months, aircrafts.
 pub async fn private_jets_in_month(
     years: impl Iterator<Item = i32>,
-    maybe_country: Option<&str>,
+    filter: &PrivateJetFilter,
+    models: &AircraftModels,
+    client: &dyn BlobStorageProvider,
+) -> Result<RequiredTasks, Box<dyn Error>> {
+    // only hold the private jets of each snapshot
+    let predicate = |a: &Aircraft| filter.matches(a) && models.contains_key(&a.model);
+    let aircrafts = crate::aircraft::read_all_filtered(client, &predicate).await?;
+    Ok(in_month(years, filter, models, aircrafts))
+}
+
+/// Like [`private_jets_in_month`], caching the snapshots of aircrafts of `client` in `cache`
+/// (see [`crate::aircraft::read_all_cached`]).
+pub async fn private_jets_in_month_cached(
+    years: impl Iterator<Item = i32>,
+    filter: &PrivateJetFilter,
+    models: &AircraftModels,
     client: &dyn BlobStorageProvider,
+    cache: &dyn BlobStorageProvider,
+    options: &RegistryCache,
 ) -> Result<RequiredTasks, Box<dyn Error>> {
-    let models = crate::model::load_private_jet_models()?;
-    let aircrafts = crate::aircraft::read_all(client).await?;
+    let predicate = |a: &Aircraft| filter.matches(a) && models.contains_key(&a.model);
+    let now = time::OffsetDateTime::now_utc();
+    let aircrafts =
+        crate::aircraft::read_all_cached(client, cache, options, now, &predicate).await?;
+    Ok(in_month(years, filter, models, aircrafts))
+}
 
+/// Returns the private jets of each month of `years` (see [`private_jets_in_month`]) from the snapshots `aircrafts`
+fn in_month(
+    years: impl Iterator<Item = i32>,
+    filter: &PrivateJetFilter,
+    models: &AircraftModels,
+    aircrafts: HashMap<Date, Aircrafts>,
+) -> RequiredTasks {
     // set of icao numbers that are private jets, for each date
     let private_jets = aircrafts
         .into_iter()
-        .map(|(date, a)| {
-            (
-                date,
-                a.into_iter()
-                    // filter by optional country
-                    .filter(|(_, a)| {
-                        maybe_country
-                            .map(|country| a.country.as_deref() == Some(country))
-                            .unwrap_or(true)
-                    })
-                    // filter for private jet models and optionally country
-                    .filter_map(|(icao_number, a)| {
-                        models
-                            .get(&a.model)
-                            .map(|m| (icao_number, (Arc::new(a), m.clone())))
-                    })
-                    .collect::<HashMap<_, _>>(),
-            )
-        })
+        .map(|(date, a)| (date, private_jets(a, models, filter)))
         .collect::<HashMap<_, _>>();
 
     // set of all months for requested years
@@ -70,7 +205,7 @@ pub async fn private_jets_in_month(
 
     // for each month, get the list of private jets closest from the start of month
     let private_jets = months
-        .map(|month| {
+        .flat_map(|month| {
             let closest_date = closest_date(private_jets.keys().copied(), month);
             private_jets
                 .get(&closest_date)
@@ -78,17 +213,18 @@ pub async fn private_jets_in_month(
                 .iter()
                 .map(move |(icao, aircraft)| ((icao.clone(), month), aircraft.clone()))
         })
-        .flatten()
         .collect::<HashMap<_, _>>();
 
-    Ok(private_jets)
+    private_jets
 }
 
 fn closest_date(dates: impl Iterator<Item = Date>, target: Date) -> Date {
     dates.fold(date!(1900 - 01 - 01), |a, b| {
-        ((a - target).abs() < (b - target).abs())
-            .then(|| a)
-            .unwrap_or(b)
+        if (a - target).abs() < (b - target).abs() {
+            a
+        } else {
+            b
+        }
     })
 }
 
@@ -98,6 +234,139 @@ mod test {
 
     use super::*;
 
+    fn aircraft(icao_number: &str, model: &str, country: &str) -> (Arc<str>, Aircraft) {
+        (
+            icao_number.into(),
+            Aircraft {
+                icao_number: icao_number.into(),
+                tail_number: format!("T-{icao_number}"),
+                type_designator: "F2TH".to_string(),
+                model: model.to_string(),
+                country: Some(country.into()),
+                military: false,
+                owner: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_private_jets() {
+        let model = AircraftModel {
+            model: "GULFSTREAM 5".to_string(),
+            gph: 455,
+            source: "".to_string(),
+            date: "".to_string(),
+        };
+        let models = AircraftModels::from([(model.model.clone(), Arc::new(model))]);
+        let aircrafts = || {
+            let (icao_number, mut state) = aircraft("d", "GULFSTREAM 5", "Denmark");
+            state.military = true;
+            let (a, mut owned) = aircraft("a", "GULFSTREAM 5", "Denmark");
+            owned.owner = Some("Acme Aviation".to_string());
+            Aircrafts::from([
+                (a, owned),
+                aircraft("b", "GULFSTREAM 5", "Portugal"),
+                aircraft("c", "BOEING 737", "Denmark"),
+                (icao_number, state),
+            ])
+        };
+        let result = |filter: PrivateJetFilter| {
+            let mut result = private_jets(aircrafts(), &models, &filter)
+                .into_keys()
+                .collect::<Vec<_>>();
+            result.sort();
+            result
+        };
+        let denmark = || Some("Denmark".to_string());
+
+        // model filter ("c" is not a private jet); state aircraft are excluded by default
+        assert_eq!(
+            result(PrivateJetFilter::default()),
+            vec!["a".into(), "b".into()]
+        );
+
+        // country
+        assert_eq!(
+            result(PrivateJetFilter {
+                country: denmark(),
+                ..Default::default()
+            }),
+            vec![Arc::<str>::from("a")]
+        );
+
+        // country and state
+        assert_eq!(
+            result(PrivateJetFilter {
+                country: denmark(),
+                include_state: true,
+                ..Default::default()
+            }),
+            vec!["a".into(), "d".into()]
+        );
+
+        // tail prefixes override country, still filtered by model
+        assert_eq!(
+            result(PrivateJetFilter {
+                country: denmark(),
+                tail_prefixes: vec!["T-b".to_string(), "T-c".to_string()],
+                ..Default::default()
+            }),
+            vec![Arc::<str>::from("b")]
+        );
+
+        // owner
+        assert_eq!(
+            result(PrivateJetFilter {
+                owner: Some("ACME aviation".to_string()),
+                ..Default::default()
+            }),
+            vec![Arc::<str>::from("a")]
+        );
+
+        // icao numbers override country and state, still filtered by model
+        assert_eq!(
+            result(PrivateJetFilter {
+                country: denmark(),
+                icao_numbers: Some(HashSet::from(["b".into(), "c".into(), "d".into()])),
+                ..Default::default()
+            }),
+            vec![Arc::<str>::from("b"), "d".into()]
+        );
+    }
+
+    #[test]
+    fn sample() {
+        let models = AircraftModels::from([(
+            "GULFSTREAM 5".to_string(),
+            Arc::new(AircraftModel {
+                model: "GULFSTREAM 5".to_string(),
+                gph: 455,
+                source: "".to_string(),
+                date: "".to_string(),
+            }),
+        )]);
+        let all = || {
+            let aircrafts = (0..100)
+                .map(|i| aircraft(&format!("{i:06x}"), "GULFSTREAM 5", "Denmark"))
+                .collect::<Aircrafts>();
+            private_jets(aircrafts, &models, &PrivateJetFilter::default())
+        };
+        let keys = |private_jets: PrivateJets| {
+            let mut keys = private_jets.into_keys().collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        let first = keys(sample_private_jets(all(), 10, Some(1)));
+        assert_eq!(first.len(), 10);
+        assert_eq!(first, keys(sample_private_jets(all(), 10, Some(1))));
+        assert_ne!(first, keys(sample_private_jets(all(), 10, Some(2))));
+
+        // without seed, by icao number
+        let first = keys(sample_private_jets(all(), 2, None));
+        assert_eq!(first, vec!["000000".into(), "000001".into()]);
+    }
+
     #[test]
     fn test_closest_date() {
         assert_eq!(
@@ -116,4 +385,71 @@ mod test {
             date!(2010 - 02 - 01)
         );
     }
+
+    #[test]
+    fn unmatched() {
+        let models = AircraftModels::from([(
+            "GULFSTREAM 5".to_string(),
+            Arc::new(AircraftModel {
+                model: "GULFSTREAM 5".to_string(),
+                gph: 455,
+                source: "".to_string(),
+                date: "".to_string(),
+            }),
+        )]);
+        let aircrafts = Aircrafts::from([
+            aircraft("a", "GULFSTREAM 5", "Denmark"),
+            aircraft("b", "GULFSTREAM V", "Denmark"),
+            aircraft("c", "GULFSTREAM V", "Denmark"),
+            aircraft("d", "UNKNOWN JET", "Denmark"),
+            aircraft("e", "UNKNOWN JET 2", "Portugal"),
+        ]);
+        let filter = PrivateJetFilter {
+            country: Some("Denmark".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            unmatched_models(&aircrafts, &models, &filter),
+            vec![
+                UnmatchedModel {
+                    model: "GULFSTREAM V".to_string(),
+                    aircraft: 2
+                },
+                UnmatchedModel {
+                    model: "UNKNOWN JET".to_string(),
+                    aircraft: 1
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn private_jets_in_month_models() {
+        let client = crate::fs::InMemory::default();
+        let snapshot = vec![
+            aircraft("a", "GULFSTREAM 5", "Denmark").1,
+            aircraft("c", "BOEING 737", "Denmark").1,
+        ];
+        crate::aircraft::load(snapshot, &date!(2023 - 01 - 01), &client)
+            .await
+            .unwrap();
+        // e.g. of a custom CSV of models
+        let model = AircraftModel {
+            model: "BOEING 737".to_string(),
+            gph: 850,
+            source: "".to_string(),
+            date: "".to_string(),
+        };
+        let models = AircraftModels::from([(model.model.clone(), Arc::new(model))]);
+
+        let result = private_jets_in_month(2023..2024, &Default::default(), &models, &client)
+            .await
+            .unwrap();
+        assert!(!result.is_empty());
+        assert!(result
+            .iter()
+            .all(|((icao_number, _), (_, model))| icao_number.as_ref() == "c"
+                && model.model == "BOEING 737"));
+    }
 }
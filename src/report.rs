@@ -0,0 +1,2009 @@
+//! Contains the aggregations of [`Leg`]s of private jets over a period, used to report facts about them.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    airports::Airports,
+    emissions::{CitizenEmissions, Class, EmissionsOptions, Equivalents},
+    fs::BlobStorageProvider,
+    http::HttpFetcher,
+    icao_to_trace::AircraftLegsOptions,
+    legs::Leg,
+    model::AircraftModel,
+    LatLon, Position, PrivateJets, RequiredTasks,
+};
+
+/// [`HashMap`] between icao number and the [`Leg`]s of the aircraft
+pub type AircraftLegs = HashMap<Arc<str>, Vec<Leg>>;
+
+/// The private jets reported on and the history of their models
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fleet {
+    /// The private jets
+    pub private_jets: PrivateJets,
+    /// The model of each private jet on each month (first day of the month), when known.
+    /// Airframes may be re-typed (e.g. after a conversion), and thus have different models over time.
+    pub models: HashMap<(Arc<str>, Date), Arc<AircraftModel>>,
+}
+
+impl Fleet {
+    /// Returns a [`Fleet`] from the private jets in each month (see [`crate::private_jets_in_month`]),
+    /// whose private jets are as of their latest month.
+    pub fn new(private_jets_in_month: RequiredTasks) -> Self {
+        let mut months = private_jets_in_month.into_iter().collect::<Vec<_>>();
+        months.sort_unstable_by_key(|((_, month), _)| *month);
+        let models = months
+            .iter()
+            .map(|(key, (_, model))| (key.clone(), model.clone()))
+            .collect();
+        let private_jets = months
+            .into_iter()
+            .map(|((icao_number, _), aircraft)| (icao_number, aircraft))
+            .collect();
+        Self {
+            private_jets,
+            models,
+        }
+    }
+
+    /// Returns the model of `icao_number` on `date`, falling back to its current model when
+    /// the model on that month is not known
+    fn model(&self, icao_number: &Arc<str>, date: Date) -> Option<&Arc<AircraftModel>> {
+        let month = date.replace_day(1).expect("day 1 never errors");
+        self.models
+            .get(&(icao_number.clone(), month))
+            .or_else(|| self.private_jets.get(icao_number).map(|(_, model)| model))
+    }
+
+    /// Returns the CO2 emissions in kg of `leg` flown by `icao_number`, using the model of the
+    /// aircraft on the day of departure, or zero if `icao_number` is not in the fleet
+    pub(crate) fn leg_co2_kg(&self, icao_number: &Arc<str>, leg: &Leg) -> f64 {
+        self.model(icao_number, leg.from().datetime().date())
+            .map(|model| crate::emissions::leg_co2_kg(model.gph.into(), leg.duration()))
+            .unwrap_or(0.0)
+    }
+
+    /// Returns the fuel in kg burnt on `leg` by `icao_number` (see [`crate::emissions::fuel_kg`]), using the model of the
+    /// aircraft on the day of departure, or zero if `icao_number` is not in the fleet
+    pub(crate) fn leg_fuel_kg(&self, icao_number: &Arc<str>, leg: &Leg) -> f64 {
+        self.model(icao_number, leg.from().datetime().date())
+            .map(|model| crate::emissions::fuel_kg(model.gph.into(), leg.duration()))
+            .unwrap_or(0.0)
+    }
+
+    fn tail_number(&self, icao_number: &str) -> String {
+        self.private_jets
+            .get(icao_number)
+            .map(|(aircraft, _)| aircraft.tail_number.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl From<PrivateJets> for Fleet {
+    fn from(private_jets: PrivateJets) -> Self {
+        Self {
+            private_jets,
+            models: Default::default(),
+        }
+    }
+}
+
+/// Returns the longest [`Leg::plausible`] [`Leg`] by [`Leg::distance`] and the icao number that flew it.
+pub fn longest_leg(legs: &AircraftLegs) -> Option<(Arc<str>, &Leg)> {
+    legs.iter()
+        .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+        .filter(|(_, leg)| leg.plausible())
+        .max_by(|(_, a), (_, b)| a.distance().total_cmp(&b.distance()))
+        .map(|(icao_number, leg)| (icao_number.clone(), leg))
+}
+
+/// Returns the icao numbers of the aircraft with a leg departing from or arriving to `region`
+/// between `from` (inclusive) and `to` (exclusive).
+pub fn aircraft_in_region(
+    legs: &AircraftLegs,
+    region: impl Fn(&Position) -> bool,
+    from: Date,
+    to: Date,
+) -> HashSet<Arc<str>> {
+    let in_period = |p: &Position| p.datetime().date() >= from && p.datetime().date() < to;
+    legs.iter()
+        .filter(|(_, legs)| {
+            legs.iter().any(|leg| {
+                [leg.from(), leg.to()]
+                    .into_iter()
+                    .any(|p| in_period(p) && region(p))
+            })
+        })
+        .map(|(icao_number, _)| icao_number.clone())
+        .collect()
+}
+
+/// Returns the icao numbers present in at least `k` of `sets` (e.g. the results of [`aircraft_in_region`]
+/// for different regions and periods). Use `k = sets.len()` for the icao numbers present in all of them.
+pub fn overlap(sets: &[HashSet<Arc<str>>], k: usize) -> HashSet<Arc<str>> {
+    sets.iter()
+        .flatten()
+        .fold(
+            HashMap::<&Arc<str>, usize>::new(),
+            |mut acc, icao_number| {
+                *acc.entry(icao_number).or_default() += 1;
+                acc
+            },
+        )
+        .into_iter()
+        .filter(|(_, count)| *count >= k)
+        .map(|(icao_number, _)| icao_number.clone())
+        .collect()
+}
+
+/// The rule under which [`Leg`]s are considered consolidatable into a single flight by [`consolidation_savings`].
+///
+/// Two legs are on the same route when their origins (and their destinations) are within `radius`.
+/// Legs on the same route departing on the same (UTC) day within `window` of the first departure
+/// are consolidated, up to `max_legs` legs per flight. Since the occupancy of private jets is not known,
+/// `max_legs` represents how many (low occupancy) legs fit in the capacity of a single flight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Consolidation {
+    /// The maximum distance in km between origins (and between destinations) of legs on the same route
+    pub radius: f64,
+    /// The maximum time between the departures of consolidated legs
+    pub window: time::Duration,
+    /// The maximum number of legs consolidated into a single flight
+    pub max_legs: usize,
+}
+
+impl Default for Consolidation {
+    fn default() -> Self {
+        Self {
+            radius: 10.0,
+            window: time::Duration::hours(3),
+            max_legs: 2,
+        }
+    }
+}
+
+/// Returns the CO2 emissions in kg that would have been avoided had the legs been consolidated according to `rule`.
+/// For each group of consolidated legs, the emissions of all but the largest emitting leg are avoided.
+pub fn consolidation_savings(fleet: &Fleet, legs: &AircraftLegs, rule: &Consolidation) -> f64 {
+    let mut all = legs
+        .iter()
+        .flat_map(|(icao_number, legs)| {
+            legs.iter()
+                .map(move |leg| (leg, fleet.leg_co2_kg(icao_number, leg)))
+        })
+        .collect::<Vec<_>>();
+    all.sort_unstable_by_key(|(leg, _)| leg.from().datetime());
+
+    let same_route = |a: &Leg, b: &Leg| {
+        a.from().datetime().date() == b.from().datetime().date()
+            && a.from().distace(b.from()) <= rule.radius
+            && a.to().distace(b.to()) <= rule.radius
+    };
+
+    let mut consolidated = vec![false; all.len()];
+    let mut savings = 0.0;
+    for i in 0..all.len() {
+        if consolidated[i] {
+            continue;
+        }
+        consolidated[i] = true;
+        let (first, first_co2) = all[i];
+        let mut group = vec![first_co2];
+        for j in i + 1..all.len() {
+            let (leg, co2) = all[j];
+            if group.len() >= rule.max_legs
+                || leg.from().datetime() - first.from().datetime() > rule.window
+            {
+                break;
+            }
+            if !consolidated[j] && same_route(first, leg) {
+                consolidated[j] = true;
+                group.push(co2);
+            }
+        }
+        savings += group.iter().sum::<f64>() - group.iter().copied().fold(0.0, f64::max);
+    }
+    savings
+}
+
+/// The rule under which [`Leg`]s are considered positioning (ferry) legs by [`positioning_legs`].
+///
+/// Since passengers are not known, a leg is considered positioning when the aircraft returns from
+/// its destination to within `radius` of its origin, departing within `turnaround` of its arrival:
+/// it dropped off (or picked up) passengers and flew back empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Positioning {
+    /// The maximum distance in km between the origin of a leg and the destination of its return
+    pub radius: f64,
+    /// The maximum time on the ground between a leg and its return
+    pub turnaround: time::Duration,
+}
+
+impl Default for Positioning {
+    fn default() -> Self {
+        Self {
+            radius: 10.0,
+            turnaround: time::Duration::hours(2),
+        }
+    }
+}
+
+/// Returns whether each of `legs` of a single aircraft, sorted by departure, is a positioning leg
+/// according to `rule`. The return is the positioning leg.
+pub fn positioning_legs(legs: &[Leg], rule: &Positioning) -> Vec<bool> {
+    let mut positioning = vec![false; legs.len()];
+    for (i, pair) in legs.windows(2).enumerate() {
+        let (leg, next) = (&pair[0], &pair[1]);
+        // a positioning leg is not itself returned from
+        if !positioning[i]
+            && next.from().datetime() - leg.to().datetime() <= rule.turnaround
+            && next.to().distace(leg.from()) <= rule.radius
+        {
+            positioning[i + 1] = true;
+        }
+    }
+    positioning
+}
+
+/// The class of a [`Leg`] according to a [`Positioning`] rule (see [`classify_legs`])
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LegClass {
+    /// A leg presumably flown with passengers
+    Passenger,
+    /// A leg presumably flown empty to return or reposition the aircraft
+    Positioning,
+}
+
+/// Returns the [`LegClass`] of each of `legs` of a single aircraft, sorted by departure, according to `rule`
+/// (see [`positioning_legs`]). Occupancy is not known, so this is a heuristic.
+pub fn classify_legs(legs: &[Leg], rule: &Positioning) -> Vec<LegClass> {
+    positioning_legs(legs, rule)
+        .into_iter()
+        .map(|positioning| {
+            if positioning {
+                LegClass::Positioning
+            } else {
+                LegClass::Passenger
+            }
+        })
+        .collect()
+}
+
+/// Returns the CO2 emissions in kg of `legs` split between passenger and positioning legs
+/// (see [`positioning_legs`]), in this order.
+pub fn positioning_co2(fleet: &Fleet, legs: &AircraftLegs, rule: &Positioning) -> (f64, f64) {
+    legs.iter()
+        .flat_map(|(icao_number, legs)| {
+            positioning_legs(legs, rule)
+                .into_iter()
+                .zip(legs)
+                .map(move |(positioning, leg)| (positioning, fleet.leg_co2_kg(icao_number, leg)))
+        })
+        .fold((0.0, 0.0), |(passenger, ferry), (positioning, co2)| {
+            if positioning {
+                (passenger, ferry + co2)
+            } else {
+                (passenger + co2, ferry)
+            }
+        })
+}
+
+/// A [`Leg`] of a private jet as reported
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LegFact {
+    /// The ICAO number of the aircraft (e.g. `459CD3`)
+    pub icao_number: Arc<str>,
+    /// The tail number of the aircraft (e.g. `OY-GFS`)
+    pub tail_number: String,
+    /// Where the leg started
+    pub from: LatLon,
+    /// Where the leg ended
+    pub to: LatLon,
+    /// The total two-dimensional flown distance of the leg in km
+    pub distance: f64,
+    /// CO2 emissions in kg
+    pub co2_emissions: f64,
+}
+
+impl LegFact {
+    fn new(fleet: &Fleet, icao_number: Arc<str>, leg: &Leg) -> Self {
+        Self {
+            tail_number: fleet.tail_number(&icao_number),
+            from: leg.from().pos(),
+            to: leg.to().pos(),
+            distance: leg.distance(),
+            co2_emissions: fleet.leg_co2_kg(&icao_number, leg),
+            icao_number,
+        }
+    }
+}
+
+/// The legs of a private jet as reported
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AircraftFact {
+    /// The ICAO number of the aircraft (e.g. `459CD3`)
+    pub icao_number: Arc<str>,
+    /// The tail number of the aircraft (e.g. `OY-GFS`)
+    pub tail_number: String,
+    /// The number of legs
+    pub legs: usize,
+    /// The total airborne time in hours, i.e. the sum of the [`Leg::duration`] of its legs
+    pub flight_hours: f64,
+    /// The total CO2 emissions in kg
+    pub co2_emissions: f64,
+    /// The CO2 emissions in kg per flight hour, or `None` when it was not airborne
+    pub kg_co2_per_flight_hour: Option<f64>,
+}
+
+/// The default distance in km below which a leg is short, i.e. where rail is a competitive alternative
+pub static SHORT_LEG_KM: f64 = 300.0;
+
+/// The legs of a report shorter than a distance
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShortLegs {
+    /// The great-circle distance in km below which a leg is short
+    pub threshold: f64,
+    /// The number of short legs
+    pub legs: usize,
+    /// The fraction of all legs that are short, between 0 and 1 (0 without legs)
+    pub fraction: f64,
+    /// The CO2 emissions in kg of the short legs
+    pub co2_emissions: f64,
+}
+
+/// Returns the [`ShortLegs`] of `legs` whose great-circle distance is below `threshold` km
+/// (e.g. [`SHORT_LEG_KM`])
+pub fn short_legs(fleet: &Fleet, legs: &AircraftLegs, threshold: f64) -> ShortLegs {
+    let all = legs.values().map(|legs| legs.len()).sum::<usize>();
+    let short = legs
+        .iter()
+        .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+        .filter(|(_, leg)| leg.great_circle_distance() < threshold)
+        .collect::<Vec<_>>();
+    ShortLegs {
+        threshold,
+        legs: short.len(),
+        fraction: if all > 0 {
+            short.len() as f64 / all as f64
+        } else {
+            0.0
+        },
+        co2_emissions: short
+            .iter()
+            .map(|(icao_number, leg)| fleet.leg_co2_kg(icao_number, leg))
+            .sum(),
+    }
+}
+
+/// The default boundaries in km of the distance bands of [`emissions_by_distance_band`],
+/// i.e. the bands `< 200`, `200 - 500`, `500 - 1000` and `>= 1000` km
+pub static DISTANCE_BANDS_KM: [f64; 3] = [200.0, 500.0, 1000.0];
+
+/// The legs of a report whose great-circle distance is within a band
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DistanceBand {
+    /// The great-circle distance in km from which a leg is in the band (inclusive)
+    pub from: f64,
+    /// The great-circle distance in km until which a leg is in the band (exclusive), or `None` for the last band
+    pub to: Option<f64>,
+    /// The number of legs in the band
+    pub legs: usize,
+    /// The CO2 emissions in kg of the legs in the band
+    pub co2_emissions: f64,
+}
+
+/// Returns the legs and CO2 emissions of `legs` within each band of great-circle distance delimited by the
+/// boundaries `bands` in km (e.g. [`DISTANCE_BANDS_KM`]), from 0 to the first boundary and from the last to
+/// any distance, e.g. to show the emissions of short legs that rail could replace (see also [`short_legs`]).
+/// Boundaries are sorted and deduplicated, so there is one more band than distinct boundaries.
+pub fn emissions_by_distance_band(
+    fleet: &Fleet,
+    legs: &AircraftLegs,
+    bands: &[f64],
+) -> Vec<DistanceBand> {
+    let mut boundaries = bands.to_vec();
+    boundaries.sort_unstable_by(f64::total_cmp);
+    boundaries.dedup();
+
+    let mut bands = std::iter::once(0.0)
+        .chain(boundaries.iter().copied())
+        .zip(boundaries.iter().copied().map(Some).chain([None]))
+        .map(|(from, to)| DistanceBand {
+            from,
+            to,
+            legs: 0,
+            co2_emissions: 0.0,
+        })
+        .collect::<Vec<_>>();
+    for (icao_number, leg) in legs
+        .iter()
+        .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+    {
+        // the number of boundaries at or below the distance is the index of its band
+        let distance = leg.great_circle_distance();
+        let band = &mut bands[boundaries.partition_point(|boundary| *boundary <= distance)];
+        band.legs += 1;
+        band.co2_emissions += fleet.leg_co2_kg(icao_number, leg);
+    }
+    bands
+}
+
+/// The legs of the private jets of an owner or operator
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OwnerSummary {
+    /// The number of private jets with legs
+    pub private_jets: usize,
+    /// The number of legs
+    pub legs: usize,
+    /// The total two-dimensional flown distance in km
+    pub distance: f64,
+    /// The total CO2 emissions in kg
+    pub co2_emissions: f64,
+}
+
+/// Returns an [`OwnerSummary`] of `legs` per owner or operator ([`Aircraft::owner`](crate::aircraft::Aircraft::owner))
+/// of the private jets of `fleet`. Private jets without a known owner are not included.
+pub fn group_legs_by_owner(fleet: &Fleet, legs: &AircraftLegs) -> HashMap<String, OwnerSummary> {
+    legs.iter()
+        .filter(|(_, legs)| !legs.is_empty())
+        .filter_map(|(icao_number, legs)| {
+            let (aircraft, _) = fleet.private_jets.get(icao_number)?;
+            Some((aircraft.owner.clone()?, icao_number, legs))
+        })
+        .fold(HashMap::new(), |mut acc, (owner, icao_number, legs)| {
+            let summary = acc.entry(owner).or_insert(OwnerSummary {
+                private_jets: 0,
+                legs: 0,
+                distance: 0.0,
+                co2_emissions: 0.0,
+            });
+            summary.private_jets += 1;
+            summary.legs += legs.len();
+            summary.distance += legs.iter().map(|leg| leg.distance()).sum::<f64>();
+            summary.co2_emissions += legs
+                .iter()
+                .map(|leg| fleet.leg_co2_kg(icao_number, leg))
+                .sum::<f64>();
+            acc
+        })
+}
+
+/// Returns an [`AircraftFact`] per aircraft with legs, ranked by CO2 emissions (largest first)
+pub fn aircraft_ranking(fleet: &Fleet, legs: &AircraftLegs) -> Vec<AircraftFact> {
+    let mut ranking = legs
+        .iter()
+        .filter(|(_, legs)| !legs.is_empty())
+        .map(|(icao_number, legs)| {
+            let flight_hours = legs
+                .iter()
+                .map(|leg| leg.duration().as_seconds_f64() / 60.0 / 60.0)
+                .sum::<f64>();
+            let co2_emissions = legs
+                .iter()
+                .map(|leg| fleet.leg_co2_kg(icao_number, leg))
+                .sum::<f64>();
+            AircraftFact {
+                icao_number: icao_number.clone(),
+                tail_number: fleet.tail_number(icao_number),
+                legs: legs.len(),
+                flight_hours,
+                co2_emissions,
+                kg_co2_per_flight_hour: (flight_hours > 0.0).then(|| co2_emissions / flight_hours),
+            }
+        })
+        .collect::<Vec<_>>();
+    ranking.sort_unstable_by(|a, b| {
+        b.co2_emissions
+            .total_cmp(&a.co2_emissions)
+            .then_with(|| a.icao_number.cmp(&b.icao_number))
+    });
+    ranking
+}
+
+/// The history of a single private jet over a period, e.g. for a page per private jet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AircraftReport {
+    /// The ICAO number of the aircraft (e.g. `459CD3`)
+    pub icao_number: Arc<str>,
+    /// The tail number of the aircraft (e.g. `OY-GFS`)
+    pub tail_number: String,
+    /// The number of legs
+    pub legs: usize,
+    /// The total two-dimensional flown distance in km
+    pub distance: f64,
+    /// The total CO2 emissions in kg
+    pub co2_emissions: f64,
+    /// The ICAO codes of the airports where its legs started or ended (see [`Leg::from_airport`]), sorted
+    pub airports: Vec<String>,
+    /// Its first position in the period, if any
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub first_seen: Option<time::OffsetDateTime>,
+    /// Its last position in the period, if any
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_seen: Option<time::OffsetDateTime>,
+    /// The CO2 equivalent emissions, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub co2e: Option<Co2eFact>,
+}
+
+/// The options of [`AircraftReport::new`] and [`aircraft_report`]
+#[derive(Debug, Clone, Copy)]
+pub struct AircraftReportOptions<'a> {
+    /// The airports where legs start or end
+    pub airports: &'a Airports,
+    /// The maximum distance in km from an airport for a leg's start or end to be at it (see [`crate::airports::DEFAULT_RADIUS`])
+    pub radius: f64,
+    /// The options to identify the legs
+    pub legs: AircraftLegsOptions,
+    /// The options to report CO2 equivalent emissions (see [`AircraftReport::co2e`])
+    pub emissions: EmissionsOptions,
+}
+
+impl AircraftReport {
+    /// Returns the [`AircraftReport`] of `icao_number` from its positions, ordered by time.
+    /// Legs are identified with [`AircraftReportOptions::legs`] (see [`aircraft_legs_with`](crate::icao_to_trace::aircraft_legs_with)),
+    /// and airports are the nearest of [`AircraftReportOptions::airports`] within [`AircraftReportOptions::radius`] km
+    /// of the start or end of each leg.
+    pub fn new(
+        fleet: &Fleet,
+        icao_number: Arc<str>,
+        positions: Vec<Position>,
+        options: &AircraftReportOptions<'_>,
+    ) -> Self {
+        let first_seen = positions.first().map(|p| p.datetime());
+        let last_seen = positions.last().map(|p| p.datetime());
+        let positions = match options.legs.smooth_window {
+            Some(window) => crate::legs::smooth_altitude(positions, window),
+            None => positions,
+        };
+        let legs =
+            crate::legs::legs_with(positions.into_iter(), options.legs.legs).collect::<Vec<_>>();
+        let co2_emissions = legs
+            .iter()
+            .map(|leg| fleet.leg_co2_kg(&icao_number, leg))
+            .sum();
+
+        let airports = legs
+            .iter()
+            .flat_map(|leg| {
+                [
+                    leg.from_airport(options.airports, options.radius),
+                    leg.to_airport(options.airports, options.radius),
+                ]
+            })
+            .flatten()
+            .map(|airport| airport.icao.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        Self {
+            tail_number: fleet.tail_number(&icao_number),
+            legs: legs.len(),
+            distance: legs.iter().map(|leg| leg.distance()).sum(),
+            co2_emissions,
+            airports,
+            first_seen,
+            last_seen,
+            co2e: Co2eFact::new(co2_emissions, &options.emissions),
+            icao_number,
+        }
+    }
+}
+
+/// Returns the [`AircraftReport`] of `icao_number` between `from` (inclusive) and `to` (exclusive).
+/// # Implementation
+/// The positions are retrieved by [`aircraft_positions`](crate::icao_to_trace::aircraft_positions),
+/// at most [`AircraftLegsOptions::concurrency`] days concurrently (see [`AircraftReport::new`]).
+/// # Error
+/// Errors when positions cannot be retrieved
+pub async fn aircraft_report(
+    icao_number: &Arc<str>,
+    from: Date,
+    to: Date,
+    fleet: &Fleet,
+    client: &dyn BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    options: &AircraftReportOptions<'_>,
+) -> Result<AircraftReport, Box<dyn Error>> {
+    let positions = crate::icao_to_trace::aircraft_positions(
+        from,
+        to,
+        icao_number,
+        client,
+        fetcher,
+        options.legs.concurrency,
+    )
+    .await?;
+    Ok(AircraftReport::new(
+        fleet,
+        icao_number.clone(),
+        positions,
+        options,
+    ))
+}
+
+/// Returns the CO2 emissions in kg of `legs` by the month (UTC) of their departure, e.g. to chart trends.
+/// Months without legs are omitted.
+pub fn monthly_emissions(fleet: &Fleet, legs: &AircraftLegs) -> BTreeMap<(i32, time::Month), f64> {
+    legs.iter()
+        .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+        .fold(BTreeMap::new(), |mut acc, (icao_number, leg)| {
+            let date = leg.from().datetime().date();
+            *acc.entry((date.year(), date.month())).or_default() +=
+                fleet.leg_co2_kg(icao_number, leg);
+            acc
+        })
+}
+
+/// Returns the number of legs departing on each day between `from` (inclusive) and `to` (exclusive)
+/// by each aircraft, as row labels (icao numbers, sorted), column labels (days) and the grid of counts
+/// (one row per icao number, one column per day), e.g. for a calendar heatmap.
+pub fn activity_matrix(
+    legs: &AircraftLegs,
+    from: Date,
+    to: Date,
+) -> (Vec<Arc<str>>, Vec<Date>, Vec<Vec<u32>>) {
+    let mut icao_numbers = legs.keys().cloned().collect::<Vec<_>>();
+    icao_numbers.sort();
+    let days = crate::DateIter {
+        from,
+        to,
+        increment: time::Duration::days(1),
+    }
+    .collect::<Vec<_>>();
+
+    let grid = icao_numbers
+        .iter()
+        .map(|icao_number| {
+            let mut row = vec![0; days.len()];
+            for leg in &legs[icao_number] {
+                let day = leg.from().datetime().date();
+                if day >= from && day < to {
+                    row[(day - from).whole_days() as usize] += 1;
+                }
+            }
+            row
+        })
+        .collect();
+    (icao_numbers, days, grid)
+}
+
+/// The format of an offset from UTC, e.g. `+01:00`
+static OFFSET: &[time::format_description::FormatItem<'static>] =
+    time::macros::format_description!("[offset_hour sign:mandatory]:[offset_minute]");
+
+/// The time zone of local time, parsed from and displayed as an IANA name (e.g. `Europe/Copenhagen`)
+/// or as an offset from UTC (e.g. `+01:00`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeZone {
+    /// A fixed offset from UTC, not adjusted for daylight saving time
+    Offset(time::UtcOffset),
+    /// A time zone of the IANA database, whose offset from UTC changes with daylight saving time
+    /// (e.g. `+01:00` in winter and `+02:00` in summer in `Europe/Copenhagen`)
+    Iana(&'static time_tz::Tz),
+}
+
+impl TimeZone {
+    pub const UTC: Self = Self::Offset(time::UtcOffset::UTC);
+
+    /// Returns `datetime` in local time
+    pub fn to_local(&self, datetime: time::OffsetDateTime) -> time::OffsetDateTime {
+        match self {
+            Self::Offset(offset) => datetime.to_offset(*offset),
+            Self::Iana(tz) => time_tz::OffsetDateTimeExt::to_timezone(&datetime, *tz),
+        }
+    }
+
+    /// Returns the [`time::OffsetDateTime`] of `datetime` in local time. A local time that happens twice
+    /// (when clocks are set back) is the first one, and one that is skipped (when clocks are set forward)
+    /// is moved forward by the change, as clocks are.
+    pub fn assume_local(&self, datetime: time::PrimitiveDateTime) -> time::OffsetDateTime {
+        use time_tz::{OffsetResult, PrimitiveDateTimeExt};
+        match self {
+            Self::Offset(offset) => datetime.assume_offset(*offset),
+            Self::Iana(tz) => match datetime.assume_timezone(*tz) {
+                OffsetResult::Some(datetime) | OffsetResult::Ambiguous(datetime, _) => datetime,
+                OffsetResult::None => {
+                    // the offset before clocks were set forward
+                    let day_before = datetime - time::Duration::days(1);
+                    datetime.assume_offset(day_before.assume_timezone_utc(*tz).offset())
+                }
+            },
+        }
+    }
+}
+
+impl Default for TimeZone {
+    /// UTC
+    fn default() -> Self {
+        Self::UTC
+    }
+}
+
+impl std::fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offset(offset) => {
+                let offset = offset.format(OFFSET).map_err(|_| std::fmt::Error)?;
+                f.write_str(&offset)
+            }
+            Self::Iana(tz) => f.write_str(time_tz::TimeZone::name(*tz)),
+        }
+    }
+}
+
+impl std::str::FromStr for TimeZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(offset) = time::UtcOffset::parse(s, OFFSET) {
+            return Ok(Self::Offset(offset));
+        }
+        time_tz::timezones::get_by_name(s)
+            .map(Self::Iana)
+            .ok_or_else(|| {
+                format!("\"{s}\" is neither an offset (e.g. `+01:00`) nor an IANA time zone (e.g. `Europe/Copenhagen`)")
+            })
+    }
+}
+
+impl Serialize for TimeZone {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeZone {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The number of legs departing on each hour of the day and day of the week, in local time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DepartureStats {
+    /// The time zone of local time
+    pub time_zone: TimeZone,
+    /// The number of legs departing on each hour (`0` is 00:00 to 01:00)
+    pub by_hour: [usize; 24],
+    /// The number of legs departing on each day of the week (`0` is monday)
+    pub by_weekday: [usize; 7],
+}
+
+/// Returns the [`DepartureStats`] of `legs` in local time of `time_zone`.
+/// With an IANA time zone, each leg is in the local time of its departure, adjusted for daylight saving time;
+/// with an offset, all legs are at that offset.
+pub fn departure_histogram(legs: &AircraftLegs, time_zone: TimeZone) -> DepartureStats {
+    legs.values().flatten().fold(
+        DepartureStats {
+            time_zone,
+            by_hour: [0; 24],
+            by_weekday: [0; 7],
+        },
+        |mut stats, leg| {
+            let departure = time_zone.to_local(leg.from().datetime());
+            stats.by_hour[departure.hour() as usize] += 1;
+            stats.by_weekday[departure.weekday().number_days_from_monday() as usize] += 1;
+            stats
+        },
+    )
+}
+
+/// A weekly window of local time, e.g. to contrast business with leisure use of private jets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BusinessHours {
+    /// The start (inclusive) of the window on each day, in local time
+    pub start: time::Time,
+    /// The end (exclusive) of the window on each day, in local time
+    pub end: time::Time,
+    /// The time zone of local time (e.g. `Europe/Copenhagen`, or a fixed offset such as `+01:00`)
+    pub time_zone: TimeZone,
+    /// Whether saturdays and sundays are part of the window
+    pub include_weekends: bool,
+}
+
+impl Default for BusinessHours {
+    /// Monday to friday from 07:00 to 19:00 UTC
+    fn default() -> Self {
+        Self {
+            start: time::macros::time!(07:00),
+            end: time::macros::time!(19:00),
+            time_zone: TimeZone::UTC,
+            include_weekends: false,
+        }
+    }
+}
+
+impl BusinessHours {
+    /// Returns whether `leg`, from its start to its end, overlaps the window
+    pub fn overlaps(&self, leg: &Leg) -> bool {
+        let departure = self.time_zone.to_local(leg.from().datetime());
+        let arrival = self.time_zone.to_local(leg.to().datetime());
+        crate::DateIter {
+            from: departure.date(),
+            to: arrival
+                .date()
+                .next_day()
+                .expect("dates to be far from the end of time"),
+            increment: time::Duration::days(1),
+        }
+        .filter(|day| {
+            self.include_weekends
+                || !matches!(
+                    day.weekday(),
+                    time::Weekday::Saturday | time::Weekday::Sunday
+                )
+        })
+        .any(|day| {
+            let start = self.time_zone.assume_local(day.with_time(self.start));
+            let end = self.time_zone.assume_local(day.with_time(self.end));
+            departure < end && arrival >= start
+        })
+    }
+}
+
+/// An aircraft as reported
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AircraftId {
+    /// The ICAO number of the aircraft (e.g. `459CD3`)
+    pub icao_number: Arc<str>,
+    /// The tail number of the aircraft (e.g. `OY-GFS`)
+    pub tail_number: String,
+}
+
+/// The changes in the set of active aircraft between two consecutive periods
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ActivityChanges {
+    /// The aircraft with legs in the period but not in the previous one, sorted by icao number
+    pub newly_active: Vec<AircraftId>,
+    /// The aircraft with legs in the previous period but not in the period, sorted by icao number
+    pub newly_dormant: Vec<AircraftId>,
+}
+
+/// Returns the [`ActivityChanges`] between the period from `from` (inclusive) to `to` (exclusive)
+/// and the previous period of the same duration (ending at `from`), based on when `legs` departed.
+pub fn activity_changes(
+    fleet: &Fleet,
+    legs: &AircraftLegs,
+    from: Date,
+    to: Date,
+) -> ActivityChanges {
+    let active = |from: Date, to: Date| {
+        legs.iter()
+            .filter(|(_, legs)| {
+                legs.iter().any(|leg| {
+                    let day = leg.from().datetime().date();
+                    day >= from && day < to
+                })
+            })
+            .map(|(icao_number, _)| icao_number.clone())
+            .collect::<HashSet<_>>()
+    };
+    let current = active(from, to);
+    let previous = active(from - (to - from), from);
+
+    let ids = |icao_numbers: HashSet<&Arc<str>>| {
+        let mut ids = icao_numbers
+            .into_iter()
+            .map(|icao_number| AircraftId {
+                icao_number: icao_number.clone(),
+                tail_number: fleet.tail_number(icao_number),
+            })
+            .collect::<Vec<_>>();
+        ids.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+        ids
+    };
+    ActivityChanges {
+        newly_active: ids(current.difference(&previous).collect()),
+        newly_dormant: ids(previous.difference(&current).collect()),
+    }
+}
+
+/// A place of a known country (e.g. an airport), used by [`nearest_country`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CountryPlace {
+    /// The country (in ISO 3166)
+    pub country: Arc<str>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Returns the country of the nearest of `places` to `position` within `radius` km, if any
+pub fn nearest_country(places: &[CountryPlace], position: LatLon, radius: f64) -> Option<Arc<str>> {
+    places
+        .iter()
+        .map(|place| {
+            let pos = LatLon {
+                latitude: place.latitude,
+                longitude: place.longitude,
+            };
+            (place, crate::geo::great_circle_distance_km(pos, position))
+        })
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(place, _)| place.country.clone())
+}
+
+/// The legs between a country and a foreign country
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CountryFact {
+    /// The foreign country (in ISO 3166)
+    pub country: Arc<str>,
+    /// The number of legs to the foreign country
+    pub legs_to: usize,
+    /// The number of legs from the foreign country
+    pub legs_from: usize,
+    /// The total CO2 emissions in kg of the legs to and from the foreign country
+    pub co2_emissions: f64,
+}
+
+/// The foreign countries private jets flew to and from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CountryBreakdown {
+    /// The foreign countries, ranked by number of legs (largest first)
+    pub ranking: Vec<CountryFact>,
+    /// The number of legs excluded because the country of their start or end could not be resolved
+    pub unresolved_legs: usize,
+}
+
+/// Returns the foreign countries the legs departing from or arriving to `country` flew to or from,
+/// where the country of each end of a leg is resolved with `resolve` (see [`Leg::route_countries`]).
+/// Domestic legs and legs not touching `country` are ignored.
+pub fn foreign_countries(
+    fleet: &Fleet,
+    legs: &AircraftLegs,
+    country: &str,
+    resolve: impl Fn(LatLon) -> Option<Arc<str>>,
+) -> CountryBreakdown {
+    let mut unresolved_legs = 0;
+    let mut facts = HashMap::<Arc<str>, CountryFact>::new();
+    for (icao_number, leg) in legs
+        .iter()
+        .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+    {
+        let (Some(from), Some(to)) = leg.route_countries(&resolve) else {
+            unresolved_legs += 1;
+            continue;
+        };
+        let (foreign, outbound) = match (from.as_ref() == country, to.as_ref() == country) {
+            (true, false) => (to, true),
+            (false, true) => (from, false),
+            _ => continue,
+        };
+        let fact = facts.entry(foreign.clone()).or_insert_with(|| CountryFact {
+            country: foreign,
+            legs_to: 0,
+            legs_from: 0,
+            co2_emissions: 0.0,
+        });
+        if outbound {
+            fact.legs_to += 1;
+        } else {
+            fact.legs_from += 1;
+        }
+        fact.co2_emissions += fleet.leg_co2_kg(icao_number, leg);
+    }
+    let mut ranking = facts.into_values().collect::<Vec<_>>();
+    ranking.sort_unstable_by(|a, b| {
+        (b.legs_to + b.legs_from)
+            .cmp(&(a.legs_to + a.legs_from))
+            .then_with(|| a.country.cmp(&b.country))
+    });
+    CountryBreakdown {
+        ranking,
+        unresolved_legs,
+    }
+}
+
+/// The CO2 emissions of the legs of a report compared to the annual emissions of citizens of a country
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CitizenFact {
+    /// The country (in ISO 3166)
+    pub country: String,
+    /// The year of the per capita emissions
+    pub year: i32,
+    /// The annual CO2 emissions per capita in tons
+    pub tons_co2_per_capita: f64,
+    /// The number of citizens whose annual emissions equal the emissions of the legs
+    pub citizens: f64,
+    /// The source of the per capita emissions, including its year
+    pub source: String,
+}
+
+impl CitizenFact {
+    /// Returns a new [`CitizenFact`] of `co2_emissions` in kg compared to `citizen`
+    pub fn new(co2_emissions: f64, citizen: &CitizenEmissions) -> Self {
+        Self {
+            country: citizen.country.clone(),
+            year: citizen.year,
+            tons_co2_per_capita: citizen.tons_co2,
+            citizens: co2_emissions / 1000.0 / citizen.tons_co2,
+            source: format!("{} ({})", citizen.source, citizen.year),
+        }
+    }
+}
+
+/// The CO2 emissions of a passenger flying the legs of a report commercially
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommercialFact {
+    /// The class of the seat
+    pub class: Class,
+    /// The CO2 emissions in kg of a passenger of `class` flying the great-circle distance of the legs
+    pub co2_emissions: f64,
+    /// The source of the emissions per passenger and km
+    pub source: String,
+}
+
+impl CommercialFact {
+    /// Returns a new [`CommercialFact`] of a passenger of `class` flying `legs` commercially
+    pub fn new(legs: &AircraftLegs, class: Class) -> Self {
+        let distance = legs
+            .values()
+            .flatten()
+            .map(|leg| leg.great_circle_distance())
+            .sum::<f64>();
+        Self {
+            class,
+            co2_emissions: class.co2_kg(distance),
+            source: format!(
+                "{} kg CO2 per passenger km in economy times {} in {class} (UK government GHG conversion factors, long-haul)",
+                crate::emissions::ECONOMY_KG_PER_PASSENGER_KM,
+                class.factor()
+            ),
+        }
+    }
+}
+
+/// The CO2 equivalent emissions of the legs of a report
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Co2eFact {
+    /// The CO2e emissions in kg
+    pub co2e_emissions: f64,
+    /// The assumptions used to derive the CO2e emissions from the CO2 emissions
+    pub source: String,
+}
+
+impl Co2eFact {
+    /// Returns a new [`Co2eFact`] of `co2_emissions` in kg under `options`,
+    /// or `None` when `options` report CO2 only
+    pub fn new(co2_emissions: f64, options: &EmissionsOptions) -> Option<Self> {
+        options.assumptions().map(|source| Self {
+            co2e_emissions: options.co2e_kg(co2_emissions),
+            source,
+        })
+    }
+}
+
+/// Summary of the legs of a set of private jets over a period
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    /// The number of private jets that flew
+    pub private_jets: usize,
+    /// The number of legs
+    pub legs: usize,
+    /// The total two-dimensional flown distance in km
+    pub distance: f64,
+    /// The total airborne time in hours, i.e. the sum of the [`Leg::duration`] of the legs
+    #[serde(default)]
+    pub flight_hours: f64,
+    /// The total CO2 emissions in kg
+    pub co2_emissions: f64,
+    /// The CO2 emissions in kg of passenger legs
+    #[serde(default)]
+    pub passenger_co2: f64,
+    /// The CO2 emissions in kg of positioning legs under [`Positioning::default`]
+    #[serde(default)]
+    pub positioning_co2: f64,
+    /// The longest plausible leg
+    pub longest_leg: Option<LegFact>,
+    /// The CO2 emissions in kg that would have been avoided had legs been consolidated under [`Consolidation::default`]
+    #[serde(default)]
+    pub consolidation_savings: f64,
+    /// The legs shorter than [`SHORT_LEG_KM`] (see [`short_legs`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_legs: Option<ShortLegs>,
+    /// The legs and emissions by bands of distance, [`DISTANCE_BANDS_KM`] by default (see [`emissions_by_distance_band`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_bands: Option<Vec<DistanceBand>>,
+    /// The private jets that flew, ranked by CO2 emissions (see [`aircraft_ranking`])
+    pub ranking: Vec<AircraftFact>,
+    /// The foreign countries flown to and from (see [`foreign_countries`]), when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreign_countries: Option<CountryBreakdown>,
+    /// The changes in active aircraft since the previous period (see [`activity_changes`]), when compared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity_changes: Option<ActivityChanges>,
+    /// The emissions compared to the annual emissions of citizens, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub citizen_emissions: Option<CitizenFact>,
+    /// The CO2 equivalent emissions, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub co2e: Option<Co2eFact>,
+    /// The emissions compared to relatable activities (see [`crate::emissions::equivalents`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub equivalents: Option<Equivalents>,
+    /// The emissions of a passenger flying the same legs commercially, when requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commercial: Option<CommercialFact>,
+    /// The departures of legs by hour of the day and day of the week (see [`departure_histogram`]), in UTC by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub departures: Option<DepartureStats>,
+}
+
+impl Report {
+    /// Returns a new [`Report`] of `legs` flown by the private jets of `fleet`.
+    pub fn new(fleet: &Fleet, legs: &AircraftLegs) -> Self {
+        let all = || {
+            legs.iter()
+                .flat_map(|(icao_number, legs)| legs.iter().map(move |leg| (icao_number, leg)))
+        };
+
+        let (passenger_co2, positioning_co2) =
+            positioning_co2(fleet, legs, &Positioning::default());
+        let co2_emissions = all()
+            .map(|(icao_number, leg)| fleet.leg_co2_kg(icao_number, leg))
+            .sum::<f64>();
+
+        Self {
+            private_jets: legs.values().filter(|legs| !legs.is_empty()).count(),
+            legs: all().count(),
+            distance: all().map(|(_, leg)| leg.distance()).sum(),
+            flight_hours: all()
+                .map(|(_, leg)| leg.duration().as_seconds_f64() / 60.0 / 60.0)
+                .sum(),
+            co2_emissions,
+            passenger_co2,
+            positioning_co2,
+            longest_leg: longest_leg(legs)
+                .map(|(icao_number, leg)| LegFact::new(fleet, icao_number, leg)),
+            consolidation_savings: consolidation_savings(fleet, legs, &Consolidation::default()),
+            short_legs: Some(short_legs(fleet, legs, SHORT_LEG_KM)),
+            distance_bands: Some(emissions_by_distance_band(fleet, legs, &DISTANCE_BANDS_KM)),
+            ranking: aircraft_ranking(fleet, legs),
+            foreign_countries: None,
+            activity_changes: None,
+            citizen_emissions: None,
+            equivalents: Some(crate::emissions::equivalents(co2_emissions / 1000.0)),
+            co2e: None,
+            commercial: None,
+            departures: Some(departure_histogram(legs, TimeZone::UTC)),
+        }
+    }
+}
+
+/// The [`Report`] of a country, as part of a [`Comparison`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CountryReport {
+    /// The country (in ISO 3166)
+    pub country: String,
+    pub report: Report,
+}
+
+/// The [`Report`]s of countries over the same period, compared side by side
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub countries: Vec<CountryReport>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{aircraft::Aircraft, Position};
+
+    use super::*;
+
+    fn model(gph: u32) -> Arc<AircraftModel> {
+        Arc::new(AircraftModel {
+            model: "GULFSTREAM 5".to_string(),
+            gph,
+            source: "".to_string(),
+            date: "".to_string(),
+        })
+    }
+
+    fn fleet(icao_numbers: &[&str]) -> Fleet {
+        let model = model(400);
+        icao_numbers
+            .iter()
+            .map(|&icao_number| {
+                let aircraft = Aircraft {
+                    icao_number: icao_number.into(),
+                    tail_number: format!("T-{icao_number}"),
+                    type_designator: "GLF5".to_string(),
+                    model: model.model.clone(),
+                    country: None,
+                    military: false,
+                    owner: None,
+                };
+                (icao_number.into(), (Arc::new(aircraft), model.clone()))
+            })
+            .collect::<PrivateJets>()
+            .into()
+    }
+
+    fn leg(points: &[(i64, f64, f64)]) -> Leg {
+        Leg::new(
+            points
+                .iter()
+                .map(|&(t, latitude, longitude)| Position {
+                    datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+                    latitude,
+                    longitude,
+                    altitude: Some(30000.0),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn longest() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let lax = (33.942, -118.408);
+        let legs = AircraftLegs::from([
+            (
+                "a".into(),
+                vec![leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)])],
+            ),
+            (
+                "b".into(),
+                vec![leg(&[(0, cph.0, cph.1), (11 * hour, lax.0, lax.1)])],
+            ),
+            // teleports to (0, 0) and back within one hour
+            (
+                "c".into(),
+                vec![leg(&[
+                    (0, cph.0, cph.1),
+                    (1, 0.0, 0.0),
+                    (hour, cph.0, cph.1),
+                ])],
+            ),
+        ]);
+
+        let (icao_number, leg) = longest_leg(&legs).unwrap();
+        assert_eq!(icao_number.as_ref(), "b");
+        assert!((leg.distance() - 9000.0).abs() < 100.0);
+
+        let report = Report::new(&Fleet::default(), &legs);
+        assert_eq!(report.legs, 3);
+        assert_eq!(
+            report.longest_leg.unwrap().from,
+            LatLon {
+                latitude: cph.0,
+                longitude: cph.1
+            }
+        );
+    }
+
+    #[test]
+    fn consolidation() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let cph_aal =
+            |departure: i64| leg(&[(departure, cph.0, cph.1), (departure + hour, aal.0, aal.1)]);
+        let fleet = fleet(&["a", "b", "c"]);
+        let one_leg = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+
+        // a and b fly the same route 1h apart; c flies it 12h later
+        let legs = AircraftLegs::from([
+            ("a".into(), vec![cph_aal(8 * hour)]),
+            ("b".into(), vec![cph_aal(9 * hour)]),
+            ("c".into(), vec![cph_aal(21 * hour)]),
+        ]);
+        let savings = consolidation_savings(&fleet, &legs, &Consolidation::default());
+        assert!((savings - one_leg).abs() < 1e-6);
+
+        // all three fit within a 24h window
+        let rule = Consolidation {
+            window: time::Duration::hours(24),
+            max_legs: 3,
+            ..Default::default()
+        };
+        let savings = consolidation_savings(&fleet, &legs, &rule);
+        assert!((savings - 2.0 * one_leg).abs() < 1e-6);
+    }
+
+    #[test]
+    fn positioning() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let fleet = fleet(&["a", "b"]);
+        let legs = AircraftLegs::from([
+            (
+                "a".into(),
+                vec![
+                    leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]),
+                    // returns empty after one hour on the ground
+                    leg(&[(2 * hour, aal.0, aal.1), (3 * hour, cph.0, cph.1)]),
+                    // a later leg is a passenger leg
+                    leg(&[(10 * hour, cph.0, cph.1), (11 * hour, aal.0, aal.1)]),
+                ],
+            ),
+            // returns after a day on the ground
+            (
+                "b".into(),
+                vec![
+                    leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]),
+                    leg(&[(25 * hour, aal.0, aal.1), (26 * hour, cph.0, cph.1)]),
+                ],
+            ),
+        ]);
+        let one_leg = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+
+        assert_eq!(
+            positioning_legs(&legs["a"], &Positioning::default()),
+            vec![false, true, false]
+        );
+        let report = Report::new(&fleet, &legs);
+        assert!((report.positioning_co2 - one_leg).abs() < 1e-6);
+        assert!((report.passenger_co2 - 4.0 * one_leg).abs() < 1e-6);
+        assert!(
+            (report.passenger_co2 + report.positioning_co2 - report.co2_emissions).abs() < 1e-6
+        );
+    }
+
+    #[test]
+    fn per_flight_hour() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let fleet = fleet(&["a", "b"]);
+        let legs = AircraftLegs::from([
+            (
+                "a".into(),
+                vec![
+                    leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]),
+                    // a day on the ground in between is not counted
+                    leg(&[(25 * hour, aal.0, aal.1), (27 * hour, cph.0, cph.1)]),
+                ],
+            ),
+            // zero duration
+            (
+                "b".into(),
+                vec![leg(&[(0, cph.0, cph.1), (0, cph.0, cph.1)])],
+            ),
+        ]);
+
+        let ranking = aircraft_ranking(&fleet, &legs);
+        let a = &ranking[0];
+        assert_eq!(a.icao_number.as_ref(), "a");
+        assert_eq!(a.legs, 2);
+        assert!((a.flight_hours - 3.0).abs() < 1e-9);
+        let expected = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(3));
+        assert!((a.co2_emissions - expected).abs() < 1e-6);
+        assert!((a.kg_co2_per_flight_hour.unwrap() - expected / 3.0).abs() < 1e-6);
+
+        assert_eq!(ranking[1].kg_co2_per_flight_hour, None);
+    }
+
+    #[test]
+    fn short() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let ams = (52.310, 4.768);
+        let fleet = fleet(&["a", "b"]);
+        let legs = AircraftLegs::from([
+            (
+                "a".into(),
+                vec![
+                    // ~240 km
+                    leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]),
+                    // ~620 km
+                    leg(&[(2 * hour, cph.0, cph.1), (4 * hour, ams.0, ams.1)]),
+                ],
+            ),
+            (
+                "b".into(),
+                // ~450 km
+                vec![leg(&[(0, cph.0, cph.1), (hour, 52.0, 12.656)])],
+            ),
+        ]);
+
+        let short = short_legs(&fleet, &legs, SHORT_LEG_KM);
+        assert_eq!(short.legs, 1);
+        assert!((short.fraction - 1.0 / 3.0).abs() < 1e-9);
+        let one_leg = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+        assert!((short.co2_emissions - one_leg).abs() < 1e-6);
+
+        let short = short_legs(&fleet, &legs, 500.0);
+        assert_eq!((short.threshold, short.legs), (500.0, 2));
+        assert!((short.co2_emissions - 2.0 * one_leg).abs() < 1e-6);
+
+        assert_eq!(
+            short_legs(&fleet, &AircraftLegs::new(), 500.0).fraction,
+            0.0
+        );
+    }
+
+    #[test]
+    fn by_owner() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let cph_aal = || leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]);
+        let mut fleet = fleet(&["a", "b", "c", "d"]);
+        for (icao_number, owner) in [("a", "Acme"), ("b", "Acme"), ("c", "Other")] {
+            let (aircraft, _) = fleet.private_jets.get_mut(icao_number).unwrap();
+            Arc::make_mut(aircraft).owner = Some(owner.to_string());
+        }
+        let legs = AircraftLegs::from([
+            ("a".into(), vec![cph_aal(), cph_aal()]),
+            ("b".into(), vec![cph_aal()]),
+            ("c".into(), vec![]),
+            // no known owner
+            ("d".into(), vec![cph_aal()]),
+        ]);
+
+        let owners = group_legs_by_owner(&fleet, &legs);
+        assert_eq!(owners.len(), 1);
+        let acme = &owners["Acme"];
+        assert_eq!((acme.private_jets, acme.legs), (2, 3));
+        let one_leg = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+        assert!((acme.co2_emissions - 3.0 * one_leg).abs() < 1e-6);
+        assert!((acme.distance - 3.0 * cph_aal().distance()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn model_change() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let january = time::macros::date!(1970 - 01 - 01);
+        let february = time::macros::date!(1970 - 02 - 01);
+        let cph_aal =
+            |departure: i64| leg(&[(departure, cph.0, cph.1), (departure + hour, aal.0, aal.1)]);
+        let legs = AircraftLegs::from([(
+            "a".into(),
+            // one leg in january, one in february
+            vec![cph_aal(0), cph_aal(40 * 24 * hour)],
+        )]);
+        let (aircraft, _) = fleet(&["a"]).private_jets.remove("a").unwrap();
+
+        // re-typed in february from a 400 gph to a 200 gph model
+        let fleet = Fleet::new(RequiredTasks::from([
+            (("a".into(), january), (aircraft.clone(), model(400))),
+            (("a".into(), february), (aircraft, model(200))),
+        ]));
+        assert_eq!(fleet.private_jets["a"].1.gph, 200);
+        let report = Report::new(&fleet, &legs);
+        let expected = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1))
+            + crate::emissions::leg_co2_kg(200.0, time::Duration::hours(1));
+        assert!((report.co2_emissions - expected).abs() < 1e-6);
+
+        // without history, the current model is used
+        let fleet = Fleet::from(fleet.private_jets);
+        let report = Report::new(&fleet, &legs);
+        let expected = 2.0 * crate::emissions::leg_co2_kg(200.0, time::Duration::hours(1));
+        assert!((report.co2_emissions - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn countries() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let nce = (43.658, 7.216);
+        let lbg = (48.969, 2.441);
+        let places = [
+            ("Denmark", cph),
+            ("Denmark", aal),
+            ("France", nce),
+            ("France", lbg),
+        ]
+        .map(|(country, (latitude, longitude))| CountryPlace {
+            country: country.into(),
+            latitude,
+            longitude,
+        });
+        let resolve = |pos| nearest_country(&places, pos, 10.0);
+        let flight =
+            |from: (f64, f64), to: (f64, f64)| leg(&[(0, from.0, from.1), (hour, to.0, to.1)]);
+
+        let legs = AircraftLegs::from([
+            (
+                "a".into(),
+                vec![
+                    flight(cph, nce),
+                    flight(nce, cph),
+                    flight(aal, lbg),
+                    // domestic
+                    flight(cph, aal),
+                    // to nowhere known
+                    flight(cph, (0.0, 0.0)),
+                ],
+            ),
+            // not touching Denmark
+            ("b".into(), vec![flight(nce, lbg)]),
+        ]);
+        let fleet = fleet(&["a", "b"]);
+
+        let breakdown = foreign_countries(&fleet, &legs, "Denmark", resolve);
+        assert_eq!(breakdown.unresolved_legs, 1);
+        assert_eq!(breakdown.ranking.len(), 1);
+        let france = &breakdown.ranking[0];
+        assert_eq!(france.country.as_ref(), "France");
+        assert_eq!((france.legs_to, france.legs_from), (2, 1));
+        let one_leg = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+        assert!((france.co2_emissions - 3.0 * one_leg).abs() < 1e-6);
+    }
+
+    #[test]
+    fn activity() {
+        let day = 24 * 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let flight = |t: i64| leg(&[(t, cph.0, cph.1), (t + 3600, aal.0, aal.1)]);
+        let legs = AircraftLegs::from([
+            ("b".into(), vec![flight(0), flight(7200), flight(2 * day)]),
+            // outside of the period
+            ("a".into(), vec![flight(10 * day)]),
+        ]);
+        let from = time::macros::date!(1970 - 01 - 01);
+
+        let (icao_numbers, days, grid) =
+            activity_matrix(&legs, from, from + time::Duration::days(3));
+        assert_eq!(icao_numbers, vec!["a".into(), "b".into()]);
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[2], time::macros::date!(1970 - 01 - 03));
+        assert_eq!(grid, vec![vec![0, 0, 0], vec![2, 0, 1]]);
+    }
+
+    #[test]
+    fn changes() {
+        let day = 24 * 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let flight = |t: i64| leg(&[(t, cph.0, cph.1), (t + 3600, aal.0, aal.1)]);
+        // periods: [day 0, day 10) and [day 10, day 20)
+        let legs = AircraftLegs::from([
+            ("a".into(), vec![flight(day), flight(11 * day)]),
+            ("b".into(), vec![flight(12 * day)]),
+            ("c".into(), vec![flight(2 * day)]),
+        ]);
+        let fleet = fleet(&["a", "b", "c"]);
+        let from = time::macros::date!(1970 - 01 - 11);
+
+        let changes = activity_changes(&fleet, &legs, from, from + time::Duration::days(10));
+        assert_eq!(
+            changes.newly_active,
+            vec![AircraftId {
+                icao_number: "b".into(),
+                tail_number: "T-b".to_string()
+            }]
+        );
+        assert_eq!(changes.newly_dormant.len(), 1);
+        assert_eq!(changes.newly_dormant[0].icao_number.as_ref(), "c");
+    }
+
+    #[test]
+    fn business_hours() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let flight = |t: i64| leg(&[(t, cph.0, cph.1), (t + hour, aal.0, aal.1)]);
+        // 1970-01-06 was a tuesday and 1970-01-10 a saturday
+        let tuesday = 5 * 24 * hour;
+        let saturday = 9 * 24 * hour;
+
+        let hours = BusinessHours::default();
+        assert!(hours.overlaps(&flight(tuesday + 12 * hour)));
+        assert!(!hours.overlaps(&flight(saturday + 12 * hour)));
+        assert!(!hours.overlaps(&flight(tuesday + 22 * hour)));
+        // lands within the window
+        assert!(hours.overlaps(&flight(tuesday + 6 * hour + hour / 2)));
+
+        let weekends = BusinessHours {
+            include_weekends: true,
+            ..Default::default()
+        };
+        assert!(weekends.overlaps(&flight(saturday + 12 * hour)));
+
+        // 18:30 UTC is 20:30 local time at +02:00
+        let local = BusinessHours {
+            time_zone: TimeZone::Offset(time::macros::offset!(+2)),
+            ..Default::default()
+        };
+        assert!(hours.overlaps(&flight(tuesday + 18 * hour + hour / 2)));
+        assert!(!local.overlaps(&leg(&[
+            (tuesday + 18 * hour + hour / 2, cph.0, cph.1),
+            (tuesday + 18 * hour + hour / 2 + 60, aal.0, aal.1)
+        ])));
+
+        // 17:30 UTC is 18:30 in winter and 19:30 in summer in Copenhagen
+        let copenhagen = BusinessHours {
+            time_zone: "Europe/Copenhagen".parse().unwrap(),
+            ..Default::default()
+        };
+        let at = |t: time::OffsetDateTime| {
+            let t = t.unix_timestamp();
+            leg(&[(t, cph.0, cph.1), (t + 60, aal.0, aal.1)])
+        };
+        assert!(copenhagen.overlaps(&at(time::macros::datetime!(2024-01-09 17:30 UTC))));
+        assert!(!copenhagen.overlaps(&at(time::macros::datetime!(2024-07-09 17:30 UTC))));
+    }
+
+    #[test]
+    fn jet_setters() {
+        let hour = 60 * 60;
+        let day = 24 * hour;
+        let zrh = (47.458, 8.548);
+        let sma = (46.529, 9.879); // Samedan, close to Davos
+        let nce = (43.658, 7.216); // Nice, close to Cannes
+        let near = |(latitude, longitude): (f64, f64)| {
+            move |p: &Position| {
+                let center = LatLon {
+                    latitude,
+                    longitude,
+                };
+                crate::geo::great_circle_distance_km(p.pos(), center) < 50.0
+            }
+        };
+        let t0 = time::OffsetDateTime::from_unix_timestamp(0).unwrap().date();
+
+        let legs = AircraftLegs::from([
+            (
+                "a".into(),
+                vec![
+                    leg(&[(0, zrh.0, zrh.1), (hour, sma.0, sma.1)]),
+                    leg(&[(10 * day, sma.0, sma.1), (10 * day + hour, nce.0, nce.1)]),
+                ],
+            ),
+            (
+                "b".into(),
+                vec![leg(&[(0, zrh.0, zrh.1), (hour, sma.0, sma.1)])],
+            ),
+        ]);
+
+        let davos = aircraft_in_region(&legs, near(sma), t0, t0 + time::Duration::days(5));
+        let cannes = aircraft_in_region(
+            &legs,
+            near(nce),
+            t0 + time::Duration::days(5),
+            t0 + time::Duration::days(15),
+        );
+        assert_eq!(davos.len(), 2);
+        assert_eq!(cannes, HashSet::from(["a".into()]));
+
+        let sets = [davos, cannes];
+        assert_eq!(overlap(&sets, 2), HashSet::from(["a".into()]));
+        assert_eq!(overlap(&sets, 1).len(), 2);
+    }
+
+    #[test]
+    fn classify() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let quick = vec![
+            leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]),
+            // repositions after 30 minutes on the ground
+            leg(&[(3 * hour / 2, aal.0, aal.1), (5 * hour / 2, cph.0, cph.1)]),
+        ];
+        let gap = vec![
+            leg(&[(0, cph.0, cph.1), (hour, aal.0, aal.1)]),
+            // returns after three days
+            leg(&[(73 * hour, aal.0, aal.1), (74 * hour, cph.0, cph.1)]),
+        ];
+        let rule = Positioning::default();
+        assert_eq!(
+            classify_legs(&quick, &rule),
+            vec![LegClass::Passenger, LegClass::Positioning]
+        );
+        assert_eq!(
+            classify_legs(&gap, &rule),
+            vec![LegClass::Passenger, LegClass::Passenger]
+        );
+
+        // a longer turnaround
+        let rule = Positioning {
+            turnaround: time::Duration::days(4),
+            ..rule
+        };
+        assert_eq!(
+            classify_legs(&gap, &rule),
+            vec![LegClass::Passenger, LegClass::Positioning]
+        );
+    }
+
+    #[test]
+    fn departures() {
+        let at = |t: time::OffsetDateTime| {
+            let t = t.unix_timestamp();
+            leg(&[(t, 55.618, 12.656), (t + 3600, 57.093, 9.849)])
+        };
+        let legs = AircraftLegs::from([(
+            "a".into(),
+            vec![
+                // monday 08:30 local time
+                at(time::macros::datetime!(2024-01-01 07:30 UTC)),
+                // sunday 23:30 UTC is monday 00:30 local time
+                at(time::macros::datetime!(2024-01-07 23:30 UTC)),
+                // wednesday 12:00 local time
+                at(time::macros::datetime!(2024-01-03 11:00 UTC)),
+            ],
+        )]);
+        let time_zone = TimeZone::Offset(time::macros::offset!(+1));
+
+        let stats = departure_histogram(&legs, time_zone);
+        assert_eq!(stats.time_zone, time_zone);
+        let hours = stats
+            .by_hour
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .collect::<Vec<_>>();
+        assert_eq!(hours, vec![(0, &1), (8, &1), (12, &1)]);
+        assert_eq!(stats.by_weekday, [2, 0, 1, 0, 0, 0, 0]);
+
+        // in UTC, the second leg departs on sunday
+        let stats = departure_histogram(&legs, TimeZone::UTC);
+        assert_eq!(stats.by_weekday, [1, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(stats.by_hour[23], 1);
+
+        // 07:30 UTC is 08:30 in winter and 09:30 in summer in Copenhagen
+        let legs = AircraftLegs::from([(
+            "a".into(),
+            vec![
+                at(time::macros::datetime!(2024-01-01 07:30 UTC)),
+                at(time::macros::datetime!(2024-07-01 07:30 UTC)),
+            ],
+        )]);
+        let copenhagen = "Europe/Copenhagen".parse::<TimeZone>().unwrap();
+        let stats = departure_histogram(&legs, copenhagen);
+        assert_eq!((stats.by_hour[8], stats.by_hour[9]), (1, 1));
+        let stats = departure_histogram(&legs, time_zone);
+        assert_eq!(stats.by_hour[8], 2);
+    }
+
+    #[test]
+    fn time_zone() {
+        let copenhagen = "Europe/Copenhagen".parse::<TimeZone>().unwrap();
+        assert_eq!(copenhagen.to_string(), "Europe/Copenhagen");
+        let offset = "-05:30".parse::<TimeZone>().unwrap();
+        assert_eq!(offset, TimeZone::Offset(time::macros::offset!(-05:30)));
+        assert_eq!(offset.to_string(), "-05:30");
+        assert!("Europe/Atlantis".parse::<TimeZone>().is_err());
+
+        let json = serde_json::to_string(&copenhagen).unwrap();
+        assert_eq!(json, "\"Europe/Copenhagen\"");
+        assert_eq!(serde_json::from_str::<TimeZone>(&json).unwrap(), copenhagen);
+
+        // clocks are set forward from 02:00 to 03:00 (02:30 is 03:30) and back from 03:00 to 02:00
+        let spring = time::macros::datetime!(2024-03-31 02:30);
+        let autumn = time::macros::datetime!(2024-10-27 02:30);
+        assert_eq!(
+            copenhagen.assume_local(spring),
+            time::macros::datetime!(2024-03-31 01:30 UTC)
+        );
+        assert_eq!(
+            copenhagen.assume_local(autumn),
+            time::macros::datetime!(2024-10-27 00:30 UTC)
+        );
+        assert_eq!(
+            copenhagen.assume_local(time::macros::datetime!(2024-07-01 07:00)),
+            time::macros::datetime!(2024-07-01 05:00 UTC)
+        );
+    }
+
+    #[test]
+    fn report_of_older_shape() {
+        // a report serialized before the breakdowns of emissions were added
+        let json = r#"{
+            "private_jets": 1,
+            "legs": 2,
+            "distance": 400.0,
+            "co2_emissions": 1000.0,
+            "longest_leg": null,
+            "ranking": []
+        }"#;
+        let report = serde_json::from_str::<Report>(json).unwrap();
+        assert_eq!(report.legs, 2);
+        assert_eq!(report.co2_emissions, 1000.0);
+        assert_eq!(
+            (
+                report.passenger_co2,
+                report.positioning_co2,
+                report.consolidation_savings
+            ),
+            (0.0, 0.0, 0.0)
+        );
+        assert_eq!(report.flight_hours, 0.0);
+    }
+
+    #[test]
+    fn aircraft_report() {
+        // an altitude of 0 is on the ground
+        let at = |t: i64, (latitude, longitude): (f64, f64), altitude: f64| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude,
+            longitude,
+            altitude: (altitude > 0.0).then_some(altitude),
+        };
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let mid = (56.4, 11.2);
+        let positions = vec![
+            at(0, cph, 0.0),
+            at(240, cph, 12000.0),
+            at(1800, mid, 30000.0),
+            at(3360, aal, 12000.0),
+            at(3600, aal, 0.0),
+            at(2 * hour, aal, 0.0),
+            at(2 * hour + 240, aal, 12000.0),
+            at(2 * hour + 1800, mid, 30000.0),
+            at(3 * hour - 240, cph, 12000.0),
+            at(3 * hour, cph, 0.0),
+        ];
+        let fleet = fleet(&["a"]);
+        let airports = Airports::new();
+        let options = AircraftReportOptions {
+            airports: &airports,
+            radius: crate::airports::DEFAULT_RADIUS,
+            legs: Default::default(),
+            emissions: Default::default(),
+        };
+
+        let report = AircraftReport::new(&fleet, "a".into(), positions.clone(), &options);
+        let legs = crate::legs::legs(positions.clone().into_iter()).collect::<Vec<_>>();
+        assert_eq!(report.tail_number, "T-a");
+        assert_eq!(report.legs, 2);
+        assert_eq!(
+            report.distance,
+            legs.iter().map(|leg| leg.distance()).sum::<f64>()
+        );
+        assert!(report.distance > 2.0 * 200.0);
+        assert_eq!(
+            report.co2_emissions,
+            legs.iter()
+                .map(|leg| fleet.leg_co2_kg(&"a".into(), leg))
+                .sum::<f64>()
+        );
+        assert!(report.co2_emissions > 0.0);
+        assert_eq!(report.airports, vec!["EKCH", "EKYT"]);
+        assert_eq!(report.first_seen.unwrap().unix_timestamp(), 0);
+        assert_eq!(report.last_seen.unwrap().unix_timestamp(), 3 * hour);
+        assert_eq!(report.co2e, None);
+
+        // the options of legs and emissions are used
+        let legs_options = AircraftLegsOptions {
+            legs: crate::legs::LegOptions {
+                low_altitude: 40000.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let high = AircraftReportOptions {
+            legs: legs_options,
+            ..options
+        };
+        let high = AircraftReport::new(&fleet, "a".into(), positions.clone(), &high);
+        assert_eq!(high.legs, 0);
+
+        let co2e = AircraftReportOptions {
+            emissions: EmissionsOptions {
+                rfi: Some(2.0),
+                ..Default::default()
+            },
+            ..options
+        };
+        let co2e = AircraftReport::new(&fleet, "a".into(), positions, &co2e);
+        assert_eq!(
+            co2e.co2e.unwrap().co2e_emissions,
+            2.0 * report.co2_emissions
+        );
+
+        let empty = AircraftReport::new(&fleet, "a".into(), vec![], &options);
+        assert_eq!((empty.legs, empty.first_seen), (0, None));
+        assert!(empty.airports.is_empty());
+    }
+
+    #[test]
+    fn monthly() {
+        let day = 24 * 60 * 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        // a 1 hour leg departing at `t`
+        let cph_aal = |t: i64| leg(&[(t, cph.0, cph.1), (t + 60 * 60, aal.0, aal.1)]);
+        let legs = AircraftLegs::from([
+            // two legs in january (one by each aircraft), none in february, one in march
+            ("a".into(), vec![cph_aal(0), cph_aal(60 * day)]),
+            ("b".into(), vec![cph_aal(10 * day)]),
+        ]);
+        let fleet = fleet(&["a", "b"]);
+        let hour = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+
+        let monthly = monthly_emissions(&fleet, &legs);
+        assert_eq!(
+            monthly.keys().copied().collect::<Vec<_>>(),
+            vec![(1970, time::Month::January), (1970, time::Month::March)]
+        );
+        assert!((monthly[&(1970, time::Month::January)] - 2.0 * hour).abs() < 1e-6);
+        assert!((monthly[&(1970, time::Month::March)] - hour).abs() < 1e-6);
+        let total = monthly.values().sum::<f64>();
+        assert!((total - Report::new(&fleet, &legs).co2_emissions).abs() < 1e-6);
+    }
+
+    #[test]
+    fn flight_hours() {
+        let minute = 60;
+        let cph = (55.618, 12.656);
+        let aal = (57.093, 9.849);
+        let cph_aal = |departure: i64, minutes: i64| {
+            leg(&[
+                (departure, cph.0, cph.1),
+                (departure + minutes * minute, aal.0, aal.1),
+            ])
+        };
+        let legs = AircraftLegs::from([
+            ("a".into(), vec![cph_aal(0, 45), cph_aal(600 * minute, 90)]),
+            ("b".into(), vec![cph_aal(0, 105)]),
+            ("c".into(), vec![]),
+        ]);
+        let report = Report::new(&fleet(&["a", "b", "c"]), &legs);
+        assert!((report.flight_hours - 4.0).abs() < 1e-9);
+        assert_eq!(
+            Report::new(&fleet(&["a"]), &AircraftLegs::new()).flight_hours,
+            0.0
+        );
+    }
+
+    #[test]
+    fn distance_bands() {
+        let hour = 60 * 60;
+        let cph = (55.618, 12.656);
+        // ~100 km, ~240 km, ~620 km and ~1160 km from Copenhagen
+        let destinations = [(55.7, 11.0), (57.093, 9.849), (59.65, 17.92), (66.0, 14.0)];
+        let legs = AircraftLegs::from([(
+            "a".into(),
+            destinations
+                .iter()
+                .enumerate()
+                .map(|(i, to)| {
+                    let t = i as i64 * 10 * hour;
+                    leg(&[(t, cph.0, cph.1), (t + hour, to.0, to.1)])
+                })
+                .collect::<Vec<_>>(),
+        )]);
+        let fleet = fleet(&["a"]);
+        let hour_co2 = crate::emissions::leg_co2_kg(400.0, time::Duration::hours(1));
+
+        let bands = emissions_by_distance_band(&fleet, &legs, &DISTANCE_BANDS_KM);
+        let summary = bands
+            .iter()
+            .map(|band| (band.from, band.to, band.legs))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (0.0, Some(200.0), 1),
+                (200.0, Some(500.0), 1),
+                (500.0, Some(1000.0), 1),
+                (1000.0, None, 1),
+            ]
+        );
+        assert!(bands
+            .iter()
+            .all(|band| (band.co2_emissions - hour_co2).abs() < 1e-6));
+
+        // unsorted and repeated boundaries
+        let bands = emissions_by_distance_band(&fleet, &legs, &[500.0, 300.0, 500.0]);
+        let legs_per_band = bands.iter().map(|band| band.legs).collect::<Vec<_>>();
+        assert_eq!(legs_per_band, vec![2, 0, 2]);
+        // a single band without boundaries
+        let bands = emissions_by_distance_band(&fleet, &legs, &[]);
+        assert_eq!((bands.len(), bands[0].legs, bands[0].to), (1, 4, None));
+    }
+}
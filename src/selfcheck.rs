@@ -0,0 +1,199 @@
+//! Contains the self-check of the pipeline, that computes a [`Report`] from a committed, network-free
+//! fixture and compares it against an expected [`Summary`], so that changes in the methodology are noticed.
+//!
+//! A fixture is a directory with
+//! * `fleet.json`: the period (`from`, `to`) and the private jets (an [`Aircraft`] with the `gph` of its model)
+//! * `traces/{yyyy}/{mm}/{dd}/trace_full_{icao}.json`: the traces as served by adsbexchange. Missing traces are days without positions
+//! * `expected.json`: the expected [`Summary`]
+use std::{error::Error, path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    aircraft::Aircraft,
+    fs::InMemory,
+    http::HttpFetcher,
+    model::AircraftModel,
+    report::{AircraftLegs, Report},
+    PrivateJets,
+};
+
+/// A private jet of a fixture
+#[derive(Deserialize, Debug, Clone)]
+struct FixtureAircraft {
+    #[serde(flatten)]
+    aircraft: Aircraft,
+    /// The consumption of its model in gallons per hour
+    gph: u32,
+}
+
+/// The period and private jets of a fixture
+#[derive(Deserialize, Debug, Clone)]
+struct FixtureFleet {
+    #[serde(with = "crate::serde::date")]
+    from: Date,
+    #[serde(with = "crate::serde::date")]
+    to: Date,
+    aircraft: Vec<FixtureAircraft>,
+}
+
+/// [`HttpFetcher`] serving the traces of a fixture
+struct Traces(PathBuf);
+
+#[async_trait::async_trait]
+impl HttpFetcher for Traces {
+    async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+        // e.g. `https://globe.adsbexchange.com/globe_history/2023/01/04/traces/0d/trace_full_45860d.json`
+        let (_, path) = url
+            .split_once("globe_history/")
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, url))?;
+        let parts = path.split('/').collect::<Vec<_>>();
+        let [year, month, day, "traces", _, file] = parts[..] else {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, url));
+        };
+        std::fs::read(self.0.join(year).join(month).join(day).join(file))
+    }
+}
+
+/// The headline numbers of a [`Report`] compared by the self-check
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub private_jets: usize,
+    pub legs: usize,
+    /// in km
+    pub distance: f64,
+    /// in kg
+    pub co2_emissions: f64,
+}
+
+impl From<&Report> for Summary {
+    fn from(report: &Report) -> Self {
+        Self {
+            private_jets: report.private_jets,
+            legs: report.legs,
+            distance: report.distance,
+            co2_emissions: report.co2_emissions,
+        }
+    }
+}
+
+/// Returns the [`Summary`] of the [`Report`] computed from the fixture in `dir`.
+/// # Implementation
+/// Positions are computed from the traces in the fixture by the same functions used against adsbexchange,
+/// cached in memory, so that this neither requires network nor writes to disk.
+/// # Error
+/// Errors when the fixture cannot be read
+pub async fn run(dir: impl Into<PathBuf>) -> Result<Summary, Box<dyn Error>> {
+    let dir = dir.into();
+    let fleet: FixtureFleet = serde_json::from_slice(&std::fs::read(dir.join("fleet.json"))?)?;
+    let client = InMemory::default();
+    let fetcher = Traces(dir.join("traces"));
+
+    let private_jets = fleet
+        .aircraft
+        .into_iter()
+        .map(|FixtureAircraft { aircraft, gph }| {
+            let model = AircraftModel {
+                model: aircraft.model.clone(),
+                gph,
+                source: "fixture".to_string(),
+                date: "".to_string(),
+            };
+            (
+                aircraft.icao_number.clone(),
+                (Arc::new(aircraft), Arc::new(model)),
+            )
+        })
+        .collect::<PrivateJets>();
+
+    let mut legs = AircraftLegs::new();
+    for icao_number in private_jets.keys() {
+        let aircraft_legs = crate::icao_to_trace::aircraft_legs(
+            fleet.from,
+            fleet.to,
+            icao_number,
+            &client,
+            &fetcher,
+            crate::icao_to_trace::DEFAULT_CONCURRENCY,
+        )
+        .await?;
+        legs.insert(icao_number.clone(), aircraft_legs);
+    }
+
+    Ok(Summary::from(&Report::new(&private_jets.into(), &legs)))
+}
+
+/// Returns whether `actual` matches `expected`: counts must be equal and
+/// the remaining numbers must be within a relative `tolerance` (e.g. `0.01` for 1%).
+/// # Error
+/// Errors with a description of each mismatch
+pub fn check(actual: &Summary, expected: &Summary, tolerance: f64) -> Result<(), String> {
+    let mut mismatches = vec![];
+    let counts = [
+        ("private_jets", actual.private_jets, expected.private_jets),
+        ("legs", actual.legs, expected.legs),
+    ];
+    for (name, actual, expected) in counts {
+        if actual != expected {
+            mismatches.push(format!("{name}: expected {expected}, got {actual}"));
+        }
+    }
+    let numbers = [
+        ("distance", actual.distance, expected.distance),
+        (
+            "co2_emissions",
+            actual.co2_emissions,
+            expected.co2_emissions,
+        ),
+    ];
+    for (name, actual, expected) in numbers {
+        if (actual - expected).abs() > tolerance * expected.abs() {
+            mismatches.push(format!(
+                "{name}: expected {expected:.1} (± {:.1}%), got {actual:.1}",
+                tolerance * 100.0
+            ));
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(mismatches.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static FIXTURE: &str = "tests/fixtures/selfcheck";
+
+    #[tokio::test]
+    async fn fixture() {
+        let actual = run(FIXTURE).await.unwrap();
+        let expected: Summary =
+            serde_json::from_slice(&std::fs::read(format!("{FIXTURE}/expected.json")).unwrap())
+                .unwrap();
+        check(&actual, &expected, 0.001).unwrap();
+    }
+
+    #[test]
+    fn tolerance() {
+        let expected = Summary {
+            private_jets: 1,
+            legs: 2,
+            distance: 1000.0,
+            co2_emissions: 2000.0,
+        };
+        let mut actual = Summary {
+            distance: 1009.0,
+            ..expected.clone()
+        };
+        assert!(check(&actual, &expected, 0.01).is_ok());
+
+        actual.distance = 1011.0;
+        actual.legs = 3;
+        let error = check(&actual, &expected, 0.01).unwrap_err();
+        assert!(error.contains("legs") && error.contains("distance"));
+    }
+}
@@ -1,5 +1,12 @@
 use std::collections::HashMap;
 
+// (De)serializes a [`time::Date`] in ISO 8601 ("2023-01-31"), for use in `#[serde(with = "...")]`
+time::serde::format_description!(
+    pub date,
+    Date,
+    "[year]-[month]-[day]"
+);
+
 /// Returns the ISO 8601 representation of a month ("2023-01")
 pub fn month_to_part(date: time::Date) -> String {
     format!("{}-{:02}", date.year(), date.month() as u8)
@@ -19,7 +26,7 @@ pub fn parse_month(date: &str) -> time::Date {
     .unwrap()
 }
 
-pub fn hive_to_map<'a>(mut blob: &'a str) -> HashMap<&'a str, &'a str> {
+pub fn hive_to_map(mut blob: &str) -> HashMap<&str, &str> {
     let mut a = HashMap::new();
     while !blob.is_empty() {
         let position = blob.find("=").unwrap();
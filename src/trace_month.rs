@@ -1,118 +1,427 @@
-use std::{collections::HashSet, error::Error, sync::Arc};
+use std::{collections::HashSet, error::Error, path::Path, sync::Arc};
 
-use futures::{StreamExt, TryStreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
 use time::Date;
 
 use super::Position;
-use crate::{fs, icao_to_trace::cached_aircraft_positions};
+use crate::{
+    fs,
+    http::HttpFetcher,
+    icao_to_trace::cached_aircraft_positions,
+    legs::{Leg, LegOptions},
+};
 
-static DATABASE: &'static str = "position/";
+static DATABASE: &str = "position/";
 
-fn pk_to_blob_name(icao: &str, date: time::Date) -> String {
+/// The default maximum number of concurrent tasks of [`aircraft_positions`] and [`month_positions`]
+pub static DEFAULT_CONCURRENCY: usize = 4;
+
+fn pk_to_prefix(icao: &str, date: time::Date) -> String {
     let month = crate::serde::month_to_part(date);
-    format!("{DATABASE}icao_number={icao}/month={month}/data.json",)
+    format!("{DATABASE}icao_number={icao}/month={month}/")
+}
+
+fn pk_to_blob_name(icao: &str, date: time::Date) -> String {
+    format!("{}data.json", pk_to_prefix(icao, date))
 }
 
-fn blob_name_to_pk(blob: &str) -> (Arc<str>, time::Date) {
-    let mut keys = crate::serde::hive_to_map(&blob[DATABASE.len()..blob.len() - "data.json".len()]);
-    let icao = keys.remove("icao_number").unwrap();
-    let date = keys.remove("month").unwrap();
-    (icao.into(), crate::serde::parse_month(date))
+/// Returns the (icao, month) of a blob named by [`pk_to_blob_name`], or `None` when the name has any other structure
+fn blob_name_to_pk(blob: &str) -> Option<(Arc<str>, time::Date)> {
+    let keys = blob.strip_prefix(DATABASE)?.strip_suffix("/data.json")?;
+    let (icao, month) = keys.split_once('/')?;
+    let icao = icao.strip_prefix("icao_number=")?;
+    let month = month.strip_prefix("month=")?;
+    if icao.is_empty() || icao.contains('/') {
+        return None;
+    }
+    let month = time::Date::parse(
+        &format!("{month}-01"),
+        time::macros::format_description!("[year]-[month]-[day]"),
+    )
+    .ok()?;
+    Some((icao.into(), month))
+}
+
+/// Returns whether `blob` is named after a complete date instead of a month
+/// (e.g. `position/icao_number=aa/month=2024-01-05/data.json`).
+/// Such daily blobs are not part of the database of positions, whose blobs are monthly (see [`pk_to_blob_name`]),
+/// and are not read: positions of each day are cached as traces instead (see [`crate::icao_to_trace::globe_history_cached`]).
+fn is_daily_blob(blob: &str) -> bool {
+    let Some(month) = blob
+        .strip_prefix(DATABASE)
+        .and_then(|keys| keys.strip_suffix("/data.json"))
+        .and_then(|keys| keys.rsplit_once("/month="))
+        .map(|(_, month)| month)
+    else {
+        return false;
+    };
+    time::Date::parse(
+        month,
+        time::macros::format_description!("[year]-[month]-[day]"),
+    )
+    .is_ok()
+}
+
+/// Returns the (icao, month) of the monthly blobs of `blobs` (see [`blob_name_to_pk`]), ignoring [partial](is_partial_blob)
+/// months and logging how many were skipped because they are [daily](is_daily_blob) or have any other structure
+fn blob_names_to_pks<'a>(blobs: impl IntoIterator<Item = &'a str>) -> Vec<(Arc<str>, Date)> {
+    let (mut daily, mut other) = (0, 0);
+    let pks = blobs
+        .into_iter()
+        .filter_map(|blob| {
+            let pk = blob_name_to_pk(blob);
+            if pk.is_none() && !is_partial_blob(blob) {
+                if is_daily_blob(blob) {
+                    daily += 1;
+                } else {
+                    other += 1;
+                }
+            }
+            pk
+        })
+        .collect();
+    if daily > 0 {
+        log::info!("skipped {daily} daily blobs of positions");
+    }
+    if other > 0 {
+        log::warn!("skipped {other} blobs of positions with an unknown name");
+    }
+    pks
+}
+
+/// Returns the (first day of the) months with at least one day between `from` (inclusive) and `to` (exclusive), in chronological order
+fn months(from: Date, to: Date) -> Vec<Date> {
+    let dates = super::DateIter {
+        from,
+        to,
+        increment: time::Duration::days(1),
+    };
+
+    let mut months = dates
+        .map(|x| {
+            time::Date::from_calendar_date(x.year(), x.month(), 1).expect("day 1 never errors")
+        })
+        .collect::<Vec<_>>();
+    months.dedup();
+    months
 }
 
 /// Returns the first day of the next month
 pub fn first_of_next_month(month: &time::Date) -> time::Date {
     let next_month = month.month().next();
-    (next_month == time::Month::January)
-        .then(|| {
-            time::Date::from_calendar_date(month.year() + 1, time::Month::January, 1)
-                .expect("day 1 never errors")
-        })
-        .unwrap_or_else(|| {
-            time::Date::from_calendar_date(month.year(), next_month, 1).expect("day 1 never errors")
-        })
+    if next_month == time::Month::January {
+        time::Date::from_calendar_date(month.year() + 1, time::Month::January, 1)
+            .expect("day 1 never errors")
+    } else {
+        time::Date::from_calendar_date(month.year(), next_month, 1).expect("day 1 never errors")
+    }
 }
 
-/// Returns the positions of an aircraft at a given month, ordered by timestamp
+/// Returns the positions of an aircraft at a given month, ordered by timestamp.
+/// Days without a trace (e.g. when the aircraft did not fly) have no positions; a month without any is empty.
+/// At most `concurrency` days are fetched concurrently (see [`DEFAULT_CONCURRENCY`]).
 /// # Implementation
 /// This function is idempotent but not pure:
 /// * the data is retrieved from `https://globe.adsbexchange.com`
 /// * the call is cached on local disk or Remote Blob (depending on `client` configuration)
+/// * past months are immutable and served from the cache, while the current month is
+///   still growing and is therefore always refetched and its month blob is never written (see [`fs::CacheAction::from_date`])
+/// * the positions of the complete days of the current month are appended to a partial month
+///   (`partial.json`, next to the month blob), so that refetching it only fetches the days since the last fetch, and days after today are not fetched
 pub async fn month_positions(
     icao_number: &str,
     month: time::Date,
     client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
+) -> Result<Vec<Position>, std::io::Error> {
+    let today = time::OffsetDateTime::now_utc().date();
+    month_positions_at(icao_number, month, today, client, fetcher, concurrency).await
+}
+
+/// The positions of the first days of a month that are complete, stored while the month is still growing
+/// (see [`month_positions`]). It is removed once the complete month is stored.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct Partial {
+    /// The number of complete days since the start of the month
+    days: u8,
+    /// The positions of those days, ordered by timestamp
+    positions: Vec<Position>,
+}
+
+fn pk_to_partial_blob_name(icao: &str, date: time::Date) -> String {
+    format!("{}partial.json", pk_to_prefix(icao, date))
+}
+
+/// Returns whether `blob` is a [`Partial`] month (see [`pk_to_partial_blob_name`])
+fn is_partial_blob(blob: &str) -> bool {
+    blob.starts_with(DATABASE) && blob.ends_with("/partial.json")
+}
+
+/// Like [`month_positions`], with `today` instead of the current date (UTC)
+async fn month_positions_at(
+    icao_number: &str,
+    month: time::Date,
+    today: time::Date,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
 ) -> Result<Vec<Position>, std::io::Error> {
     log::info!("month_positions({icao_number},{month})");
     assert_eq!(month.day(), 1);
-    let blob_name = pk_to_blob_name(&icao_number, month);
+    let blob_name = pk_to_blob_name(icao_number, month);
+    let partial_blob_name = pk_to_partial_blob_name(icao_number, month);
 
     let to = first_of_next_month(&month);
-    let action = fs::CacheAction::from_date(&to);
+    let action = fs::CacheAction::from_date_at(&to, today);
+    // days after today have no positions yet
+    let tomorrow = today
+        .next_day()
+        .expect("dates to be far from the end of time");
+    let until = to.min(tomorrow);
+    // days before today are complete
+    let complete = to.min(today.max(month));
 
+    let mut merged = false;
     let fetch = async {
-        // fetch all positions for the month for icao
-        let tasks = cached_aircraft_positions(icao_number, month, to, client);
+        let partial = match client.maybe_get(&partial_blob_name).await? {
+            Some(data) => serde_json::from_slice::<Partial>(&data)?,
+            None => Partial::default(),
+        };
+        let start = month
+            .checked_add(time::Duration::days(partial.days.into()))
+            .unwrap_or(month)
+            .min(until);
+
+        // fetch the positions of the days that are not in the partial month
+        let tasks = cached_aircraft_positions(icao_number, start, until, today, client, fetcher);
         let mut positions = futures::stream::iter(tasks)
             // limit concurrent tasks
-            .buffered(5)
+            .buffered(concurrency)
             .try_collect::<Vec<_>>()
             .await
             .map(|x| x.into_iter().flatten().collect::<Vec<_>>())?;
 
         // sort them
         positions.sort_unstable_by_key(|p| p.datetime());
+        let positions = partial
+            .positions
+            .into_iter()
+            .chain(positions)
+            .collect::<Vec<_>>();
+
+        if action == fs::CacheAction::Fetch {
+            // append the days completed since the partial month was stored
+            let days = (complete - month).whole_days() as u8;
+            if days > partial.days && client.can_put() {
+                let partial = Partial {
+                    days,
+                    positions: positions
+                        .iter()
+                        .filter(|p| p.datetime().date() < complete)
+                        .cloned()
+                        .collect(),
+                };
+                client
+                    .put(&partial_blob_name, serde_json::to_vec(&partial)?)
+                    .await?;
+            }
+        } else {
+            merged = partial.days > 0;
+        }
         let mut bytes: Vec<u8> = Vec::new();
         serde_json::to_writer(&mut bytes, &positions)?;
         Ok(bytes)
     };
 
     let r = fs::cached_call(&blob_name, fetch, client, action).await?;
+    // the complete month is stored
+    if merged && client.can_put() {
+        if let Err(e) = client.delete(&partial_blob_name).await {
+            log::warn!("month_positions({icao_number},{month}): {e}");
+        }
+    }
     Ok(serde_json::from_slice(&r)?)
 }
 
-/// Returns a list of positions within two dates ordered by timestamp
+/// The progress of fetching the positions of an aircraft, reported each time a month completes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub icao_number: Arc<str>,
+    /// The (first day of the) month that completed
+    pub month: Date,
+    /// The number of months completed so far, including `month`
+    pub completed: usize,
+    /// The total number of months
+    pub total: usize,
+}
+
+fn ignore(_: Progress) {}
+
+/// Returns a stream of the positions within two dates of each month, as `(first day of the month, positions)`,
+/// so that callers can process them (e.g. into legs) without holding all of them in memory.
+/// Months are yielded in chronological order and their positions are ordered by timestamp.
+/// At most `concurrency` months, and days within each month, are fetched concurrently (see [`DEFAULT_CONCURRENCY`]).
 /// # Implementation
 /// This function is idempotent but not pure:
 /// * the data is retrieved from `https://globe.adsbexchange.com`
 /// * the call is cached on local disk or Remote Blob (depending on `client` configuration)
 /// * the data is retrieved in batches of months and cached, to reduce IO
+pub fn aircraft_positions_stream<'a>(
+    from: Date,
+    to: Date,
+    icao_number: &'a str,
+    client: &'a dyn fs::BlobStorageProvider,
+    fetcher: &'a dyn HttpFetcher,
+    concurrency: usize,
+) -> impl Stream<Item = Result<(Date, Vec<Position>), std::io::Error>> + 'a {
+    aircraft_positions_stream_with_progress(
+        from,
+        to,
+        icao_number,
+        client,
+        fetcher,
+        concurrency,
+        &ignore,
+    )
+}
+
+/// Like [`aircraft_positions_stream`], calling `progress` each time a month is yielded, e.g. to render a progress bar.
+/// `progress` is called from the stream, so it should not block (e.g. use `try_send` to send it to a channel).
+pub fn aircraft_positions_stream_with_progress<'a>(
+    from: Date,
+    to: Date,
+    icao_number: &'a str,
+    client: &'a dyn fs::BlobStorageProvider,
+    fetcher: &'a dyn HttpFetcher,
+    concurrency: usize,
+    progress: &'a (dyn Fn(Progress) + Send + Sync),
+) -> impl Stream<Item = Result<(Date, Vec<Position>), std::io::Error>> + 'a {
+    let months = months(from, to);
+    let total = months.len();
+    let icao: Arc<str> = icao_number.into();
+    let mut completed = 0;
+
+    futures::stream::iter(months)
+        .map(move |month| async move {
+            let positions = month_positions(icao_number, month, client, fetcher, concurrency)
+                .await?
+                .into_iter()
+                .filter(|p| (p.datetime().date() >= from) && (p.datetime().date() < to))
+                .collect();
+            Ok((month, positions))
+        })
+        // limit concurrent tasks; keeps the order of the months
+        .buffered(concurrency)
+        .inspect_ok(move |(month, _)| {
+            completed += 1;
+            progress(Progress {
+                icao_number: icao.clone(),
+                month: *month,
+                completed,
+                total,
+            })
+        })
+}
+
+/// Returns a list of positions within two dates ordered by timestamp.
+/// See [`aircraft_positions_stream`] to process them month by month.
 pub async fn aircraft_positions(
     from: Date,
     to: Date,
     icao_number: &str,
     client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
 ) -> Result<Vec<Position>, Box<dyn Error>> {
-    let dates = super::DateIter {
+    aircraft_positions_with_progress(from, to, icao_number, client, fetcher, concurrency, &ignore)
+        .await
+}
+
+/// Like [`aircraft_positions`], calling `progress` each time a month completes
+/// (see [`aircraft_positions_stream_with_progress`]).
+pub async fn aircraft_positions_with_progress(
+    from: Date,
+    to: Date,
+    icao_number: &str,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> Result<Vec<Position>, Box<dyn Error>> {
+    let months = aircraft_positions_stream_with_progress(
         from,
         to,
-        increment: time::Duration::days(1),
-    };
+        icao_number,
+        client,
+        fetcher,
+        concurrency,
+        progress,
+    )
+    .try_collect::<Vec<_>>()
+    .await?;
+    Ok(months
+        .into_iter()
+        .flat_map(|(_, positions)| positions)
+        .collect())
+}
 
-    let months = dates
-        .clone()
-        .map(|x| {
-            time::Date::from_calendar_date(x.year(), x.month(), 1).expect("day 1 never errors")
-        })
-        .collect::<HashSet<_>>();
+/// Returns the [`Leg`]s of an aircraft within two dates, identified from its positions (see [`aircraft_positions`]).
+/// The authentication to adsbexchange is part of `fetcher` (see [`crate::http`]), so that callers only
+/// choose where positions are cached and fetched from.
+pub async fn aircraft_legs(
+    from: Date,
+    to: Date,
+    icao_number: &str,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
+) -> Result<Vec<Leg>, Box<dyn Error>> {
+    let options = AircraftLegsOptions {
+        concurrency,
+        ..Default::default()
+    };
+    aircraft_legs_with(from, to, icao_number, client, fetcher, &options).await
+}
 
-    let tasks = months
-        .into_iter()
-        .map(|month| async move { month_positions(icao_number, month, client).await });
+/// The options to identify the [`Leg`]s of an aircraft from its positions by [`aircraft_legs_with`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AircraftLegsOptions {
+    /// The maximum number of months, and days within each, fetched concurrently (see [`DEFAULT_CONCURRENCY`])
+    pub concurrency: usize,
+    /// Optional number of positions over which altitude is smoothed before identifying legs
+    /// (see [`crate::legs::smooth_altitude`])
+    pub smooth_window: Option<usize>,
+    /// The options to identify legs (see [`crate::legs::legs_with`])
+    pub legs: LegOptions,
+}
 
-    let positions = futures::stream::iter(tasks)
-        // limit concurrent tasks
-        .buffered(10)
-        .try_collect::<Vec<_>>()
-        .await?;
+impl Default for AircraftLegsOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            smooth_window: None,
+            legs: LegOptions::default(),
+        }
+    }
+}
 
-    let mut positions = positions
-        .into_iter()
-        .flatten()
-        .filter(|p| (p.datetime().date() >= from) && (p.datetime().date() < to))
-        .collect::<Vec<_>>();
-    positions.sort_unstable_by_key(|p| p.datetime());
-    Ok(positions)
+/// Like [`aircraft_legs`], with `options`
+pub async fn aircraft_legs_with(
+    from: Date,
+    to: Date,
+    icao_number: &str,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    options: &AircraftLegsOptions,
+) -> Result<Vec<Leg>, Box<dyn Error>> {
+    let positions =
+        aircraft_positions(from, to, icao_number, client, fetcher, options.concurrency).await?;
+    let positions = match options.smooth_window {
+        Some(window) => crate::legs::smooth_altitude(positions, window),
+        None => positions,
+    };
+    Ok(crate::legs::legs_with(positions.into_iter(), options.legs).collect())
 }
 
 /// Returns the positions of an aircraft at a given month from the database.
@@ -123,43 +432,818 @@ pub async fn get_month_positions(
     client: &dyn fs::BlobStorageProvider,
 ) -> Result<Vec<Position>, std::io::Error> {
     log::info!("get_months_positions({icao_number},{month})");
+    maybe_get_month_positions(icao_number, month, client)
+        .await?
+        .ok_or_else(|| {
+            let blob_name = pk_to_blob_name(icao_number, month);
+            std::io::Error::other(format!("{blob_name} does not exist"))
+        })
+}
+
+/// Returns the positions of an aircraft at a given month from the database, or `None` when they were not stored
+pub async fn maybe_get_month_positions(
+    icao_number: &str,
+    month: time::Date,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<Option<Vec<Position>>, std::io::Error> {
     assert_eq!(month.day(), 1);
-    let blob_name = pk_to_blob_name(&icao_number, month);
+    let blob_name = pk_to_blob_name(icao_number, month);
 
-    let r = client
+    client
         .maybe_get(&blob_name)
         .await?
-        .ok_or_else(|| std::io::Error::other(format!("{blob_name} does not exist")))?;
-    Ok(serde_json::from_slice(&r)?)
+        .map(|r| serde_json::from_slice(&r).map_err(std::io::Error::from))
+        .transpose()
 }
 
 /// Returns the set of (icao, month) that exists in the db
 pub async fn list_months_positions(
     client: &dyn fs::BlobStorageProvider,
 ) -> Result<HashSet<(Arc<str>, time::Date)>, std::io::Error> {
-    Ok(client
-        .list(DATABASE)
-        .await?
+    let blobs = client.list(DATABASE).await?;
+    Ok(blob_names_to_pks(blobs.iter().map(String::as_str))
         .into_iter()
-        .map(|blob| blob_name_to_pk(&blob))
         .collect())
 }
 
+/// The number of (icao, month) of positions served from the db and fetched from adsbexchange
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+pub struct FetchEstimate {
+    /// The number of (icao, month) in the db
+    pub cached: usize,
+    /// The number of (icao, month) that will be fetched from adsbexchange
+    pub to_fetch: usize,
+}
+
+impl FetchEstimate {
+    /// Returns the [`FetchEstimate`] of `required` given the (icao, month) that exist in the db (`completed`).
+    /// The current month is always fetched (see [`month_positions`]), even when it exists.
+    pub fn new(
+        required: &HashSet<(Arc<str>, time::Date)>,
+        completed: &HashSet<(Arc<str>, time::Date)>,
+    ) -> Self {
+        let cached = required
+            .iter()
+            .filter(|pk| {
+                completed.contains(*pk)
+                    && fs::CacheAction::from_date(&first_of_next_month(&pk.1))
+                        != fs::CacheAction::Fetch
+            })
+            .count();
+        Self {
+            cached,
+            to_fetch: required.len() - cached,
+        }
+    }
+}
+
+/// Returns the [`FetchEstimate`] of the positions of `icao_numbers` between `from` (inclusive) and `to` (exclusive),
+/// i.e. how many months would be served from the db and fetched by [`aircraft_positions`], without fetching any trace.
+/// # Implementation
+/// Lists the existing months with [`list_months_positions`].
+pub async fn estimate_fetches(
+    icao_numbers: &HashSet<Arc<str>>,
+    from: Date,
+    to: Date,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<FetchEstimate, std::io::Error> {
+    let months = months(from, to);
+    let required = icao_numbers
+        .iter()
+        .flat_map(|icao_number| months.iter().map(|month| (icao_number.clone(), *month)))
+        .collect();
+    let completed = list_months_positions(client).await?;
+    Ok(FetchEstimate::new(&required, &completed))
+}
+
+/// The outcome of [`backfill`]
+#[derive(Debug, Default)]
+pub struct Backfill {
+    /// The number of (icao, month) that already existed in the db
+    pub skipped: usize,
+    /// The number of (icao, month) fetched from adsbexchange and stored in the db
+    pub stored: usize,
+    /// The (icao, month) that failed, with their error
+    pub errors: Vec<((Arc<str>, Date), std::io::Error)>,
+}
+
+/// Fetches and stores the positions of every combination of `icao_numbers` and `months` (first day of each month)
+/// that does not exist in the db (see [`list_months_positions`]), e.g. to populate the db with a new month.
+/// At most `concurrency` (icao, month), and days within each, are fetched concurrently (see [`DEFAULT_CONCURRENCY`]).
+/// Errors do not stop the backfill; they are returned in [`Backfill::errors`].
+/// The current month is always fetched (see [`month_positions`]), even when it exists.
+pub async fn backfill(
+    icao_numbers: &[&str],
+    months: &[Date],
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
+) -> Result<Backfill, std::io::Error> {
+    backfill_with_progress(icao_numbers, months, client, fetcher, concurrency, &ignore).await
+}
+
+/// Like [`backfill`], calling `progress` each time an (icao, month) to fetch completes, successfully or not
+pub async fn backfill_with_progress(
+    icao_numbers: &[&str],
+    months: &[Date],
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> Result<Backfill, std::io::Error> {
+    let required = icao_numbers
+        .iter()
+        .flat_map(|icao_number| months.iter().map(|month| (Arc::from(*icao_number), *month)))
+        .collect::<HashSet<_>>();
+    let completed = list_months_positions(client).await?;
+    Ok(backfill_required(
+        &required,
+        &completed,
+        client,
+        fetcher,
+        concurrency,
+        progress,
+    )
+    .await)
+}
+
+/// Like [`backfill_with_progress`], fetching and storing the (icao, month) of `required` that are not in `completed`
+/// (e.g. the result of [`list_months_positions_cached`]), for when they are not every combination of icao numbers and months
+pub async fn backfill_required(
+    required: &HashSet<(Arc<str>, Date)>,
+    completed: &HashSet<(Arc<str>, Date)>,
+    client: &dyn fs::BlobStorageProvider,
+    fetcher: &dyn HttpFetcher,
+    concurrency: usize,
+    progress: &(dyn Fn(Progress) + Send + Sync),
+) -> Backfill {
+    let mut todo = required
+        .iter()
+        .filter(|pk| {
+            !completed.contains(*pk)
+                || fs::CacheAction::from_date(&first_of_next_month(&pk.1)) == fs::CacheAction::Fetch
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    todo.sort_unstable_by(|(icao_a, a), (icao_b, b)| (a, icao_a).cmp(&(b, icao_b)));
+    let total = todo.len();
+    log::info!(
+        "backfill: {} existing, {total} to fetch",
+        required.len() - total
+    );
+
+    let mut result = Backfill {
+        skipped: required.len() - total,
+        ..Default::default()
+    };
+    let tasks = todo.into_iter().map(|(icao_number, month)| async move {
+        let r = month_positions(&icao_number, month, client, fetcher, concurrency).await;
+        ((icao_number, month), r)
+    });
+    let mut results = futures::stream::iter(tasks)
+        // limit concurrent tasks
+        .buffer_unordered(concurrency);
+    let mut completed = 0;
+    while let Some(((icao_number, month), r)) = results.next().await {
+        completed += 1;
+        match r {
+            Ok(_) => result.stored += 1,
+            Err(e) => {
+                log::error!("backfill({icao_number},{month}): {e}");
+                result.errors.push(((icao_number.clone(), month), e))
+            }
+        }
+        progress(Progress {
+            icao_number,
+            month,
+            completed,
+            total,
+        })
+    }
+    result
+}
+
+/// The local catalog of [`list_months_positions_cached`]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Catalog {
+    /// When the db was last listed
+    #[serde(with = "time::serde::rfc3339")]
+    built_at: time::OffsetDateTime,
+    /// The blob names of the (icao, month) that exist in the db
+    blobs: Vec<String>,
+    /// The blob names of the (icao, month) that were listed and did not exist in the db
+    absent: Vec<String>,
+}
+
+impl Catalog {
+    /// Returns the [`Catalog`] at `path`, or `None` when it does not exist or can't be read (e.g. of an older format)
+    fn read(path: &Path) -> Result<Option<Self>, std::io::Error> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match serde_json::from_slice(&data) {
+            Ok(catalog) => Ok(Some(catalog)),
+            Err(e) => {
+                log::warn!(
+                    "catalog: {} is invalid ({e}); rebuilding it",
+                    path.display()
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Writes the catalog to `path` atomically, i.e. to a temporary file that is then renamed to `path`,
+    /// so that an interrupted run never leaves a partial catalog behind
+    fn write(&self, path: &Path) -> Result<(), std::io::Error> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        std::fs::write(&temp, serde_json::to_vec(self)?)?;
+        if let Err(e) = std::fs::rename(&temp, path) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+fn pks_to_blob_names<'a>(pks: impl Iterator<Item = &'a (Arc<str>, time::Date)>) -> Vec<String> {
+    let mut blobs = pks
+        .map(|(icao_number, month)| pk_to_blob_name(icao_number, *month))
+        .collect::<Vec<_>>();
+    blobs.sort_unstable();
+    blobs
+}
+
+/// Returns the set of (icao, month) that exists in the db, like [`list_months_positions`], using and updating
+/// a catalog (a local file at `catalog` with the result of a previous call).
+///
+/// Since positions are never deleted from the db, only the (icao, month) of `required` that the catalog does not know
+/// (e.g. the months since the catalog was built) are listed. The (icao, month) listed that do not exist are recorded as
+/// absent and are not listed again, unless their month had not ended when they were listed (its positions may be
+/// stored later). When the catalog does not exist or `rebuild` is true, all positions are listed.
+///
+/// Positions stored after this call (e.g. by [`backfill_required`]) should be recorded with [`catalog_stored`].
+pub async fn list_months_positions_cached(
+    required: &HashSet<(Arc<str>, time::Date)>,
+    catalog: &Path,
+    rebuild: bool,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<HashSet<(Arc<str>, time::Date)>, std::io::Error> {
+    let cached = if rebuild {
+        None
+    } else {
+        Catalog::read(catalog)?
+    };
+    // positions stored while listing are listed again on the next call
+    let built_at = time::OffsetDateTime::now_utc();
+
+    let (completed, absent) = if let Some(cached) = cached {
+        let completed = blob_names_to_pks(cached.blobs.iter().map(String::as_str))
+            .into_iter()
+            .collect::<HashSet<_>>();
+        // absent (icao, month) of months that had ended when listed are still absent
+        let absent = blob_names_to_pks(cached.absent.iter().map(String::as_str))
+            .into_iter()
+            .filter(|pk| first_of_next_month(&pk.1) <= cached.built_at.date())
+            .collect::<HashSet<_>>();
+        let missing = required
+            .iter()
+            .filter(|pk| !completed.contains(*pk) && !absent.contains(*pk))
+            .collect::<Vec<_>>();
+        let tasks = missing.into_iter().map(|pk| async move {
+            client
+                .list(&pk_to_prefix(&pk.0, pk.1))
+                .await
+                .map(|blobs| (pk, blobs))
+        });
+        // fold each listing into the sets as it resolves, instead of collecting all of them first
+        let (completed, absent, listed) = futures::stream::iter(tasks)
+            .buffered(100)
+            .try_fold(
+                (completed, absent, 0),
+                |(mut completed, mut absent, listed), (pk, blobs)| async move {
+                    let found = blob_names_to_pks(blobs.iter().map(String::as_str));
+                    if !found.contains(pk) {
+                        absent.insert(pk.clone());
+                    }
+                    completed.extend(found);
+                    Ok((completed, absent, listed + 1))
+                },
+            )
+            .await?;
+        log::info!("catalog: listed {listed} (icao, month)");
+        (completed, absent)
+    } else {
+        log::info!("catalog: listing all (icao, month)");
+        let completed = list_months_positions(client).await?;
+        let absent = required
+            .iter()
+            .filter(|pk| !completed.contains(*pk))
+            .cloned()
+            .collect();
+        (completed, absent)
+    };
+
+    Catalog {
+        built_at,
+        blobs: pks_to_blob_names(completed.iter()),
+        absent: pks_to_blob_names(absent.iter()),
+    }
+    .write(catalog)?;
+    Ok(completed)
+}
+
+/// Records in the catalog of [`list_months_positions_cached`] that the (icao, month) of `stored` exist in the db,
+/// e.g. after they were stored by [`backfill_required`], so that they are not considered absent.
+/// Does nothing when the catalog does not exist.
+pub fn catalog_stored<'a>(
+    catalog: &Path,
+    stored: impl IntoIterator<Item = &'a (Arc<str>, time::Date)>,
+) -> Result<(), std::io::Error> {
+    let Some(mut cached) = Catalog::read(catalog)? else {
+        return Ok(());
+    };
+    let mut completed = blob_names_to_pks(cached.blobs.iter().map(String::as_str))
+        .into_iter()
+        .collect::<HashSet<_>>();
+    completed.extend(stored.into_iter().cloned());
+    let absent = blob_names_to_pks(cached.absent.iter().map(String::as_str))
+        .into_iter()
+        .filter(|pk| !completed.contains(pk))
+        .collect::<Vec<_>>();
+    cached.blobs = pks_to_blob_names(completed.iter());
+    cached.absent = pks_to_blob_names(absent.iter());
+    cached.write(catalog)
+}
+
 #[cfg(test)]
 mod test {
+    use std::sync::Mutex;
+
     use fs::LocalDisk;
     use time::macros::date;
 
     use super::*;
 
+    /// A [`fs::BlobStorageProvider`] with a fixed set of blobs that records the prefixes listed
+    struct Listing {
+        blobs: Vec<String>,
+        listed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl fs::BlobStorageProvider for Listing {
+        async fn maybe_get(&self, _: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+            unimplemented!()
+        }
+        async fn put(&self, _: &str, _: Vec<u8>) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+        async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+            self.listed.lock().unwrap().push(prefix.to_string());
+            Ok(self
+                .blobs
+                .iter()
+                .filter(|blob| blob.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+        async fn delete(&self, _: &str) -> Result<(), std::io::Error> {
+            unimplemented!()
+        }
+        fn can_put(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn catalog() {
+        let catalog = std::env::temp_dir().join(format!("catalog-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&catalog);
+        let jan = ("a".into(), date!(2024 - 01 - 01));
+        let feb = ("a".into(), date!(2024 - 02 - 01));
+        let client = Listing {
+            blobs: vec![pk_to_blob_name("a", jan.1)],
+            listed: Default::default(),
+        };
+
+        // no catalog => lists everything
+        let required = HashSet::from([jan.clone()]);
+        let completed = list_months_positions_cached(&required, &catalog, false, &client)
+            .await
+            .unwrap();
+        assert_eq!(completed, required);
+        assert_eq!(*client.listed.lock().unwrap(), vec![DATABASE.to_string()]);
+
+        // a new month => lists only the new month and merges it with the catalog
+        let client = Listing {
+            blobs: vec![pk_to_blob_name("a", jan.1), pk_to_blob_name("a", feb.1)],
+            listed: Default::default(),
+        };
+        let required = HashSet::from([jan.clone(), feb.clone()]);
+        let completed = list_months_positions_cached(&required, &catalog, false, &client)
+            .await
+            .unwrap();
+        assert_eq!(completed, required);
+        assert_eq!(
+            *client.listed.lock().unwrap(),
+            vec![pk_to_prefix("a", feb.1)]
+        );
+
+        // an (icao, month) that does not exist => listed once and recorded as absent
+        let other = ("b".into(), date!(2024 - 01 - 01));
+        let required = HashSet::from([jan.clone(), feb.clone(), other.clone()]);
+        for _ in 0..2 {
+            let completed = list_months_positions_cached(&required, &catalog, false, &client)
+                .await
+                .unwrap();
+            assert_eq!(completed, HashSet::from([jan.clone(), feb.clone()]));
+        }
+        assert_eq!(
+            client.listed.lock().unwrap()[1..],
+            [pk_to_prefix("b", other.1)]
+        );
+
+        // once stored, it is in the catalog without listing it
+        catalog_stored(&catalog, [&other]).unwrap();
+        let completed = list_months_positions_cached(&required, &catalog, false, &client)
+            .await
+            .unwrap();
+        assert_eq!(completed, required);
+        assert_eq!(client.listed.lock().unwrap().len(), 2);
+        // the catalog is written atomically, without leaving temporary files
+        let mut temp = catalog.clone().into_os_string();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        assert!(!Path::new(&temp).exists());
+
+        // rebuild => lists everything
+        let completed = list_months_positions_cached(&required, &catalog, true, &client)
+            .await
+            .unwrap();
+        assert_eq!(completed, HashSet::from([jan, feb]));
+        assert_eq!(client.listed.lock().unwrap().last().unwrap(), DATABASE);
+        std::fs::remove_file(&catalog).unwrap();
+    }
+
+    /// [`HttpFetcher`] returning a trace with one position per day
+    struct Daily;
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for Daily {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            // e.g. `.../globe_history/2019/01/04/traces/0d/trace_full_45860d.json`
+            let (_, path) = url.split_once("globe_history/").unwrap();
+            let date = time::Date::parse(
+                &path[..10],
+                time::macros::format_description!("[year]/[month]/[day]"),
+            )
+            .unwrap();
+            let timestamp = date.midnight().assume_utc().unix_timestamp();
+            let trace = format!(
+                r#"{{"timestamp": {timestamp}, "trace": [[3600.0, 55.6, 12.6, "ground"]]}}"#
+            );
+            Ok(trace.into_bytes())
+        }
+    }
+
+    /// [`HttpFetcher`] failing on every request, to assert that no network call occurs
+    struct Offline;
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for Offline {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            panic!("unexpected network call to {url}")
+        }
+    }
+
+    #[tokio::test]
+    async fn in_memory_cache() {
+        let (from, to) = (date!(2019 - 01 - 01), date!(2019 - 02 - 01));
+        let client = fs::InMemory::default();
+        let positions = aircraft_positions(from, to, "45860d", &client, &Daily, 1)
+            .await
+            .unwrap();
+        assert_eq!(positions.len(), 31);
+        assert!(
+            fs::BlobStorageProvider::maybe_get(&client, &pk_to_blob_name("45860d", from))
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // the month is read back from the cache
+        let cached = aircraft_positions(from, to, "45860d", &client, &Offline, 1)
+            .await
+            .unwrap();
+        assert_eq!(cached, positions);
+    }
+
+    #[tokio::test]
+    async fn disk_layout() {
+        let root = std::env::temp_dir().join(format!("flights-{:x}", rand::random::<u64>()));
+        let disk = fs::Disk::new(&root);
+        let month = date!(2019 - 01 - 01);
+        let positions =
+            aircraft_positions(month, date!(2019 - 02 - 01), "45860d", &disk, &Daily, 2)
+                .await
+                .unwrap();
+        assert_eq!(positions.len(), 31);
+
+        // the month is stored at its blob name under the root
+        let blob_name = pk_to_blob_name("45860d", month);
+        assert_eq!(
+            blob_name,
+            "position/icao_number=45860d/month=2019-01/data.json"
+        );
+        assert!(root.join(&blob_name).is_file());
+        assert_eq!(disk.path(&blob_name), root.join(&blob_name));
+        assert_eq!(
+            fs::BlobStorageProvider::list(&disk, DATABASE)
+                .await
+                .unwrap(),
+            vec![blob_name]
+        );
+        assert_eq!(
+            super::list_months_positions(&disk).await.unwrap(),
+            HashSet::from([("45860d".into(), month)])
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrency() {
+        let (from, to) = (date!(2019 - 01 - 20), date!(2019 - 03 - 10));
+        let positions = |concurrency| async move {
+            let client = fs::InMemory::default();
+            aircraft_positions(from, to, "45860d", &client, &Daily, concurrency)
+                .await
+                .unwrap()
+        };
+        let serial = positions(1).await;
+        assert_eq!(serial.len(), (to - from).whole_days() as usize);
+        assert_eq!(positions(8).await, serial);
+    }
+
+    #[tokio::test]
+    async fn stream() {
+        let (from, to) = (date!(2019 - 01 - 20), date!(2019 - 03 - 10));
+        let client = fs::InMemory::default();
+        let months = aircraft_positions_stream(from, to, "45860d", &client, &Daily, 2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let days = months
+            .iter()
+            .map(|(month, positions)| (*month, positions.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            days,
+            vec![
+                (date!(2019 - 01 - 01), 12),
+                (date!(2019 - 02 - 01), 28),
+                (date!(2019 - 03 - 01), 9)
+            ]
+        );
+
+        let eager = aircraft_positions(from, to, "45860d", &client, &Daily, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            months.into_iter().flat_map(|x| x.1).collect::<Vec<_>>(),
+            eager
+        );
+        assert!(crate::legs::ensure_sorted(&eager));
+    }
+
+    #[tokio::test]
+    async fn progress() {
+        let (from, to) = (date!(2019 - 01 - 20), date!(2019 - 03 - 10));
+        let client = fs::InMemory::default();
+        let events = std::sync::Mutex::new(vec![]);
+        let record = |progress: Progress| events.lock().unwrap().push(progress);
+        aircraft_positions_with_progress(from, to, "45860d", &client, &Daily, 2, &record)
+            .await
+            .unwrap();
+
+        let events = events.into_inner().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events
+                .iter()
+                .map(|p| (p.month, p.completed, p.total))
+                .collect::<Vec<_>>(),
+            vec![
+                (date!(2019 - 01 - 01), 1, 3),
+                (date!(2019 - 02 - 01), 2, 3),
+                (date!(2019 - 03 - 01), 3, 3)
+            ]
+        );
+        assert!(events.iter().all(|p| p.icao_number.as_ref() == "45860d"));
+
+        // e.g. to a channel
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+        let send = move |progress: Progress| {
+            let _ = sender.try_send(progress);
+        };
+        aircraft_positions_with_progress(from, to, "45860d", &client, &Daily, 2, &send)
+            .await
+            .unwrap();
+        let mut received = 0;
+        while let Ok(progress) = receiver.try_recv() {
+            received += 1;
+            assert_eq!(progress.completed, received);
+        }
+        assert_eq!(received, 3);
+    }
+
     #[test]
     fn roundtrip() {
         let icao: Arc<str> = "aa".into();
         let month = date!(2022 - 02 - 01);
         assert_eq!(
             blob_name_to_pk(&pk_to_blob_name(icao.as_ref(), month)),
-            (icao, month)
+            Some((icao, month))
+        );
+        assert_eq!(
+            blob_name_to_pk("position/icao_number=4ca8e1/month=1999-12/data.json"),
+            Some(("4ca8e1".into(), date!(1999 - 12 - 01)))
+        );
+    }
+
+    #[test]
+    fn invalid_blob_names() {
+        for blob in [
+            // a complete date
+            "position/icao_number=aa/month=2024-01-05/data.json",
+            "position/icao_number=aa/month=2024-1/data.json",
+            "position/icao_number=aa/month=2024-13/data.json",
+            "position/icao_number=aa/data.json",
+            "position/month=2024-01/icao_number=aa/data.json",
+            "position/icao_number=/month=2024-01/data.json",
+            "position/icao_number=aa/b/month=2024-01/data.json",
+            "position/icao_number=aa/month=2024-01/data.csv",
+            "leg/icao_number=aa/month=2024-01/data.json",
+            "",
+            "garbage",
+        ] {
+            assert_eq!(blob_name_to_pk(blob), None, "{blob}");
+        }
+    }
+
+    /// [`HttpFetcher`] of [`Daily`] that records the (icao, month) requested, failing for icao `"bad000"`
+    #[derive(Default)]
+    struct Months(Mutex<HashSet<(Arc<str>, Date)>>);
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for Months {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            // e.g. `.../globe_history/2019/01/04/traces/0d/trace_full_45860d.json`
+            let (_, path) = url.split_once("globe_history/").unwrap();
+            let month = time::Date::parse(
+                &format!("{}/01", &path[..7]),
+                time::macros::format_description!("[year]/[month]/[day]"),
+            )
+            .unwrap();
+            let (_, icao) = path.split_once("trace_full_").unwrap();
+            let icao = icao.strip_suffix(".json").unwrap();
+            self.0.lock().unwrap().insert((icao.into(), month));
+            if icao == "bad000" {
+                return Err(std::io::Error::other("unavailable"));
+            }
+            Daily.get_bytes(url).await
+        }
+    }
+
+    #[tokio::test]
+    async fn backfill_missing() {
+        let (jan, feb) = (date!(2019 - 01 - 01), date!(2019 - 02 - 01));
+        let client = fs::InMemory::default();
+        fs::BlobStorageProvider::put(&client, &pk_to_blob_name("aa0001", jan), b"[]".to_vec())
+            .await
+            .unwrap();
+
+        let fetcher = Months::default();
+        let events = Mutex::new(vec![]);
+        let record = |progress: Progress| events.lock().unwrap().push(progress);
+        let result = backfill_with_progress(
+            &["aa0001", "bb0002", "bad000"],
+            &[jan, feb],
+            &client,
+            &fetcher,
+            2,
+            &record,
         )
+        .await
+        .unwrap();
+
+        // only the missing (icao, month) are fetched
+        assert_eq!(
+            fetcher.0.into_inner().unwrap(),
+            HashSet::from([
+                ("aa0001".into(), feb),
+                ("bb0002".into(), jan),
+                ("bb0002".into(), feb),
+                ("bad000".into(), jan),
+                ("bad000".into(), feb),
+            ])
+        );
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.stored, 3);
+        let mut errors = result
+            .errors
+            .iter()
+            .map(|(pk, _)| pk.clone())
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(errors, vec![("bad000".into(), jan), ("bad000".into(), feb)]);
+
+        let events = events.into_inner().unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events.last().map(|p| (p.completed, p.total)), Some((5, 5)));
+
+        // everything that succeeded is now stored
+        assert_eq!(
+            super::list_months_positions(&client).await.unwrap(),
+            HashSet::from([
+                ("aa0001".into(), jan),
+                ("aa0001".into(), feb),
+                ("bb0002".into(), jan),
+                ("bb0002".into(), feb),
+            ])
+        );
+        // the existing month is left untouched
+        assert_eq!(
+            get_month_positions("aa0001", jan, &client).await.unwrap(),
+            vec![]
+        );
+
+        // a second backfill only retries the failures
+        let fetcher = Months::default();
+        let result = backfill(
+            &["aa0001", "bb0002", "bad000"],
+            &[jan, feb],
+            &client,
+            &fetcher,
+            2,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (result.skipped, result.stored, result.errors.len()),
+            (4, 0, 2)
+        );
+        assert!(fetcher
+            .0
+            .into_inner()
+            .unwrap()
+            .iter()
+            .all(|(icao, _)| icao.as_ref() == "bad000"));
+    }
+
+    #[tokio::test]
+    async fn backfill_required_pairs() {
+        let (jan, feb) = (date!(2019 - 01 - 01), date!(2019 - 02 - 01));
+        let client = fs::InMemory::default();
+        let required = HashSet::from([("aa0001".into(), jan), ("bb0002".into(), feb)]);
+        // e.g. from a catalog, without listing the db
+        let completed = HashSet::from([("aa0001".into(), jan)]);
+
+        let fetcher = Months::default();
+        let result = backfill_required(&required, &completed, &client, &fetcher, 2, &ignore).await;
+
+        assert_eq!(
+            fetcher.0.into_inner().unwrap(),
+            HashSet::from([("bb0002".into(), feb)])
+        );
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.stored, 1);
+        assert!(result.errors.is_empty());
+        assert!(super::get_month_positions("bb0002", feb, &client)
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn daily_blobs() {
+        let monthly = "position/icao_number=aa/month=2024-01/data.json";
+        let daily = "position/icao_number=aa/month=2024-01-05/data.json";
+        assert!(is_daily_blob(daily));
+        assert!(!is_daily_blob(monthly));
+        assert!(!is_daily_blob(
+            "position/icao_number=aa/month=2024-13-05/data.json"
+        ));
+        assert!(!is_daily_blob(
+            "leg/icao_number=aa/month=2024-01-05/data.json"
+        ));
+
+        // only monthly blobs are read; daily blobs and unknown names are skipped
+        assert_eq!(
+            blob_names_to_pks([monthly, daily, "garbage"]),
+            vec![("aa".into(), date!(2024 - 01 - 01))]
+        );
     }
 
     #[test]
@@ -179,4 +1263,279 @@ mod test {
         let a = super::list_months_positions(&LocalDisk).await.unwrap();
         assert!(a.is_empty())
     }
+
+    #[tokio::test]
+    async fn current_month_is_refetched() {
+        let stale = b"[]".to_vec();
+        let client = fs::InMemory::default();
+
+        // a past month is served from the cache
+        let past = date!(2019 - 01 - 01);
+        fs::BlobStorageProvider::put(&client, &pk_to_blob_name("45860d", past), stale.clone())
+            .await
+            .unwrap();
+        let positions = month_positions("45860d", past, &client, &Offline, 1)
+            .await
+            .unwrap();
+        assert!(positions.is_empty());
+
+        // the current month is refetched and not written
+        let today = time::OffsetDateTime::now_utc().date();
+        let current = today.replace_day(1).unwrap();
+        let blob_name = pk_to_blob_name("45860d", current);
+        fs::BlobStorageProvider::put(&client, &blob_name, stale.clone())
+            .await
+            .unwrap();
+        let positions = month_positions("45860d", current, &client, &Daily, 1)
+            .await
+            .unwrap();
+        assert!(!positions.is_empty());
+        assert_eq!(
+            fs::BlobStorageProvider::maybe_get(&client, &blob_name)
+                .await
+                .unwrap(),
+            Some(stale)
+        );
+    }
+
+    #[tokio::test]
+    async fn estimate() {
+        let (jan, feb) = (date!(2024 - 01 - 01), date!(2024 - 02 - 01));
+        let client = Listing {
+            blobs: vec![
+                pk_to_blob_name("a", jan),
+                pk_to_blob_name("a", feb),
+                pk_to_blob_name("b", jan),
+                "position/unrelated.json".to_string(),
+            ],
+            listed: Default::default(),
+        };
+        let icao_numbers = HashSet::from(["a".into(), "b".into(), "c".into()]);
+
+        let estimate = estimate_fetches(
+            &icao_numbers,
+            date!(2024 - 01 - 15),
+            date!(2024 - 02 - 10),
+            &client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            estimate,
+            FetchEstimate {
+                cached: 3,
+                to_fetch: 3
+            }
+        );
+        assert_eq!(*client.listed.lock().unwrap(), vec![DATABASE.to_string()]);
+
+        // the current month is always fetched
+        let current = time::OffsetDateTime::now_utc()
+            .date()
+            .replace_day(1)
+            .unwrap();
+        let required = HashSet::from([("a".into(), current)]);
+        assert_eq!(
+            FetchEstimate::new(&required, &required),
+            FetchEstimate {
+                cached: 0,
+                to_fetch: 1
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn catalog_many() {
+        let catalog =
+            std::env::temp_dir().join(format!("catalog-many-{}.json", std::process::id()));
+        std::fs::write(
+            &catalog,
+            br#"{"built_at":"2024-01-01T00:00:00Z","blobs":[],"absent":[]}"#,
+        )
+        .unwrap();
+        let months = months(date!(2019 - 01 - 01), date!(2024 - 01 - 01));
+        let required = (0..200)
+            .flat_map(|i| {
+                let icao_number: Arc<str> = format!("{i:06x}").into();
+                months
+                    .iter()
+                    .map(move |month| (icao_number.clone(), *month))
+            })
+            .collect::<HashSet<_>>();
+        // every other (icao, month) exists
+        let client = Listing {
+            blobs: required
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| i % 2 == 0)
+                .map(|(_, (icao_number, month))| pk_to_blob_name(icao_number, *month))
+                .collect(),
+            listed: Default::default(),
+        };
+
+        let completed = list_months_positions_cached(&required, &catalog, false, &client)
+            .await
+            .unwrap();
+        assert_eq!(completed.len(), required.len() / 2);
+        assert_eq!(
+            completed,
+            super::list_months_positions(&client).await.unwrap()
+        );
+        std::fs::remove_file(&catalog).unwrap();
+    }
+
+    /// [`HttpFetcher`] of [`Daily`] that records the urls requested
+    #[derive(Default)]
+    struct Recording(Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for Recording {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            self.0.lock().unwrap().push(url.to_string());
+            Daily.get_bytes(url).await
+        }
+    }
+
+    /// Returns the days of the urls requested by `fetcher`
+    fn fetched_days(fetcher: &Recording) -> Vec<u8> {
+        let mut days = fetcher
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|url| {
+                let (_, path) = url.split_once("globe_history/").unwrap();
+                path[8..10].parse().unwrap()
+            })
+            .collect::<Vec<_>>();
+        days.sort_unstable();
+        days
+    }
+
+    #[tokio::test]
+    async fn current_month_fetches_new_days() {
+        let month = date!(2024 - 01 - 01);
+        let client = fs::InMemory::default();
+        let partial = pk_to_partial_blob_name("45860d", month);
+
+        // the first fetch fetches every day until today, and stores the complete days
+        let fetcher = Recording::default();
+        let positions =
+            month_positions_at("45860d", month, date!(2024 - 01 - 03), &client, &fetcher, 2)
+                .await
+                .unwrap();
+        assert_eq!(positions.len(), 3);
+        assert_eq!(fetched_days(&fetcher), vec![1, 2, 3]);
+        let stored: Partial = serde_json::from_slice(
+            &fs::BlobStorageProvider::maybe_get(&client, &partial)
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(stored.days, 2);
+        assert_eq!(stored.positions, positions[..2]);
+
+        // the positions of the complete days are read from the partial month, not from their traces
+        for day in 1..=2 {
+            let date = month.replace_day(day).unwrap();
+            let trace = format!(
+                "{}/{date}/trace_full_45860d.json",
+                crate::icao_to_trace::DATABASE
+            );
+            fs::BlobStorageProvider::delete(&client, &trace)
+                .await
+                .unwrap();
+        }
+
+        // a day later, only the previous and the new day are fetched and appended
+        let fetcher = Recording::default();
+        let again =
+            month_positions_at("45860d", month, date!(2024 - 01 - 04), &client, &fetcher, 2)
+                .await
+                .unwrap();
+        assert_eq!(again.len(), 4);
+        assert_eq!(again[..3], positions[..]);
+        assert_eq!(fetched_days(&fetcher), vec![3, 4]);
+        let stored: Partial = serde_json::from_slice(
+            &fs::BlobStorageProvider::maybe_get(&client, &partial)
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(stored.days, 3);
+
+        // the complete month is stored once it ends, and the partial month is removed
+        let fetcher = Recording::default();
+        let complete =
+            month_positions_at("45860d", month, date!(2024 - 02 - 02), &client, &fetcher, 2)
+                .await
+                .unwrap();
+        assert_eq!(complete.len(), 31);
+        assert_eq!(fetched_days(&fetcher), (4..=31).collect::<Vec<_>>());
+        assert!(fs::BlobStorageProvider::maybe_get(&client, &partial)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            get_month_positions("45860d", month, &client).await.unwrap(),
+            complete
+        );
+        assert_eq!(
+            super::list_months_positions(&client).await.unwrap(),
+            HashSet::from([("45860d".into(), month)])
+        );
+    }
+
+    /// [`HttpFetcher`] of [`Daily`] for an aircraft idle between the 4th and 9th of the month,
+    /// whose traces of those days are missing (404) or have no positions
+    struct Idle;
+
+    #[async_trait::async_trait]
+    impl HttpFetcher for Idle {
+        async fn get_bytes(&self, url: &str) -> Result<Vec<u8>, std::io::Error> {
+            let (_, path) = url.split_once("globe_history/").unwrap();
+            match &path[8..10] {
+                "04" | "05" => Err(std::io::Error::new(std::io::ErrorKind::NotFound, url)),
+                "06" => Ok(vec![]),
+                "07" => Ok(b"{}".to_vec()),
+                "08" | "09" => Ok(br#"{"timestamp": 1546300800.0, "trace": []}"#.to_vec()),
+                _ => Daily.get_bytes(url).await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_days() {
+        let client = fs::InMemory::default();
+        let positions = aircraft_positions(
+            date!(2019 - 01 - 01),
+            date!(2019 - 01 - 15),
+            "45860d",
+            &client,
+            &Idle,
+            DEFAULT_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        let days = positions
+            .iter()
+            .map(|p| p.datetime().day())
+            .collect::<Vec<_>>();
+        assert_eq!(days, vec![1, 2, 3, 10, 11, 12, 13, 14]);
+
+        // a period without any position is empty
+        let positions = aircraft_positions(
+            date!(2019 - 01 - 04),
+            date!(2019 - 01 - 10),
+            "45860d",
+            &client,
+            &Idle,
+            DEFAULT_CONCURRENCY,
+        )
+        .await
+        .unwrap();
+        assert!(positions.is_empty());
+    }
 }
@@ -1,6 +1,6 @@
 use std::error::Error;
 
-use flights::{fs::BlobStorageProvider, fs::LocalDisk, legs::Leg};
+use flights::{fs::BlobStorageProvider, fs::LocalDisk, icao_to_trace::AdsbExchange, legs::Leg};
 use time::{
     macros::{date, datetime},
     Date,
@@ -11,8 +11,13 @@ use time::{
 /// https://globe.adsbexchange.com/?icao=45d2ed&lat=54.128&lon=9.185&zoom=5.0&showTrace=2023-10-13
 #[tokio::test]
 async fn acceptance_legs() -> Result<(), Box<dyn Error>> {
-    let positions =
-        flights::icao_to_trace::positions("45d2ed", date!(2023 - 10 - 13), &LocalDisk).await?;
+    let positions = flights::icao_to_trace::positions(
+        "45d2ed",
+        date!(2023 - 10 - 13),
+        &LocalDisk,
+        &AdsbExchange,
+    )
+    .await?;
     let legs = flights::legs::legs(positions).collect::<Vec<_>>();
 
     assert_eq!(legs.len(), 2);
@@ -30,8 +35,13 @@ async fn acceptance_legs() -> Result<(), Box<dyn Error>> {
 
 #[tokio::test]
 async fn legs_() -> Result<(), Box<dyn Error>> {
-    let positions =
-        flights::icao_to_trace::positions("459cd3", date!(2023 - 11 - 17), &LocalDisk).await?;
+    let positions = flights::icao_to_trace::positions(
+        "459cd3",
+        date!(2023 - 11 - 17),
+        &LocalDisk,
+        &AdsbExchange,
+    )
+    .await?;
     let legs = flights::legs::legs(positions);
 
     // same as ads-b computes: https://globe.adsbexchange.com/?icao=459cd3&lat=53.265&lon=8.038&zoom=6.5&showTrace=2023-11-17
@@ -45,8 +55,15 @@ async fn legs(
     icao_number: &str,
     client: &dyn BlobStorageProvider,
 ) -> Result<Vec<Leg>, Box<dyn Error>> {
-    let positions =
-        flights::icao_to_trace::aircraft_positions(from, to, icao_number, client).await?;
+    let positions = flights::icao_to_trace::aircraft_positions(
+        from,
+        to,
+        icao_number,
+        client,
+        &AdsbExchange,
+        flights::icao_to_trace::DEFAULT_CONCURRENCY,
+    )
+    .await?;
     Ok(flights::legs::legs(positions.into_iter()).collect::<Vec<_>>())
 }
 
@@ -122,7 +139,9 @@ async fn case_45c824_2023_12_12() -> Result<(), Box<dyn Error>> {
 async fn gets_db_positions() -> Result<(), Box<dyn Error>> {
     let client = flights::fs_s3::anonymous_client().await;
 
-    let _ = flights::icao_to_trace::positions("459cd3", date!(2020 - 01 - 01), &client).await?;
+    let _ =
+        flights::icao_to_trace::positions("459cd3", date!(2020 - 01 - 01), &client, &AdsbExchange)
+            .await?;
     Ok(())
 }
 
@@ -139,9 +158,46 @@ async fn gets_db_month() -> Result<(), Box<dyn Error>> {
 async fn private_jets_in_month() -> Result<(), Box<dyn Error>> {
     let client = flights::fs_s3::anonymous_client().await;
 
-    let aircraft = flights::private_jets_in_month(2022..2024, None, &client).await?;
+    let aircraft = flights::private_jets_in_month(
+        2022..2024,
+        &flights::PrivateJetFilter {
+            include_state: true,
+            ..Default::default()
+        },
+        &flights::model::load_private_jet_models()?,
+        &client,
+    )
+    .await?;
 
     // this number should be constant, as the db of aircrafts does not change in the past
     assert_eq!(aircraft.len(), 29425 * 24);
     Ok(())
 }
+
+/// Verifies that blobs round-trip through an S3-compatible bucket other than the default one,
+/// e.g. localstack (`S3_ENDPOINT=http://localhost:4566` with an existing bucket `S3_BUCKET`).
+/// Skipped when `S3_ENDPOINT` is not set.
+#[tokio::test]
+async fn s3_roundtrip() -> Result<(), Box<dyn Error>> {
+    let Ok(endpoint_url) = std::env::var("S3_ENDPOINT") else {
+        return Ok(());
+    };
+    let bucket = flights::fs_s3::Bucket {
+        endpoint_url: Some(endpoint_url),
+        region: "us-east-1".to_string(),
+        name: std::env::var("S3_BUCKET").unwrap_or_else(|_| "private-jets".to_string()),
+        force_path_style: true,
+    };
+    let client = flights::fs_s3::client_in(&bucket, "test".to_string(), "test".to_string()).await;
+
+    let blob_name = "test/s3_roundtrip/data.json";
+    client.put(blob_name, b"[]".to_vec()).await?;
+    assert_eq!(client.maybe_get(blob_name).await?, Some(b"[]".to_vec()));
+    assert_eq!(
+        client.list("test/s3_roundtrip/").await?,
+        vec![blob_name.to_string()]
+    );
+    client.delete(blob_name).await?;
+    assert_eq!(client.maybe_get(blob_name).await?, None);
+    Ok(())
+}